@@ -0,0 +1,255 @@
+// Extraction of the --archive inputs, so the binaries inside can be resolved with the
+// archive root as the --sysroot.
+
+use crate::error::RlddError;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ZIP_EMPTY_MAGIC: &[u8] = b"PK\x05\x06";
+// The ustar magic at offset 257 of the first header, with either the POSIX or the GNU
+// version.
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+// Extract the tar (optionally gzip compressed) or zip archive FILENAME into DEST, keeping
+// the symbolic links and file modes.
+pub fn extract(filename: &str, dest: &Path) -> Result<(), RlddError> {
+    let mut file = File::open(filename)?;
+    let mut header = [0u8; TAR_MAGIC_OFFSET + TAR_MAGIC.len()];
+    let len = read_header(&mut file, &mut header)?;
+    let header = &header[..len];
+    file.seek(SeekFrom::Start(0))?;
+
+    if header.starts_with(ZIP_MAGIC) || header.starts_with(ZIP_EMPTY_MAGIC) {
+        extract_zip(file, dest)
+    } else if header.starts_with(GZIP_MAGIC) {
+        extract_tar(flate2::read::GzDecoder::new(file), dest)
+    } else if header.ends_with(TAR_MAGIC) {
+        extract_tar(file, dest)
+    } else {
+        Err(RlddError::Parse("not a tar or zip archive".to_string()))
+    }
+}
+
+// Fill as much of HEADER as the file allows, since an archive might be smaller than the
+// tar header.
+fn read_header(file: &mut File, header: &mut [u8]) -> Result<usize, RlddError> {
+    let mut len = 0;
+    while len < header.len() {
+        match file.read(&mut header[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+fn extract_tar<R: Read>(reader: R, dest: &Path) -> Result<(), RlddError> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    // The entries with '..' are skipped, and the absolute ones are extracted relative to
+    // DEST.
+    archive.unpack(dest).map_err(RlddError::Io)
+}
+
+fn extract_zip(file: File, dest: &Path) -> Result<(), RlddError> {
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|_| RlddError::Parse("invalid zip archive".to_string()))?;
+    std::fs::create_dir_all(dest)?;
+    let root = dest.canonicalize()?;
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|_| RlddError::Parse("invalid zip archive".to_string()))?;
+        // Skip the entries outside DEST, as tar does.
+        let name = match entry.enclosed_name() {
+            Some(name) => name.to_path_buf(),
+            None => continue,
+        };
+        let path = root.join(&name);
+        // An earlier symbolic link entry might redirect the path out of DEST.
+        check_enclosed(&path, &root)?;
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // The zip symbolic links (as created by 'zip -y') have the target as contents.
+        let mode = entry.unix_mode();
+        if mode.is_some_and(|m| m & S_IFMT == S_IFLNK) {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            if !link_enclosed(&name, Path::new(&target)) {
+                return Err(RlddError::Parse(format!(
+                    "symbolic link {} points outside the archive",
+                    name.display()
+                )));
+            }
+            std::os::unix::fs::symlink(target, &path)?;
+            continue;
+        }
+        // The entry itself might be an earlier symbolic link.
+        let mut out = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&path)?;
+        std::io::copy(&mut entry, &mut out)?;
+        if let Some(mode) = mode {
+            out.set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+    }
+    Ok(())
+}
+
+// Fail if the deepest existing ancestor of PATH does not resolve to a directory under
+// ROOT.  The components created afterwards are plain names, so they stay under it.
+fn check_enclosed(path: &Path, root: &Path) -> Result<(), RlddError> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.symlink_metadata().is_ok())
+        .unwrap_or(root);
+    if existing.canonicalize()?.starts_with(root) {
+        Ok(())
+    } else {
+        Err(RlddError::Parse(format!(
+            "{} is outside the archive",
+            path.display()
+        )))
+    }
+}
+
+// Whether the symbolic link NAME (relative to the archive root) to TARGET stays in the
+// archive, without following the other links.
+fn link_enclosed(name: &Path, target: &Path) -> bool {
+    let mut depth = name.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn check_extracted(dest: &Path) -> Result<(), std::io::Error> {
+        assert_eq!(std::fs::read(dest.join("usr/lib/libfoo.so.1"))?, b"foo");
+        assert_eq!(
+            std::fs::read_link(dest.join("usr/lib/libfoo.so"))?,
+            Path::new("libfoo.so.1")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extract_tar_gz() -> Result<(), std::io::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filename = tmp_dir.path().join("root.tar.gz");
+
+        let gz = flate2::write::GzEncoder::new(File::create(&filename)?, Default::default());
+        let mut builder = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, "usr/lib/libfoo.so.1", &b"foo"[..])?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder.append_link(&mut header, "usr/lib/libfoo.so", "libfoo.so.1")?;
+        builder.into_inner()?.finish()?;
+
+        let dest = tmp_dir.path().join("root");
+        extract(&filename.to_string_lossy(), &dest)?;
+        check_extracted(&dest)
+    }
+
+    #[test]
+    fn extract_zip() -> Result<(), std::io::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filename = tmp_dir.path().join("root.zip");
+
+        let mut writer = zip::ZipWriter::new(File::create(&filename)?);
+        let options = zip::write::FileOptions::default().unix_permissions(0o755);
+        writer.start_file("usr/lib/libfoo.so.1", options)?;
+        writer.write_all(b"foo")?;
+        writer.add_symlink("usr/lib/libfoo.so", "libfoo.so.1", options)?;
+        writer.finish()?;
+
+        let dest = tmp_dir.path().join("root");
+        extract(&filename.to_string_lossy(), &dest)?;
+        check_extracted(&dest)
+    }
+
+    #[test]
+    fn extract_zip_outside_link() -> Result<(), std::io::Error> {
+        let tmp_dir = TempDir::new()?;
+        let outside = tmp_dir.path().join("outside");
+        std::fs::create_dir(&outside)?;
+
+        for target in [
+            outside.to_string_lossy().to_string(),
+            "../outside".to_string(),
+        ] {
+            let filename = tmp_dir.path().join("evil.zip");
+            let mut writer = zip::ZipWriter::new(File::create(&filename)?);
+            let options = zip::write::FileOptions::default().unix_permissions(0o755);
+            writer.add_symlink("link", &target, options)?;
+            writer.start_file("link/pwned", options)?;
+            writer.write_all(b"foo")?;
+            writer.finish()?;
+
+            let dest = tmp_dir.path().join("root");
+            assert!(matches!(
+                extract(&filename.to_string_lossy(), &dest),
+                Err(RlddError::Parse(_))
+            ));
+            assert!(!outside.join("pwned").exists());
+            std::fs::remove_dir_all(&dest)?;
+        }
+
+        // A link already in the destination is not followed either.
+        let filename = tmp_dir.path().join("root.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&filename)?);
+        writer.start_file("link/pwned", Default::default())?;
+        writer.write_all(b"foo")?;
+        writer.finish()?;
+        let dest = tmp_dir.path().join("root");
+        std::fs::create_dir(&dest)?;
+        std::os::unix::fs::symlink(&outside, dest.join("link"))?;
+        assert!(extract(&filename.to_string_lossy(), &dest).is_err());
+        assert!(!outside.join("pwned").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn extract_not_archive() -> Result<(), std::io::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filename = tmp_dir.path().join("root.tar");
+        std::fs::write(&filename, b"foo")?;
+
+        assert!(matches!(
+            extract(&filename.to_string_lossy(), tmp_dir.path()),
+            Err(RlddError::Parse(_))
+        ));
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
 mod arenatree;
 use crate::pathutils;
@@ -11,6 +12,12 @@ pub struct DepNode {
     pub name: String,
     pub mode: DepMode,
     pub found: bool,
+    pub trace: SearchTrace,
+    pub warnings: Vec<String>,
+    // Additional object information, printed after the resolution mode.
+    pub info: Vec<String>,
+    // Device and inode of the object, only set if hardlinks are merged.
+    pub file_id: Option<(u64, u64)>,
 }
 
 impl arenatree::EqualString for DepNode {
@@ -34,10 +41,23 @@ impl arenatree::EqualString for DepNode {
 // The resolved binary dependency tree.
 pub type DepTree = arenatree::ArenaTree<DepNode>;
 
+// A candidate location checked while resolving a dependency, in search order.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SearchAttempt {
+    pub path: String,
+    pub mode: DepMode,
+    pub found: bool,
+    // Why an existing candidate was not used, if known.
+    pub rejected: Option<String>,
+}
+
+pub type SearchTrace = Vec<SearchAttempt>;
+
 // The resolution mode for a dependency, used mostly for printing.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[allow(dead_code)]
 pub enum DepMode {
+    Mapped,        // Forced location (--map).
     Preload,       // Preload library.
     Direct,        // DT_SONAME refers to an aboslute path.
     DtRpath,       // DT_RPATH.
@@ -45,13 +65,64 @@ pub enum DepMode {
     DtRunpath,     // DT_RUNPATH.
     LdCache,       // Loader cache (ld.so.cache, etc.).
     SystemDirs,    // Default system directory (i.e '/lib64').
+    ExtraDirs,     // Additional directories searched last (--extra-dir).
     Executable,    // The root executable/library.
     NotFound,
 }
 
+impl DepMode {
+    // Short explanation of the mode label, used by --legend.
+    pub fn description(&self) -> &'static str {
+        match self {
+            DepMode::Mapped => "location forced with --map",
+            DepMode::Preload => "preloaded library (--preload or the loader preload file)",
+            DepMode::Direct => "dependency with an absolute path",
+            DepMode::DtRpath => "found in a DT_RPATH directory of the requiring object",
+            #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+            DepMode::LdLibraryPath => "found in a --library-path (LD_LIBRARY_PATH) directory",
+            #[cfg(target_os = "macos")]
+            DepMode::LdLibraryPath => "found in a --library-path (DYLD_LIBRARY_PATH) directory",
+            DepMode::DtRunpath => "found in a DT_RUNPATH directory of the requiring object",
+            #[cfg(target_os = "macos")]
+            DepMode::LdCache => "provided by the dyld shared cache",
+            #[cfg(not(target_os = "macos"))]
+            DepMode::LdCache => "found through the loader cache or configuration file",
+            DepMode::SystemDirs => "found in a default system directory of the loader",
+            DepMode::ExtraDirs => "found in an --extra-dir directory, not searched by the loader",
+            DepMode::Executable => "the input object",
+            DepMode::NotFound => "not found in any search location",
+        }
+    }
+}
+
+// Parse the mode name as shown in the tree, with or without the brackets.
+impl FromStr for DepMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim_start_matches('[').trim_end_matches(']');
+        [
+            DepMode::Mapped,
+            DepMode::Preload,
+            DepMode::Direct,
+            DepMode::DtRpath,
+            DepMode::LdLibraryPath,
+            DepMode::DtRunpath,
+            DepMode::LdCache,
+            DepMode::SystemDirs,
+            DepMode::ExtraDirs,
+            DepMode::NotFound,
+        ]
+        .into_iter()
+        .find(|m| m.to_string().eq_ignore_ascii_case(&format!("[{name}]")))
+        .ok_or(format!("invalid resolution mode '{s}'"))
+    }
+}
+
 impl fmt::Display for DepMode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            DepMode::Mapped => write!(f, "[mapped]"),
             DepMode::Preload => write!(f, "[preload]"),
             DepMode::Direct => write!(f, "[direct]"),
             DepMode::DtRpath => write!(f, "[rpath]"),
@@ -75,6 +146,7 @@ impl fmt::Display for DepMode {
             #[cfg(target_os = "macos")]
             DepMode::LdCache => write!(f, "[dyld cache]"),
             DepMode::SystemDirs => write!(f, "[system default paths]"),
+            DepMode::ExtraDirs => write!(f, "[extra dirs]"),
             DepMode::Executable => write!(f, ""),
             DepMode::NotFound => write!(f, "[not found]"),
         }
@@ -1,4 +1,5 @@
-use std::io::{Error, ErrorKind};
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::{fmt, fs, str};
 
@@ -8,10 +9,16 @@ use object::read::StringTable;
 use object::Endianness;
 
 use crate::deptree::*;
+use crate::error::RlddError;
+mod arch;
 mod platform;
+#[cfg(target_os = "linux")]
+use crate::options::Libc;
+use crate::options::{Limits, Mapping, NodeHook, ResolveOptions, Simulate, Sysroot};
 use crate::pathutils;
 use crate::search_path;
 
+mod symbols;
 mod system_dirs;
 
 #[cfg(target_os = "android")]
@@ -25,6 +32,8 @@ mod ld_hints_freebsd;
 #[cfg(target_os = "openbsd")]
 mod ld_hints_openbsd;
 #[cfg(target_os = "linux")]
+mod ld_musl_path;
+#[cfg(target_os = "linux")]
 mod ld_preload;
 #[cfg(target_os = "linux")]
 mod ld_so_cache;
@@ -41,14 +50,32 @@ type LoaderCache = ld_config_txt::LdCache;
 ))]
 type LoaderCache = search_path::SearchPathVec;
 
-type DepsVec = Vec<String>;
+// A DT_NEEDED entry along with its position in the DT_NEEDED list, which sets the order
+// the loader loads and initializes the dependencies.
+#[derive(Debug)]
+struct Needed {
+    name: String,
+    index: usize,
+}
+
+type DepsVec = Vec<Needed>;
 
 // A parsed ELF object with the relevant informations:
 // - ei_class/ei_data/ei_osabi: ElfXX_Ehdr fields used in system library paths resolution,
+// - e_type: ElfXX_Ehdr field used to tell executables from shared libraries.
 // - soname: DT_SONAME, if present.
 // - rpath: DT_RPATH search list paths, if present.
 // - runpatch: DT_RUNPATH search list paths, if present.
 // - nodeflibs: set if DF_1_NODEFLIB from DT_FLAGS_1 is set.
+// - is_glibc/is_musl: set if the program interpreter is the glibc or musl loader.
+// - feature_1: GNU_PROPERTY_X86_FEATURE_1_AND or GNU_PROPERTY_AARCH64_FEATURE_1_AND bits.
+// - relr: set if DT_RELR is present.
+// - bind_now: set if DT_BIND_NOW, DF_BIND_NOW from DT_FLAGS, or DF_1_NOW from DT_FLAGS_1
+//   is set, so the loader binds all the symbols at load time instead of lazily.
+// - textrel: set if DT_TEXTREL is present or DF_TEXTREL from DT_FLAGS is set.
+// - execstack: set if PT_GNU_STACK has PF_X set.
+// - flags_1: DT_FLAGS_1 bits.
+// - strsz: DT_STRSZ, the dynamic string table size.
 #[derive(Debug)]
 struct ElfInfo {
     ei_class: u8,
@@ -56,6 +83,7 @@ struct ElfInfo {
     ei_osabi: u8,
     #[allow(dead_code)]
     ei_abiver: u8,
+    e_type: u16,
     e_machine: u16,
     #[allow(dead_code)]
     e_flags: u32,
@@ -65,66 +93,74 @@ struct ElfInfo {
     rpath: search_path::SearchPathVec,
     runpath: search_path::SearchPathVec,
     nodeflibs: bool,
+    is_glibc: bool,
     is_musl: bool,
+    feature_1: u32,
+    relr: bool,
+    bind_now: bool,
+    textrel: bool,
+    execstack: bool,
+    flags_1: u64,
+    strsz: u64,
 
     deps: DepsVec,
 }
 
+// Values used on the DT_RPATH/DT_RUNPATH expansion and when opening the candidates: the
+// $PLATFORM override, the --sysroot the absolute entries are relative to, and whether a
+// DT_SONAME with additional version components is accepted (--relaxed-soname).
+struct Expand<'a> {
+    platform: Option<&'a String>,
+    sysroot: Option<&'a Sysroot>,
+    relaxed_soname: bool,
+}
+
 // ELF Parsing routines.
 
-fn parse_object(
-    data: &[u8],
-    origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+fn parse_object(data: &[u8], origin: &str, expand: &Expand) -> Result<ElfInfo, RlddError> {
     let kind = match object::FileKind::parse(data) {
         Ok(file) => file,
-        Err(_err) => return Err("Failed to parse file"),
+        Err(_err) => return Err(RlddError::Parse("Failed to parse file".to_string())),
     };
 
     match kind {
-        object::FileKind::Elf32 => parse_elf32(data, origin, platform),
-        object::FileKind::Elf64 => parse_elf64(data, origin, platform),
-        _ => Err("Invalid object"),
+        object::FileKind::Elf32 => parse_elf32(data, origin, expand),
+        object::FileKind::Elf64 => parse_elf64(data, origin, expand),
+        _ => Err(RlddError::Parse("Invalid object".to_string())),
     }
 }
 
-fn parse_elf32(
-    data: &[u8],
-    origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+fn parse_elf32(data: &[u8], origin: &str, expand: &Expand) -> Result<ElfInfo, RlddError> {
     if let Some(elf) = FileHeader32::<Endianness>::parse(data).handle_err() {
-        return parse_elf(elf, data, origin, platform);
+        return parse_elf(elf, data, origin, expand);
     }
-    Err("Invalid ELF32 object")
+    Err(RlddError::Parse("Invalid ELF32 object".to_string()))
 }
 
-fn parse_elf64(
-    data: &[u8],
-    origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+fn parse_elf64(data: &[u8], origin: &str, expand: &Expand) -> Result<ElfInfo, RlddError> {
     if let Some(elf) = FileHeader64::<Endianness>::parse(data).handle_err() {
-        return parse_elf(elf, data, origin, platform);
+        return parse_elf(elf, data, origin, expand);
     }
-    Err("Invalid ELF64 object")
+    Err(RlddError::Parse("Invalid ELF64 object".to_string()))
 }
 
 fn parse_elf<Elf: FileHeader<Endian = Endianness>>(
     elf: &Elf,
     data: &[u8],
     origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
     let endian = match elf.endian() {
         Ok(val) => val,
-        Err(_) => return Err("invalid endianess"),
+        Err(_) => return Err(RlddError::Parse("invalid endianess".to_string())),
     };
 
     match elf.e_type(endian) {
-        ET_EXEC | ET_DYN => parse_header_elf(endian, elf, data, origin, platform),
-        _ => Err("Invalid ELF file"),
+        ET_EXEC | ET_DYN => parse_header_elf(endian, elf, data, origin, expand),
+        // Core dumps are valid ELF files without dynamic section, so they are reported as
+        // such instead of as an invalid file.
+        ET_CORE => Err(RlddError::CoreFile),
+        _ => Err(RlddError::Parse("Invalid ELF file".to_string())),
     }
 }
 
@@ -146,16 +182,17 @@ fn parse_header_elf<Elf: FileHeader<Endian = Endianness>>(
     elf: &Elf,
     data: &[u8],
     origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
     match elf.program_headers(endian, data) {
-        Ok(segments) => parse_elf_program_headers(endian, data, elf, segments, origin, platform),
-        Err(_) => Err("invalid segment"),
+        Ok(segments) => parse_elf_program_headers(endian, data, elf, segments, origin, expand),
+        Err(_) => Err(RlddError::Parse("invalid segment".to_string())),
     }
 }
 
 #[cfg(target_os = "linux")]
 fn handle_loader(elc: &mut ElfInfo) {
+    elc.is_glibc = interp::is_glibc(&elc.interp, &[]);
     elc.is_musl = interp::is_musl(&elc.interp)
 }
 #[cfg(all(target_family = "unix", not(target_os = "linux")))]
@@ -167,11 +204,15 @@ fn parse_elf_program_headers<Elf: FileHeader>(
     elf: &Elf,
     headers: &[Elf::ProgramHeader],
     origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
-    match parse_elf_dynamic_program_header(endian, data, elf, headers, origin, platform) {
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
+    match parse_elf_dynamic_program_header(endian, data, elf, headers, origin, expand) {
         Ok(mut elc) => {
             elc.interp = parse_elf_interp::<Elf>(endian, data, headers);
+            elc.feature_1 = parse_elf_gnu_property(endian, data, elf, headers).unwrap_or(0);
+            elc.execstack = headers
+                .iter()
+                .any(|hdr| hdr.p_type(endian) == PT_GNU_STACK && hdr.p_flags(endian) & PF_X != 0);
             handle_loader(&mut elc);
             Ok(elc)
         }
@@ -196,20 +237,183 @@ fn parse_elf_interp<Elf: FileHeader>(
     }
 }
 
+// Dynamic tags not provided by the object crate.
+const DT_RELR: u32 = 36;
+
+// GNU property definitions not provided by the object crate.
+const PT_GNU_PROPERTY: u32 = 0x6474_e553;
+const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc000_0000;
+const GNU_PROPERTY_AARCH64_FEATURE_1_BTI: u32 = 1 << 0;
+const GNU_PROPERTY_AARCH64_FEATURE_1_PAC: u32 = 1 << 1;
+const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+const GNU_PROPERTY_X86_FEATURE_1_IBT: u32 = 1 << 0;
+const GNU_PROPERTY_X86_FEATURE_1_SHSTK: u32 = 1 << 1;
+
+fn read_u32<E: object::Endian>(endian: E, data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(endian.read_u32_bytes(bytes.try_into().ok()?))
+}
+
+// The note sizes are read from the file, so the arithmetic is checked for overflows.
+fn align_up(value: usize, align: usize) -> Option<usize> {
+    Some(value.checked_add(align - 1)? & !(align - 1))
+}
+
+// The PT_GNU_PROPERTY segment contains a single NT_GNU_PROPERTY_TYPE_0 note whose
+// descriptor is an array of properties aligned to 8 bytes for ELFCLASS64 and 4 for
+// ELFCLASS32.
+fn parse_elf_gnu_property<Elf: FileHeader>(
+    endian: Elf::Endian,
+    data: &[u8],
+    elf: &Elf,
+    headers: &[Elf::ProgramHeader],
+) -> Option<u32> {
+    let feature_1_and = match elf.e_machine(endian) {
+        EM_386 | EM_X86_64 => GNU_PROPERTY_X86_FEATURE_1_AND,
+        EM_AARCH64 => GNU_PROPERTY_AARCH64_FEATURE_1_AND,
+        _ => return None,
+    };
+
+    let hdr = headers
+        .iter()
+        .find(|&hdr| hdr.p_type(endian) == PT_GNU_PROPERTY)?;
+    let note = hdr.data(endian, data).ok()?;
+
+    let align = if elf.is_class_64() { 8 } else { 4 };
+    let namesz = read_u32(endian, note, 0)? as usize;
+    let descsz = read_u32(endian, note, 4)? as usize;
+    if read_u32(endian, note, 8)? != NT_GNU_PROPERTY_TYPE_0 {
+        return None;
+    }
+    let descoff = align_up(namesz.checked_add(12)?, align)?;
+    let desc = note.get(descoff..descoff.checked_add(descsz)?)?;
+    find_gnu_property(endian, desc, align, feature_1_and)
+}
+
+// Return the u32 bitmask of the PR_TYPE property from the NT_GNU_PROPERTY_TYPE_0 descriptor
+// DESC.  Properties too short to hold the bitmask are ignored.
+fn find_gnu_property<E: object::Endian>(
+    endian: E,
+    desc: &[u8],
+    align: usize,
+    pr_type: u32,
+) -> Option<u32> {
+    let mut offset = 0;
+    while desc.len().saturating_sub(offset) >= 8 {
+        let pr_datasz = read_u32(endian, desc, offset + 4)? as usize;
+        if read_u32(endian, desc, offset)? == pr_type && pr_datasz >= 4 {
+            return read_u32(endian, desc, offset + 8);
+        }
+        offset = align_up((offset + 8).checked_add(pr_datasz)?, align)?;
+    }
+    None
+}
+
+// Return the hardening red flags: text relocations and executable stack.
+fn security_flags(elc: &ElfInfo) -> Vec<String> {
+    let mut flags = Vec::new();
+    if elc.textrel {
+        flags.push("{TEXTREL}".to_string());
+    }
+    if elc.execstack {
+        flags.push("{execstack}".to_string());
+    }
+    flags
+}
+
+// Return the dynamic section properties of interest.
+fn dynamic_metadata(elc: &ElfInfo, verbose: bool) -> Vec<String> {
+    let mut metadata = Vec::new();
+    if elc.relr {
+        metadata.push("{RELR}".to_string());
+    }
+    metadata.push(format!(
+        "{{binding: {}}}",
+        if elc.bind_now { "now" } else { "lazy" }
+    ));
+    // The DT_FLAGS_1 bits that change how the loader handles the object: it can not be
+    // unloaded, its symbols are available to all later dlopen calls, its initializer runs
+    // before the other ones, or its symbols interpose the other objects ones.
+    for (flag, name) in [
+        (DF_1_NODELETE, "DF_1_NODELETE"),
+        (DF_1_GLOBAL, "DF_1_GLOBAL"),
+        (DF_1_INITFIRST, "DF_1_INITFIRST"),
+        (DF_1_INTERPOSE, "DF_1_INTERPOSE"),
+    ] {
+        if elc.flags_1 & u64::from(flag) != 0 {
+            metadata.push(format!("{{{name}}}"));
+        }
+    }
+    // Only useful to debug a mangled string table.
+    if verbose {
+        metadata.push(format!("{{dynstr: {} bytes}}", elc.strsz));
+    }
+    metadata
+}
+
+// Both PIE executables and shared libraries are ET_DYN, where the PIE ones are marked
+// with DF_1_PIE.  For linkers that do not set it, a program interpreter without a
+// DT_SONAME is used instead, since some libraries (such as glibc libc.so.6) can also be
+// run.
+fn object_type(elc: &ElfInfo) -> &'static str {
+    if elc.e_type == ET_EXEC {
+        "executable (no-PIE)"
+    } else if elc.flags_1 & u64::from(DF_1_PIE) != 0
+        || (elc.interp.is_some() && elc.soname.is_none())
+    {
+        "executable (PIE)"
+    } else {
+        "shared library"
+    }
+}
+
+// Return the CET (x86) or BTI/PAC (AArch64) features from the GNU property note.
+fn security_features(elc: &ElfInfo) -> Option<String> {
+    let (kind, features) = match elc.e_machine {
+        EM_386 | EM_X86_64 => (
+            "CET",
+            [
+                (GNU_PROPERTY_X86_FEATURE_1_IBT, "IBT"),
+                (GNU_PROPERTY_X86_FEATURE_1_SHSTK, "SHSTK"),
+            ],
+        ),
+        EM_AARCH64 => (
+            "BTI/PAC",
+            [
+                (GNU_PROPERTY_AARCH64_FEATURE_1_BTI, "BTI"),
+                (GNU_PROPERTY_AARCH64_FEATURE_1_PAC, "PAC"),
+            ],
+        ),
+        _ => return None,
+    };
+    let enabled: Vec<&str> = features
+        .iter()
+        .filter(|(bit, _)| elc.feature_1 & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if enabled.is_empty() {
+        Some(format!("{{{kind}: none}}"))
+    } else {
+        Some(format!("{{{kind}: {}}}", enabled.join(",")))
+    }
+}
+
 fn parse_elf_dynamic_program_header<Elf: FileHeader>(
     endian: Elf::Endian,
     data: &[u8],
     elf: &Elf,
     headers: &[Elf::ProgramHeader],
     origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
     match headers
         .iter()
         .find(|&&hdr| hdr.p_type(endian) == PT_DYNAMIC)
     {
-        Some(hdr) => parse_elf_segment_dynamic(endian, data, elf, headers, hdr, origin, platform),
-        None => Err("No dynamic segments found"),
+        Some(hdr) => parse_elf_segment_dynamic(endian, data, elf, headers, hdr, origin, expand),
+        // The PT_DYNAMIC is what the loader uses, so only fallback to the section headers
+        // for objects without it (such as some relocatable intermediate outputs).
+        None => parse_elf_section_dynamic(endian, data, elf, origin, expand),
     }
 }
 
@@ -220,8 +424,8 @@ fn parse_elf_segment_dynamic<Elf: FileHeader>(
     segments: &[Elf::ProgramHeader],
     segment: &Elf::ProgramHeader,
     origin: &str,
-    platform: Option<&String>,
-) -> Result<ElfInfo, &'static str> {
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
     if let Ok(Some(dynamic)) = segment.dynamic(endian, data) {
         let mut strtab = 0;
         let mut strsz = 0;
@@ -238,37 +442,88 @@ fn parse_elf_segment_dynamic<Elf: FileHeader>(
 
         let dynstr = match parse_elf_stringtable::<Elf>(endian, data, segments, strtab, strsz) {
             Some(dynstr) => dynstr,
-            None => return Err("Failure to parse the string table"),
-        };
-
-        let df_1_nodeflib = u64::from(DF_1_NODEFLIB);
-        let dt_flags_1 = parse_elf_dyn_flags::<Elf>(endian, DT_FLAGS_1, dynamic);
-        let nodeflibs = dt_flags_1 & df_1_nodeflib == df_1_nodeflib;
-
-        return match parse_elf_dtneeded::<Elf>(endian, dynamic, dynstr) {
-            Ok(dtneeded) => Ok(ElfInfo {
-                ei_class: elf.e_ident().class,
-                ei_data: elf.e_ident().data,
-                ei_osabi: elf.e_ident().os_abi,
-                ei_abiver: elf.e_ident().abi_version,
-                e_machine: elf.e_machine(endian),
-                e_flags: elf.e_flags(endian),
-                interp: None,
-                soname: parse_elf_dyn_str::<Elf>(endian, DT_SONAME, dynamic, dynstr),
-                rpath: parse_elf_dyn_searchpath(
-                    endian, elf, DT_RPATH, dynamic, dynstr, origin, platform,
-                ),
-                runpath: parse_elf_dyn_searchpath(
-                    endian, elf, DT_RUNPATH, dynamic, dynstr, origin, platform,
-                ),
-                nodeflibs,
-                deps: dtneeded,
-                is_musl: false,
-            }),
-            Err(e) => Err(e),
+            None => {
+                return Err(RlddError::Parse(stringtable_error::<Elf>(
+                    endian, segments, strtab, strsz,
+                )))
+            }
         };
+
+        return parse_elf_dynamic(endian, elf, dynamic, dynstr, origin, expand);
+    }
+    Err(RlddError::Parse(
+        "Failure to parse dynamic segment".to_string(),
+    ))
+}
+
+fn parse_elf_section_dynamic<Elf: FileHeader>(
+    endian: Elf::Endian,
+    data: &[u8],
+    elf: &Elf,
+    origin: &str,
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
+    let sections = match elf.sections(endian, data) {
+        Ok(sections) => sections,
+        Err(_) => return Err(RlddError::Parse("invalid section".to_string())),
+    };
+
+    // Without a program header the DT_STRTAB address can not be mapped to a file offset,
+    // so use the string table linked to the section instead.
+    match sections.dynamic(endian, data) {
+        Ok(Some((dynamic, link))) => match sections.strings(endian, data, link) {
+            Ok(dynstr) => parse_elf_dynamic(endian, elf, dynamic, dynstr, origin, expand),
+            Err(_) => Err(RlddError::Parse(
+                "Failure to parse the string table".to_string(),
+            )),
+        },
+        _ => Err(RlddError::Parse("No dynamic segments found".to_string())),
     }
-    Err("Failure to parse dynamic segment")
+}
+
+fn parse_elf_dynamic<Elf: FileHeader>(
+    endian: Elf::Endian,
+    elf: &Elf,
+    dynamic: &[Elf::Dyn],
+    dynstr: StringTable,
+    origin: &str,
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
+    let df_1_nodeflib = u64::from(DF_1_NODEFLIB);
+    let dt_flags_1 = parse_elf_dyn_flags::<Elf>(endian, DT_FLAGS_1, dynamic);
+    let nodeflibs = dt_flags_1 & df_1_nodeflib == df_1_nodeflib;
+    let dt_flags = parse_elf_dyn_flags::<Elf>(endian, DT_FLAGS, dynamic);
+    let textrel = parse_elf_dyn_has_tag::<Elf>(endian, DT_TEXTREL, dynamic)
+        || dt_flags & u64::from(DF_TEXTREL) != 0;
+    let bind_now = parse_elf_dyn_has_tag::<Elf>(endian, DT_BIND_NOW, dynamic)
+        || dt_flags & u64::from(DF_BIND_NOW) != 0
+        || dt_flags_1 & u64::from(DF_1_NOW) != 0;
+
+    let dtneeded = parse_elf_dtneeded::<Elf>(endian, dynamic, dynstr)?;
+    Ok(ElfInfo {
+        ei_class: elf.e_ident().class,
+        ei_data: elf.e_ident().data,
+        ei_osabi: elf.e_ident().os_abi,
+        ei_abiver: elf.e_ident().abi_version,
+        e_type: elf.e_type(endian),
+        e_machine: elf.e_machine(endian),
+        e_flags: elf.e_flags(endian),
+        interp: None,
+        soname: parse_elf_dyn_str::<Elf>(endian, DT_SONAME, dynamic, dynstr),
+        rpath: parse_elf_dyn_searchpath(endian, elf, DT_RPATH, dynamic, dynstr, origin, expand),
+        runpath: parse_elf_dyn_searchpath(endian, elf, DT_RUNPATH, dynamic, dynstr, origin, expand),
+        nodeflibs,
+        deps: dtneeded,
+        is_glibc: false,
+        is_musl: false,
+        feature_1: 0,
+        relr: parse_elf_dyn_has_tag::<Elf>(endian, DT_RELR, dynamic),
+        bind_now,
+        textrel,
+        execstack: false,
+        flags_1: dt_flags_1,
+        strsz: parse_elf_dyn_flags::<Elf>(endian, DT_STRSZ, dynamic),
+    })
 }
 
 fn parse_elf_stringtable<'a, Elf: FileHeader>(
@@ -286,6 +541,29 @@ fn parse_elf_stringtable<'a, Elf: FileHeader>(
     None
 }
 
+// Describe why the DT_STRTAB/DT_STRSZ range can not be read: either no segment maps the
+// DT_STRTAB address, or the table goes past the end of its segment (or of the file).
+fn stringtable_error<Elf: FileHeader>(
+    endian: Elf::Endian,
+    segments: &[Elf::ProgramHeader],
+    strtab: u64,
+    strsz: u64,
+) -> String {
+    let mapped = segments.iter().any(|s| {
+        let vaddr: u64 = s.p_vaddr(endian).into();
+        let filesz: u64 = s.p_filesz(endian).into();
+        strtab >= vaddr && strtab - vaddr < filesz
+    });
+    format!(
+        "Failure to parse the string table (DT_STRTAB {strtab:#x}, DT_STRSZ {strsz}: {})",
+        if mapped {
+            "past the end of its segment"
+        } else {
+            "not mapped by any segment"
+        }
+    )
+}
+
 fn parse_elf_dyn_str<Elf: FileHeader>(
     endian: Elf::Endian,
     tag: u32,
@@ -310,23 +588,67 @@ fn parse_elf_dyn_str<Elf: FileHeader>(
     None
 }
 
+// Replace both $token and ${token}, where as for the loader the unbraced form is only
+// a match if not followed by an identifier character (so $ORIGINAL is kept verbatim).
 fn replace_dyn_str(dynstr: &str, token: &str, value: &str) -> String {
-    let newdynstr = dynstr.replace(&format!("${token}"), value);
-    // Also handle ${token}
-    newdynstr.replace(&format!("${{{token}}}"), value)
+    let unbraced = format!("${token}");
+    let braced = format!("${{{token}}}");
+    let mut newdynstr = String::with_capacity(dynstr.len());
+    let mut rest = dynstr;
+    while let Some(pos) = rest.find('$') {
+        newdynstr.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with(&braced) {
+            newdynstr.push_str(value);
+            rest = &rest[braced.len()..];
+        } else if rest.starts_with(&unbraced)
+            && !rest[unbraced.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        {
+            newdynstr.push_str(value);
+            rest = &rest[unbraced.len()..];
+        } else {
+            newdynstr.push('$');
+            rest = &rest[1..];
+        }
+    }
+    newdynstr.push_str(rest);
+    newdynstr
 }
 
+// glibc expands $LIB to the last component of the system directory (for instance 'lib64'
+// or 'libx32', without the leading '/').
 #[cfg(target_os = "linux")]
 fn parse_elf_dyn_searchpath_lib<Elf: FileHeader>(
     endian: Elf::Endian,
     elf: &Elf,
     dynstr: &mut String,
 ) {
-    let libdir = system_dirs::get_slibdir(elf.e_machine(endian), elf.e_ident().class).unwrap();
+    let slibdir = system_dirs::get_slibdir(elf.e_machine(endian), elf.e_ident().class).unwrap();
+    let libdir = slibdir.rsplit('/').next().unwrap_or(slibdir);
     *dynstr = replace_dyn_str(dynstr, "LIB", libdir);
 }
 
-#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+// The bionic loader expands $LIB to either 'lib' or 'lib64' depending of the ABI.
+#[cfg(target_os = "android")]
+fn parse_elf_dyn_searchpath_lib<Elf: FileHeader>(
+    _endian: Elf::Endian,
+    elf: &Elf,
+    dynstr: &mut String,
+) {
+    let libdir = if elf.e_ident().class == ELFCLASS64 {
+        "lib64"
+    } else {
+        "lib"
+    };
+    *dynstr = replace_dyn_str(dynstr, "LIB", libdir);
+}
+
+// The FreeBSD, NetBSD, OpenBSD, and illumos loaders do not support $LIB (only $ORIGIN,
+// $PLATFORM, and some OS specific tokens), so it is left verbatim as the loader does.
+#[cfg(all(
+    target_family = "unix",
+    not(any(target_os = "linux", target_os = "android"))
+))]
 fn parse_elf_dyn_searchpath_lib<Elf: FileHeader>(
     _endian: Elf::Endian,
     _elf: &Elf,
@@ -341,19 +663,38 @@ fn parse_elf_dyn_searchpath<Elf: FileHeader>(
     dynamic: &[Elf::Dyn],
     dynstr: StringTable,
     origin: &str,
-    platform: Option<&String>,
+    expand: &Expand,
 ) -> search_path::SearchPathVec {
-    if let Some(dynstr) = parse_elf_dyn_str::<Elf>(endian, tag, dynamic, dynstr) {
-        // EXpand $ORIGIN, $LIB, and $PLATFORM.
+    if let Some(mut dynstr) = parse_elf_dyn_str::<Elf>(endian, tag, dynamic, dynstr) {
+        // The absolute entries are relative to the --sysroot, while $ORIGIN is already a
+        // path inside it.
+        if let Some(sysroot) = expand.sysroot {
+            dynstr = dynstr
+                .split(':')
+                .map(|p| {
+                    if p.starts_with('/') {
+                        sysroot.rebase(p)
+                    } else {
+                        p.to_string()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(":");
+        }
+
+        // EXpand $ORIGIN, $LIB, $PLATFORM, and $OSNAME.
         let mut newdynstr = replace_dyn_str(&dynstr, "ORIGIN", origin);
 
         parse_elf_dyn_searchpath_lib(endian, elf, &mut newdynstr);
 
-        let platform = match platform {
+        let platform = match expand.platform {
             Some(platform) => platform.to_string(),
             None => platform::get(elf.e_machine(endian), elf.e_ident().data),
         };
-        let newdynstr = replace_dyn_str(&newdynstr, "$PLATFORM", platform.as_str());
+        let mut newdynstr = replace_dyn_str(&newdynstr, "PLATFORM", platform.as_str());
+        if let Some(osname) = platform::OSNAME {
+            newdynstr = replace_dyn_str(&newdynstr, "OSNAME", osname);
+        }
 
         return search_path::from_string(newdynstr, &[':']);
     }
@@ -364,8 +705,10 @@ fn parse_elf_dtneeded<Elf: FileHeader>(
     endian: Elf::Endian,
     dynamic: &[Elf::Dyn],
     dynstr: StringTable,
-) -> Result<DepsVec, &'static str> {
+) -> Result<DepsVec, RlddError> {
     let mut dtneeded = DepsVec::new();
+    // The index counts the entries that can not be parsed as well.
+    let mut index = 0;
     for d in dynamic {
         if d.d_tag(endian).into() == DT_NULL.into() {
             break;
@@ -378,11 +721,15 @@ fn parse_elf_dtneeded<Elf: FileHeader>(
             continue;
         }
 
+        index += 1;
         match d.string(endian, dynstr) {
             Err(_) => continue,
             Ok(s) => {
                 if let Ok(s) = str::from_utf8(s) {
-                    dtneeded.push(s.to_string());
+                    dtneeded.push(Needed {
+                        name: s.to_string(),
+                        index: index - 1,
+                    });
                 }
             }
         }
@@ -390,6 +737,17 @@ fn parse_elf_dtneeded<Elf: FileHeader>(
     Ok(dtneeded)
 }
 
+fn parse_elf_dyn_has_tag<Elf: FileHeader>(
+    endian: Elf::Endian,
+    tag: u32,
+    dynamic: &[Elf::Dyn],
+) -> bool {
+    dynamic
+        .iter()
+        .take_while(|d| d.d_tag(endian).into() != DT_NULL.into())
+        .any(|d| d.tag32(endian).is_some() && d.d_tag(endian).into() == tag.into())
+}
+
 fn parse_elf_dyn_flags<Elf: FileHeader>(
     endian: Elf::Endian,
     tag: u32,
@@ -413,18 +771,11 @@ fn open_elf_file<P: AsRef<Path>>(
     filename: &P,
     melc: Option<&ElfInfo>,
     dtneeded: Option<&String>,
-    platform: Option<&String>,
+    expand: &Expand,
     preload: bool,
-) -> Result<ElfInfo, std::io::Error> {
-    let file = match fs::File::open(filename) {
-        Ok(file) => file,
-        Err(_) => return Err(Error::new(ErrorKind::Other, "Failed to open file")),
-    };
-
-    let mmap = match unsafe { memmap2::Mmap::map(&file) } {
-        Ok(mmap) => mmap,
-        Err(_) => return Err(Error::new(ErrorKind::Other, "Failed to map file")),
-    };
+) -> Result<ElfInfo, RlddError> {
+    let file = fs::File::open(filename)?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }?;
 
     let parent = filename
         .as_ref()
@@ -432,42 +783,57 @@ fn open_elf_file<P: AsRef<Path>>(
         .and_then(Path::to_str)
         .unwrap_or("");
 
-    match parse_object(&mmap, parent, platform) {
-        Ok(elc) => {
-            if let Some(melc) = melc {
-                // Skip DT_NEEDED and SONAME checks for preload objects.
-                if !preload && !match_elf_name(melc, dtneeded, &elc) {
-                    return Err(Error::new(ErrorKind::Other, "Error parsing ELF object"));
-                }
-            }
-            Ok(elc)
+    // Not a supported object (RlddError::Parse), which --recursive skips silently.
+    let elc = parse_object(&mmap, parent, expand)?;
+    if let Some(melc) = melc {
+        // Skip DT_NEEDED and SONAME checks for preload objects.
+        if !preload {
+            // Report why the object can not be used, so it can be shown on the search
+            // trace.
+            match_elf_name(melc, dtneeded, &elc, expand.relaxed_soname)?;
         }
-        Err(e) => Err(Error::new(ErrorKind::Other, e)),
     }
+    Ok(elc)
 }
 
-fn match_elf_name(melc: &ElfInfo, dtneeded: Option<&String>, elc: &ElfInfo) -> bool {
-    if !check_elf_header(elc) || !match_elf_header(melc, elc) {
-        return false;
-    }
+fn match_elf_name(
+    melc: &ElfInfo,
+    dtneeded: Option<&String>,
+    elc: &ElfInfo,
+    relaxed: bool,
+) -> Result<(), RlddError> {
+    check_elf_header(elc).map_err(RlddError::ArchMismatch)?;
+    match_elf_header(melc, elc).map_err(RlddError::ArchMismatch)?;
 
-    // If DT_SONAME is defined compare against it.
-    if let Some(dtneeded) = dtneeded {
-        return match_elf_soname(dtneeded, elc);
+    // If DT_SONAME is defined compare against it.  A DT_NEEDED with a slash is loaded
+    // from that path without any DT_SONAME check.
+    if let Some(dtneeded) = dtneeded.filter(|d| !d.contains('/')) {
+        return match_elf_soname(dtneeded, elc, relaxed).map_err(RlddError::SonameMismatch);
     };
 
-    true
+    Ok(())
+}
+
+fn check_elf_osabi(valid: bool, elc: &ElfInfo) -> Result<(), String> {
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "unsupported EI_OSABI ({})",
+            arch::osabi_name(elc.ei_osabi)
+        ))
+    }
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-fn check_elf_header(elc: &ElfInfo) -> bool {
+fn check_elf_header(elc: &ElfInfo) -> Result<(), String> {
     let maxver = match elc.e_machine {
         EM_MIPS | EM_MIPS_RS3_LE => 6,
         EM_PPC | EM_PPC64 | EM_SPARC | EM_X86_64 | EM_RISCV => 5,
         _ => 4,
     };
 
-    let check_elf_osabi = match elc.e_machine {
+    let check_elf_osabi_linux = match elc.e_machine {
         EM_ARM => {
             |osabi| osabi == ELFOSABI_SYSV || osabi == ELFOSABI_GNU || osabi == ELFOSABI_ARM_AEABI
         }
@@ -483,35 +849,85 @@ fn check_elf_header(elc: &ElfInfo) -> bool {
         _ => |osabi, ver, maxver| ver == 0 || (osabi == ELFOSABI_GNU && ver < maxver),
     };
 
-    check_elf_osabi(elc.ei_osabi) && check_elf_abiversion(elc.ei_osabi, elc.ei_abiver, maxver)
+    check_elf_osabi(check_elf_osabi_linux(elc.ei_osabi), elc)?;
+    if !check_elf_abiversion(elc.ei_osabi, elc.ei_abiver, maxver) {
+        return Err(format!("unsupported EI_ABIVERSION ({})", elc.ei_abiver));
+    }
+    Ok(())
 }
 #[cfg(target_os = "freebsd")]
-fn check_elf_header(elc: &ElfInfo) -> bool {
-    elc.ei_osabi == ELFOSABI_FREEBSD
+fn check_elf_header(elc: &ElfInfo) -> Result<(), String> {
+    check_elf_osabi(elc.ei_osabi == ELFOSABI_FREEBSD, elc)
 }
 #[cfg(target_os = "openbsd")]
-fn check_elf_header(elc: &ElfInfo) -> bool {
-    elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_OPENBSD
+fn check_elf_header(elc: &ElfInfo) -> Result<(), String> {
+    check_elf_osabi(
+        elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_OPENBSD,
+        elc,
+    )
 }
 #[cfg(target_os = "netbsd")]
-fn check_elf_header(elc: &ElfInfo) -> bool {
-    elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_NETBSD
+fn check_elf_header(elc: &ElfInfo) -> Result<(), String> {
+    check_elf_osabi(
+        elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_NETBSD,
+        elc,
+    )
 }
 #[cfg(any(target_os = "illumos", target_os = "solaris"))]
-fn check_elf_header(elc: &ElfInfo) -> bool {
-    elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_SOLARIS
+fn check_elf_header(elc: &ElfInfo) -> Result<(), String> {
+    check_elf_osabi(
+        elc.ei_osabi == ELFOSABI_SYSV || elc.ei_osabi == ELFOSABI_SOLARIS,
+        elc,
+    )
 }
 
-fn match_elf_header(a1: &ElfInfo, a2: &ElfInfo) -> bool {
-    a1.ei_class == a2.ei_class && a1.ei_data == a2.ei_data && a1.e_machine == a2.e_machine
+fn elf_arch(elc: &ElfInfo) -> String {
+    arch::get(elc.ei_class, elc.e_machine, elc.ei_osabi)
 }
 
-fn match_elf_soname(dtneeded: &String, elc: &ElfInfo) -> bool {
-    let soname = &elc.soname;
-    if let Some(soname) = soname {
-        return dtneeded == soname;
+// Check if the candidate A2 can be loaded by A1.
+fn match_elf_header(a1: &ElfInfo, a2: &ElfInfo) -> Result<(), String> {
+    if a1.ei_class != a2.ei_class {
+        return Err(format!(
+            "EI_CLASS mismatch ({} vs {})",
+            arch::class_name(a2.ei_class),
+            arch::class_name(a1.ei_class)
+        ));
     }
-    true
+    if a1.ei_data != a2.ei_data {
+        return Err("EI_DATA mismatch".to_string());
+    }
+    if a1.e_machine != a2.e_machine {
+        return Err(format!(
+            "e_machine mismatch ({} vs {})",
+            arch::machine_name(a2.e_machine),
+            arch::machine_name(a1.e_machine)
+        ));
+    }
+    Ok(())
+}
+
+fn match_elf_soname(dtneeded: &String, elc: &ElfInfo, relaxed: bool) -> Result<(), String> {
+    match &elc.soname {
+        Some(soname) if relaxed && is_soname_version_of(dtneeded, soname) => Ok(()),
+        Some(soname) if dtneeded != soname => {
+            Err(format!("DT_SONAME mismatch ({soname} vs {dtneeded})"))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Whether SONAME is DTNEEDED followed by numeric version components (libfoo.so.1.2 for
+// libfoo.so.1).
+fn is_soname_version_of(dtneeded: &str, soname: &str) -> bool {
+    soname
+        .strip_prefix(dtneeded)
+        .and_then(|s| s.strip_prefix('.'))
+        .is_some_and(|version| {
+            version
+                .split('.')
+                .all(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()))
+        })
 }
 
 // Global configuration used on program dynamic resolution:
@@ -519,22 +935,277 @@ fn match_elf_soname(dtneeded: &String, elc: &ElfInfo) -> bool {
 // - ld_library_path: Search path parsed from --ld-library-path.
 // - ld_so_conf: paths parsed from the ld.so.conf in the system.
 // - system_dirs: system defaults deirectories based on binary architecture.
+// - extra_dirs: additional directories searched after the system ones.
+// - explain: dependency name to keep the search trace for.
+// - verbose: keep the search trace for all dependencies.
+// - audit_rpath: report insecure DT_RPATH/DT_RUNPATH entries.
+// - security: show the GNU property hardening features of each object.
+// - show_arch: show the architecture of each object and the rejected candidates.
+// - merge_hardlinks: handle objects with the same device and inode as already found.
+// - metadata: show the dynamic section metadata of each object.
 struct Config<'a> {
     ld_preload: &'a search_path::SearchPathVec,
     ld_library_path: &'a search_path::SearchPathVec,
     ld_cache: &'a Option<LoaderCache>,
     system_dirs: search_path::SearchPathVec,
-    platform: Option<&'a String>,
+    extra_dirs: &'a search_path::SearchPathVec,
+    expand: Expand<'a>,
     all: bool,
+    explain: Option<&'a String>,
+    verbose: bool,
+    audit_rpath: bool,
+    security: bool,
+    show_arch: bool,
+    merge_hardlinks: bool,
+    metadata: bool,
+    warn_shadow: bool,
+    lint: bool,
+    object_type: bool,
+    needed_index: bool,
+    // File name of the program interpreter to omit from the tree, with --no-interp.
+    no_interp: Option<String>,
+    // File name of the musl loader, which also provides libc, if the input uses it.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    musl_interp: Option<String>,
+    limits: Limits,
+    map: &'a [Mapping],
+    on_node: Option<&'a NodeHook>,
+}
+
+impl Config<'_> {
+    // Report the node at IDX to the --events hook, if any.
+    fn emit(&self, deptree: &DepTree, idx: usize) {
+        if let Some(on_node) = self.on_node {
+            let node = &deptree.arena[idx];
+            on_node(&node.val, node.parent.map(|p| &deptree.arena[p].val));
+        }
+    }
+
+    // The search trace is only kept for the dependency being explained, or for all of
+    // them in verbose mode.
+    fn keep_trace(&self, name: &str, trace: SearchTrace) -> SearchTrace {
+        match self.explain {
+            _ if self.verbose => trace,
+            Some(explain) if explain == name => trace,
+            _ => SearchTrace::new(),
+        }
+    }
+
+    fn audit_rpath(&self, elc: &ElfInfo) -> Vec<String> {
+        if !self.audit_rpath {
+            return Vec::new();
+        }
+        let mut warnings = audit_searchpath("DT_RPATH", &elc.rpath);
+        warnings.extend(audit_searchpath("DT_RUNPATH", &elc.runpath));
+        warnings
+    }
+
+    fn object_info(&self, elc: &ElfInfo) -> Vec<String> {
+        let mut info = Vec::new();
+        if self.show_arch {
+            info.push(format!("{{{}}}", elf_arch(elc)));
+        }
+        if self.security {
+            info.extend(security_features(elc));
+            info.extend(security_flags(elc));
+        }
+        if self.metadata {
+            info.extend(dynamic_metadata(elc, self.verbose));
+        }
+        info
+    }
+
+    // The DT_NEEDED list position of a dependency, for --needed-index.
+    fn needed_info(&self, index: Option<usize>) -> Vec<String> {
+        match index {
+            Some(index) if self.needed_index => vec![format!("{{needed: {index}}}")],
+            _ => Vec::new(),
+        }
+    }
+
+    // The object type is only shown for the input, since the dependencies are all
+    // shared libraries.
+    fn root_info(&self, elc: &ElfInfo) -> Vec<String> {
+        let mut info = Vec::new();
+        if self.object_type {
+            info.push(format!("{{{}}}", object_type(elc)));
+        }
+        info.extend(self.object_info(elc));
+        info
+    }
+
+    // A library whose file name does not match its DT_SONAME can not be found as a
+    // dependency.  The real file of a versioned library (libfoo.so.1.2.3 for the
+    // libfoo.so.1 DT_SONAME) is fine since ldconfig creates the DT_SONAME symlink.
+    // A DT_NEEDED with a slash is loaded from that path as-is, without any search,
+    // which usually comes from linking against a library path without a DT_SONAME
+    // and breaks once the binary is installed elsewhere.  DT_RPATH is deprecated in favor
+    // of DT_RUNPATH, which only applies to the object own dependencies and can be
+    // overridden by LD_LIBRARY_PATH.
+    fn lint(&self, arg: &str, elc: &ElfInfo) -> Vec<String> {
+        if !self.lint {
+            return Vec::new();
+        }
+        let mut warnings = Vec::new();
+        if let Some(soname) = &elc.soname {
+            let name = pathutils::get_name(&Path::new(arg));
+            if name != *soname && !name.starts_with(&format!("{soname}.")) {
+                warnings.push(format!("file name does not match DT_SONAME {soname}"));
+            }
+        }
+        for dtneeded in elc.deps.iter().map(|d| &d.name).filter(|d| d.contains('/')) {
+            let kind = if Path::new(dtneeded).is_absolute() {
+                "an absolute path"
+            } else {
+                "a relative path"
+            };
+            warnings.push(format!("DT_NEEDED {dtneeded} is {kind}"));
+        }
+        if !elc.rpath.is_empty() {
+            if elc.runpath.is_empty() {
+                warnings.push(
+                    "uses the deprecated DT_RPATH, which also applies to the dependencies \
+                    (link with --enable-new-dtags for DT_RUNPATH)"
+                        .to_string(),
+                );
+            } else {
+                warnings.push("DT_RPATH is ignored since DT_RUNPATH is set".to_string());
+            }
+        }
+        warnings
+    }
+
+    // With --relaxed-soname the DT_SONAME of the object found for DTNEEDED might have
+    // additional version components, which the loader would reject.
+    fn soname_mismatch(&self, dtneeded: &str, elc: &ElfInfo) -> Option<String> {
+        let soname = elc.soname.as_deref()?;
+        (self.expand.relaxed_soname && is_soname_version_of(dtneeded, soname))
+            .then(|| format!("DT_SONAME {soname} does not match DT_NEEDED {dtneeded}"))
+    }
+
+    // Other valid candidates for DTNEEDED in the search locations, which the loader
+    // ignores since FOUND is used instead.
+    fn shadowed(&self, dtneeded: &String, elc: &ElfInfo, found: &Path) -> Vec<String> {
+        if !self.warn_shadow {
+            return Vec::new();
+        }
+
+        let mut dirs = Vec::new();
+        if elc.runpath.is_empty() {
+            dirs.extend(elc.rpath.iter().map(|s| s.path.clone()));
+        }
+        dirs.extend(self.ld_library_path.iter().map(|s| s.path.clone()));
+        dirs.extend(elc.runpath.iter().map(|s| s.path.clone()));
+        if !elc.nodeflibs {
+            if let Some(ld_cache) = self.ld_cache {
+                dirs.extend(ld_cache_dirs(ld_cache, dtneeded));
+            }
+            dirs.extend(self.system_dirs.iter().map(|s| s.path.clone()));
+        }
+        dirs.extend(self.extra_dirs.iter().map(|s| s.path.clone()));
+
+        // The same file might be reachable from multiple directories (for instance /lib
+        // being a symlink to /usr/lib).
+        let mut seen: Vec<_> = fs::canonicalize(found).into_iter().collect();
+        let mut shadowed = Vec::new();
+        for dir in dirs {
+            let path = Path::new(&dir).join(dtneeded);
+            let canonical = match fs::canonicalize(&path) {
+                Ok(canonical) if !seen.contains(&canonical) => canonical,
+                _ => continue,
+            };
+            if open_elf_file(&path, Some(elc), Some(dtneeded), &self.expand, false).is_ok() {
+                shadowed.push(format!("shadows {}", path.display()));
+                seen.push(canonical);
+            }
+        }
+        shadowed
+    }
+
+    // The PT_INTERP loader is not searched for, so a missing one or one for another
+    // architecture (for instance on a misconfigured multiarch sysroot) only shows up as
+    // an obscure exec failure.
+    fn check_interp(&self, elc: &ElfInfo) -> Vec<String> {
+        let interp = match &elc.interp {
+            Some(interp) => interp,
+            None => return Vec::new(),
+        };
+        let path = match self.expand.sysroot {
+            Some(sysroot) if interp.starts_with('/') => sysroot.rebase(interp),
+            _ => interp.to_string(),
+        };
+        match open_elf_file(&path, Some(elc), None, &self.expand, false) {
+            Err(RlddError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                vec![format!("program interpreter {interp} not found")]
+            }
+            Err(RlddError::ArchMismatch(e)) => {
+                vec![format!("program interpreter {interp} can not be used: {e}")]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // The real location of DEPENDENCY if it has a slash (so it is loaded from that path
+    // as-is), following the symlinks.  Inside a --sysroot the symlinks are relative to
+    // the root rather than the host one, so the path is kept.
+    fn direct_path(&self, dependency: &str) -> String {
+        if !dependency.contains('/') || self.expand.sysroot.is_some() {
+            return dependency.to_string();
+        }
+        fs::canonicalize(dependency)
+            .map_or(dependency.to_string(), |p| p.to_string_lossy().to_string())
+    }
+
+    // The --map entry for DEPENDENCY, if any.
+    fn mapped(&self, dependency: &str) -> Option<&Mapping> {
+        self.map.iter().find(|m| m.soname == dependency)
+    }
+
+    fn file_id<P: AsRef<Path>>(&self, path: &P) -> Option<(u64, u64)> {
+        if !self.merge_hardlinks {
+            return None;
+        }
+        fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+    }
+
+    // Candidates found but not used, for a not found dependency.
+    fn rejected_info(&self, trace: &SearchTrace) -> Vec<String> {
+        if !self.show_arch {
+            return Vec::new();
+        }
+        trace
+            .iter()
+            .filter_map(|t| {
+                t.rejected
+                    .as_ref()
+                    .map(|r| format!("{{rejected {}: {r}}}", t.path))
+            })
+            .collect()
+    }
+}
+
+// A search path entry is considered insecure if it is relative to the current working
+// directory (since $ORIGIN is already expanded to an absolute path) or if it is
+// world-writable (the sticky bit does not prevent a new library being added).
+fn audit_searchpath(tag: &str, searchpaths: &search_path::SearchPathVec) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for searchpath in searchpaths {
+        if !searchpath.path.starts_with('/') {
+            warnings.push(format!("{tag} entry '{}' is relative", searchpath.path));
+        }
+        if searchpath.mode & 0o002 != 0 {
+            warnings.push(format!(
+                "{tag} entry '{}' is world-writable",
+                searchpath.path
+            ));
+        }
+    }
+    warnings
 }
 
 // Function that mimic the dynamic loader resolution.
 #[cfg(target_os = "linux")]
-fn resolve_binary_arch(
-    elc: &ElfInfo,
-    deptree: &mut DepTree,
-    depp: usize,
-) -> Result<(), std::io::Error> {
+fn resolve_binary_arch(elc: &ElfInfo, deptree: &mut DepTree, depp: usize) -> Result<(), RlddError> {
     // musl loader and libc is on the same shared object, so adds a synthetic dependendy for
     // the binary so it is also shown and to be returned in case a objects has libc.so
     // as needed.
@@ -542,6 +1213,7 @@ fn resolve_binary_arch(
         return Ok(());
     }
 
+    // With --libc musl there might be no program interpreter to add.
     if let Some(interp) = &elc.interp {
         let path = Path::new(&interp);
         deptree.addnode(
@@ -552,40 +1224,118 @@ fn resolve_binary_arch(
                 name: pathutils::get_name(&path),
                 mode: DepMode::SystemDirs,
                 found: true,
+                warnings: Vec::new(),
+                info: Vec::new(),
+                file_id: None,
+                trace: SearchTrace::new(),
             },
             depp,
         );
-        return Ok(());
     }
 
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "musl: failed to get INTERP value",
-    ))
+    Ok(())
 }
 #[cfg(all(target_family = "unix", not(target_os = "linux")))]
 fn resolve_binary_arch(
     _elc: &ElfInfo,
     _deptree: &mut DepTree,
     _depp: usize,
-) -> Result<(), std::io::Error> {
+) -> Result<(), RlddError> {
     Ok(())
 }
 
+// glibc loader is a regular shared object that ldd always lists, however it is only
+// shown if some object has it as DT_NEEDED (usually libc.so.6).  Add it from PT_INTERP
+// otherwise, after all dependencies are resolved so it is not added twice.  As for a
+// DT_NEEDED with a slash, it is opened as-is (inside the --sysroot, if any).
+#[cfg(target_os = "linux")]
+fn resolve_binary_interp(config: &Config, elc: &ElfInfo, deptree: &mut DepTree, depp: usize) {
+    if !elc.is_glibc {
+        return;
+    }
+
+    if let Some(interp) = &elc.interp {
+        let name = pathutils::get_name(&Path::new(interp));
+        if deptree.contains(&name) {
+            return;
+        }
+        let path = match config.expand.sysroot {
+            Some(sysroot) if interp.starts_with('/') => sysroot.rebase(interp),
+            _ => interp.to_string(),
+        };
+        let mut trace = SearchTrace::new();
+        let mode = match open_candidate(
+            &path,
+            DepMode::SystemDirs,
+            elc,
+            interp,
+            &config.expand,
+            false,
+            &mut trace,
+        ) {
+            Some(_) => DepMode::SystemDirs,
+            None => {
+                config.limits.not_found();
+                DepMode::NotFound
+            }
+        };
+        deptree.addnode(
+            DepNode {
+                path: pathutils::get_path(&Path::new(&path)),
+                info: config.rejected_info(&trace),
+                trace: config.keep_trace(&name, trace),
+                name,
+                mode,
+                found: false,
+                warnings: Vec::new(),
+                file_id: None,
+            },
+            depp,
+        );
+    }
+}
+#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+fn resolve_binary_interp(_config: &Config, _elc: &ElfInfo, _deptree: &mut DepTree, _depp: usize) {}
+
+// Whether the musl loader resolves DEPENDENCY to itself, which is already added to the
+// tree by resolve_binary_arch.
+#[cfg(target_os = "linux")]
+fn is_musl_loader(config: &Config, dependency: &str) -> bool {
+    config
+        .musl_interp
+        .as_ref()
+        .is_some_and(|interp| interp::is_musl_self(dependency, interp))
+}
+#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+fn is_musl_loader(_config: &Config, _dependency: &str) -> bool {
+    false
+}
+
+// Resolution state shared between the inputs.
+pub type Context = Option<LoaderCache>;
+
 // The loader search cache is lazy loaded if the binary has a loader that actually
 // supports it.
-pub fn create_context() -> Option<LoaderCache> {
+pub fn create_context(_opts: &ResolveOptions) -> Context {
+    None
+}
+
+// Describe the loader cache used by the resolution, for --verbose.
+#[cfg(target_os = "linux")]
+pub fn describe_context(ctx: &Context) -> Option<String> {
+    ctx.as_ref()
+        .map(|c| format!("ld.so.cache: parsed the {}", c.format))
+}
+#[cfg(not(target_os = "linux"))]
+pub fn describe_context(_ctx: &Context) -> Option<String> {
     None
 }
 
 pub fn resolve_binary(
     ld_cache: &mut Option<LoaderCache>,
-    ld_preload: &search_path::SearchPathVec,
-    ld_library_path: &search_path::SearchPathVec,
-    platform: &Option<String>,
-    all: bool,
+    opts: &ResolveOptions,
     arg: &str,
-) -> Result<DepTree, std::io::Error> {
+) -> Result<DepTree, RlddError> {
     // On glibc/Linux the RTLD_DI_ORIGIN for the executable itself (used for $ORIGIN
     // expansion) is obtained by first following the '/proc/self/exe' symlink and if
     // it is not available the loader also checks the 'LD_ORIGIN_PATH' environment
@@ -594,19 +1344,90 @@ pub fn resolve_binary(
     // try to canocalize the input filename to remove any symlinks.  There is not much
     // sense in trying LD_ORIGIN_PATH, since it is only checked by the loader if
     // the binary can not dereference the procfs entry.
-    let filename = Path::new(arg).canonicalize()?;
+    // With --no-canonicalize the argument directory is used as-is instead (only made
+    // absolute), so $ORIGIN refers to where a symlink lives rather than its target.
+    // A --simulate input is not a file, so it is always relative to the CWD.
+    let filename = if opts.no_canonicalize || opts.simulate.is_some() {
+        std::env::current_dir()?.join(arg)
+    } else {
+        Path::new(arg).canonicalize()?
+    };
 
-    let elc = open_elf_file(&filename, None, None, platform.as_ref(), false)?;
+    let expand = Expand {
+        platform: opts.platform.as_ref(),
+        sysroot: opts.sysroot.as_ref(),
+        relaxed_soname: opts.relaxed_soname,
+    };
+
+    let elc = match &opts.simulate {
+        Some(simulate) => simulate_elf_file(simulate, arg, &expand),
+        None => open_elf_file(&filename, None, None, &expand, false),
+    };
+    let elc = match elc {
+        // A core file is shown on its own, so batch scans over directories with core dumps
+        // do not report them as errors.
+        Err(RlddError::CoreFile) => {
+            let mut deptree = DepTree::new();
+            deptree.addroot(DepNode {
+                path: pathutils::get_path(&filename),
+                name: pathutils::get_name(&filename),
+                mode: DepMode::Executable,
+                found: false,
+                warnings: Vec::new(),
+                info: vec![format!("{{{}}}", RlddError::CoreFile)],
+                file_id: None,
+                trace: SearchTrace::new(),
+            });
+            return Ok(deptree);
+        }
+        elc => elc?,
+    };
+
+    // The --libc option overrides the detection from the program interpreter, for
+    // instance for a renamed loader or an object without PT_INTERP, while --glibc-interp
+    // extends it.  A shared library usually has no PT_INTERP, so the loader can not be
+    // inferred and the glibc one is assumed, otherwise the dependencies only the
+    // ld.so.cache knows about would not be found.
+    #[cfg(target_os = "linux")]
+    let elc = match opts.libc {
+        Some(libc) => ElfInfo {
+            is_glibc: libc == Libc::Glibc,
+            is_musl: libc == Libc::Musl,
+            ..elc
+        },
+        None if elc.interp.is_none() && elc.e_type == ET_DYN => ElfInfo {
+            is_glibc: true,
+            ..elc
+        },
+        None if !opts.glibc_interp.is_empty() => ElfInfo {
+            is_glibc: interp::is_glibc(&elc.interp, &opts.glibc_interp),
+            ..elc
+        },
+        None => elc,
+    };
+
+    // The loader files inside a --sysroot are not part of the host configuration.
+    let host = opts
+        .sysroot
+        .as_ref()
+        .is_none_or(|s| Path::new(&s.root) == Path::new("/"));
 
     // The cache/hints/config file is usually an optional file and failing to open it
     // does not incur on a resolution failure.
-    load_so_cache(ld_cache, &filename, &elc);
+    // The --cache option overrides the default file, for instance to analyze a cache
+    // copied from another system.  With --isolated only an explicit --cache or the one
+    // from the --sysroot is used.
+    if !opts.isolated || opts.cache.is_some() || !host {
+        load_so_cache(ld_cache, &filename, &elc, opts);
+    }
 
     // Same for glibc ld.so.preload file.
-    let mut preload = ld_preload.to_vec();
+    let mut preload = opts.ld_preload.to_vec();
     // glibc first parses LD_PRELOAD and then ld.so.preload.
     // We need a new vector for the case of binaries with different interpreters.
-    preload.extend(load_ld_so_preload(&elc.interp));
+    if !opts.isolated || !host {
+        preload.extend(load_ld_so_preload(&elc, opts.sysroot.as_ref()));
+    }
 
     // android loader only uses the default system search patch if the ld.so.config file can not
     // be loader or if an error was found parsing it (for instance if the executable does not
@@ -620,63 +1441,298 @@ pub fn resolve_binary(
         true
     }
 
-    let system_dirs = if load_system_dirs(&*ld_cache) {
-        system_dirs::get_system_dirs(&elc.interp, elc.e_machine, elc.ei_class)?
+    // The musl loader does not use the glibc ld.so.cache, and reads its default search
+    // path from a configuration file instead of using the $slibdir ones.
+    let system_dirs = if elc.is_musl {
+        load_musl_path(&elc, opts.sysroot.as_ref(), !opts.isolated || !host)
+    } else if load_system_dirs(&*ld_cache) {
+        system_dirs::get_system_dirs(
+            &elc.interp,
+            elc.e_machine,
+            elc.ei_class,
+            opts.sysroot.as_ref(),
+        )?
     } else {
         search_path::SearchPathVec::new()
     };
 
     let config = Config {
         ld_preload: &preload,
-        ld_library_path,
-        ld_cache,
+        ld_library_path: &opts.ld_library_path,
+        ld_cache: if elc.is_musl { &None } else { ld_cache },
         system_dirs,
-        platform: platform.as_ref(),
-        all,
+        extra_dirs: &opts.extra_dirs,
+        expand,
+        all: opts.all,
+        explain: opts.explain.as_ref(),
+        verbose: opts.verbose,
+        audit_rpath: opts.audit_rpath,
+        security: opts.security,
+        show_arch: opts.show_arch,
+        merge_hardlinks: opts.merge_hardlinks,
+        metadata: opts.metadata,
+        warn_shadow: opts.warn_shadow,
+        lint: opts.lint,
+        object_type: opts.object_type,
+        needed_index: opts.needed_index,
+        no_interp: match &elc.interp {
+            Some(interp) if opts.no_interp => Some(pathutils::get_name(&Path::new(interp))),
+            _ => None,
+        },
+        musl_interp: match &elc.interp {
+            Some(interp) if elc.is_musl => Some(pathutils::get_name(&Path::new(interp))),
+            _ => None,
+        },
+        limits: Limits::new(opts),
+        map: &opts.map,
+        on_node: opts.on_node.as_ref(),
     };
 
     let mut deptree = DepTree::new();
 
+    let mut warnings = config.audit_rpath(&elc);
+    warnings.extend(config.lint(arg, &elc));
+    warnings.extend(config.check_interp(&elc));
+
+    // The synthetic executable name can not match one of its dependencies.
+    let (path, name) = match opts.simulate {
+        Some(_) => (None, format!("<{arg}>")),
+        None => (
+            pathutils::get_path(&filename),
+            pathutils::get_name(&filename),
+        ),
+    };
     let depp = deptree.addroot(DepNode {
-        path: pathutils::get_path(&filename),
-        name: pathutils::get_name(&filename),
+        path,
+        name,
         mode: DepMode::Executable,
         found: false,
+        warnings,
+        info: config.root_info(&elc),
+        file_id: config.file_id(&filename),
+        trace: SearchTrace::new(),
     });
+    config.emit(&deptree, depp);
 
-    resolve_binary_arch(&elc, &mut deptree, depp)?;
+    if !opts.no_interp {
+        resolve_binary_arch(&elc, &mut deptree, depp)?;
+        for idx in depp + 1..deptree.arena.len() {
+            config.emit(&deptree, idx);
+        }
+    }
 
     for ld_preload in config.ld_preload {
-        resolve_dependency(&config, &ld_preload.path, &elc, &mut deptree, depp, true);
+        let preload = &ld_preload.path;
+        resolve_dependency(&config, preload, None, &elc, &mut deptree, depp, true)?;
     }
 
     for dep in &elc.deps {
-        resolve_dependency(&config, dep, &elc, &mut deptree, depp, false);
+        let index = Some(dep.index);
+        resolve_dependency(&config, &dep.name, index, &elc, &mut deptree, depp, false)?;
+    }
+
+    for dep in &opts.also {
+        resolve_dependency(&config, dep, None, &elc, &mut deptree, depp, false)?;
+    }
+
+    if !opts.no_interp {
+        let idx = deptree.arena.len();
+        resolve_binary_interp(&config, &elc, &mut deptree, depp);
+        if idx < deptree.arena.len() {
+            config.emit(&deptree, idx);
+        }
+    }
+
+    if opts.check_symbols {
+        symbols::check_symbols(&mut deptree);
+    }
+
+    if opts.unused {
+        let interp = elc
+            .interp
+            .as_ref()
+            .map(|interp| pathutils::get_name(&Path::new(interp)));
+        symbols::check_unused(&mut deptree, opts.deep, interp.as_ref());
+    }
+
+    if opts.warn_conflict {
+        check_conflicts(&mut deptree, &config.expand);
+    }
+
+    if opts.warn_interpose {
+        symbols::check_interpose(&mut deptree);
     }
 
     Ok(deptree)
 }
 
+// The loader only matches an already loaded object by the DT_NEEDED name or the file
+// device and inode, so different DT_NEEDED names (libfoo.so and libfoo.so.1, or an
+// absolute path) might load two copies of the same DT_SONAME, usually two versions of
+// the same library.  Each object with a DT_SONAME (or file name if there is none)
+// shared with other files is annotated with the other paths.
+fn check_conflicts(deptree: &mut DepTree, expand: &Expand) {
+    let node_path = |dep: &DepNode| Path::new(dep.path.as_deref().unwrap_or("")).join(&dep.name);
+
+    let mut sonames = BTreeMap::<String, BTreeMap<(u64, u64), Vec<usize>>>::new();
+    for dep in deptree.arena.iter().skip(1) {
+        if dep.val.found || dep.val.mode == DepMode::NotFound {
+            continue;
+        }
+        let path = node_path(&dep.val);
+        let meta = match fs::metadata(&path) {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        let soname = open_elf_file(&path, None, None, expand, false)
+            .ok()
+            .and_then(|elc| elc.soname)
+            .unwrap_or(dep.val.name.clone());
+        sonames
+            .entry(soname)
+            .or_default()
+            .entry((meta.dev(), meta.ino()))
+            .or_default()
+            .push(dep.idx);
+    }
+
+    for (soname, files) in sonames.iter().filter(|(_, files)| files.len() > 1) {
+        let paths: Vec<String> = files
+            .values()
+            .map(|idxs| node_path(&deptree.arena[idxs[0]].val).display().to_string())
+            .collect();
+        for (i, idxs) in files.values().enumerate() {
+            let others: Vec<&str> = paths
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, path)| path.as_str())
+                .collect();
+            for idx in idxs {
+                deptree.arena[*idx].val.warnings.push(format!(
+                    "DT_SONAME {soname} is also resolved to {}",
+                    others.join(", ")
+                ));
+            }
+        }
+    }
+}
+
+// Build the synthetic executable for the --simulate input NEEDED, a comma separated
+// DT_NEEDED list.  Only the ELF header and the program interpreter of the template object
+// are used.
+fn simulate_elf_file(
+    simulate: &Simulate,
+    needed: &str,
+    expand: &Expand,
+) -> Result<ElfInfo, RlddError> {
+    let like = match &simulate.like {
+        Some(like) => Path::new(like).to_path_buf(),
+        None => std::env::current_exe()?,
+    };
+    let elc = open_elf_file(&like, None, None, expand, false)?;
+
+    let rpath = match &simulate.rpath {
+        Some(rpath) => {
+            let origin = std::env::current_dir()?;
+            let rpath = replace_dyn_str(rpath, "ORIGIN", &origin.to_string_lossy());
+            search_path::from_vec(
+                &rpath
+                    .split(':')
+                    .map(|p| match expand.sysroot {
+                        Some(sysroot) if p.starts_with('/') => sysroot.rebase(p),
+                        _ => p.to_string(),
+                    })
+                    .collect::<Vec<String>>(),
+            )
+        }
+        None => search_path::SearchPathVec::new(),
+    };
+
+    Ok(ElfInfo {
+        ei_class: elc.ei_class,
+        ei_data: elc.ei_data,
+        ei_osabi: elc.ei_osabi,
+        ei_abiver: elc.ei_abiver,
+        e_type: ET_EXEC,
+        e_machine: elc.e_machine,
+        e_flags: elc.e_flags,
+        interp: elc.interp,
+        soname: None,
+        rpath,
+        runpath: search_path::SearchPathVec::new(),
+        nodeflibs: false,
+        is_glibc: elc.is_glibc,
+        is_musl: elc.is_musl,
+        feature_1: 0,
+        relr: false,
+        bind_now: false,
+        textrel: false,
+        execstack: false,
+        flags_1: 0,
+        strsz: 0,
+        deps: needed
+            .split(',')
+            .filter(|d| !d.is_empty())
+            .enumerate()
+            .map(|(index, name)| Needed {
+                name: name.to_string(),
+                index,
+            })
+            .collect(),
+    })
+}
+
 #[cfg(target_os = "linux")]
-fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, _binary: &P, elc: &ElfInfo) {
-    if interp::is_glibc(&elc.interp) {
+fn load_so_cache<P: AsRef<Path>>(
+    ld_cache: &mut Option<LoaderCache>,
+    _binary: &P,
+    elc: &ElfInfo,
+    opts: &ResolveOptions,
+) {
+    if elc.is_glibc {
         // glibc's ld.so.cache is shared between all executables, so there is no need
         // to reload for multiple entries.
+        // With --sysroot both the cache file and its entries are inside the root.
         if ld_cache.is_none() {
+            let cache = match &opts.sysroot {
+                Some(sysroot) => sysroot.cache_path(),
+                None => opts.cache.clone().unwrap_or("/etc/ld.so.cache".to_string()),
+            };
             *ld_cache = ld_so_cache::parse_ld_so_cache(
-                &Path::new("/etc/ld.so.cache"),
+                &Path::new(&cache),
                 elc.ei_class,
                 elc.e_machine,
                 elc.e_flags,
+                !opts.isolated || opts.hwcap.is_some(),
+                opts.hwcap.as_deref(),
             )
-            .ok();
+            .ok()
+            .map(|c| match &opts.sysroot {
+                Some(sysroot) => ld_so_cache::LdCache {
+                    entries: c
+                        .entries
+                        .into_iter()
+                        .map(|(k, v)| (k, sysroot.rebase(&v)))
+                        .collect(),
+                    ..c
+                },
+                None => c,
+            });
         }
     };
 }
 #[cfg(target_os = "android")]
-fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, binary: &P, elc: &ElfInfo) {
-    if let Some(ld_config_path) =
-        ld_config_txt::get_ld_config_path(binary, elc.e_machine, elc.ei_data)
+fn load_so_cache<P: AsRef<Path>>(
+    ld_cache: &mut Option<LoaderCache>,
+    binary: &P,
+    elc: &ElfInfo,
+    opts: &ResolveOptions,
+) {
+    if let Some(ld_config_path) = opts
+        .cache
+        .clone()
+        .or_else(|| ld_config_txt::get_ld_config_path(binary, elc.e_machine, elc.ei_data))
     {
         // On Android 10 and forward each executable might have a associated ld.config.txt
         // file in different paths, so we need to reload for each argument.
@@ -691,118 +1747,342 @@ fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, binary: &P,
     }
 }
 #[cfg(target_os = "freebsd")]
-fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, _binary: &P, _elc: &ElfInfo) {
+fn load_so_cache<P: AsRef<Path>>(
+    ld_cache: &mut Option<LoaderCache>,
+    _binary: &P,
+    _elc: &ElfInfo,
+    opts: &ResolveOptions,
+) {
     if ld_cache.is_none() {
-        *ld_cache =
-            ld_hints_freebsd::parse_ld_so_hints(&Path::new("/var/run/ld-elf.so.hints")).ok();
+        *ld_cache = ld_hints_freebsd::parse_ld_so_hints(&Path::new(
+            opts.cache
+                .as_ref()
+                .map_or("/var/run/ld-elf.so.hints", String::as_str),
+        ))
+        .ok();
     }
 }
 #[cfg(target_os = "openbsd")]
-fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, _binary: &P, _ecl: &ElfInfo) {
+fn load_so_cache<P: AsRef<Path>>(
+    ld_cache: &mut Option<LoaderCache>,
+    _binary: &P,
+    _ecl: &ElfInfo,
+    opts: &ResolveOptions,
+) {
     if ld_cache.is_none() {
-        *ld_cache = ld_hints_openbsd::parse_ld_so_hints(&Path::new("/var/run/ld.so.hints")).ok()
+        *ld_cache = ld_hints_openbsd::parse_ld_so_hints(&Path::new(
+            opts.cache
+                .as_ref()
+                .map_or("/var/run/ld.so.hints", String::as_str),
+        ))
+        .ok()
     }
 }
 #[cfg(target_os = "netbsd")]
-fn load_so_cache<P: AsRef<Path>>(ld_cache: &mut Option<LoaderCache>, _binary: &P, _ecl: &ElfInfo) {
+fn load_so_cache<P: AsRef<Path>>(
+    ld_cache: &mut Option<LoaderCache>,
+    _binary: &P,
+    _ecl: &ElfInfo,
+    opts: &ResolveOptions,
+) {
     if ld_cache.is_none() {
-        *ld_cache = ld_so_conf_netbsd::parse_ld_so_conf(&Path::new("/etc/ld.so.conf")).ok()
+        *ld_cache = ld_so_conf_netbsd::parse_ld_so_conf(&Path::new(
+            opts.cache
+                .as_ref()
+                .map_or("/etc/ld.so.conf", String::as_str),
+        ))
+        .ok()
     }
 }
 #[cfg(any(target_os = "illumos", target_os = "solaris"))]
-fn load_so_cache<P: AsRef<Path>>(_ld_cache: &mut Option<LoaderCache>, _binary: &P, _ecl: &ElfInfo) {
+fn load_so_cache<P: AsRef<Path>>(
+    _ld_cache: &mut Option<LoaderCache>,
+    _binary: &P,
+    _ecl: &ElfInfo,
+    _opts: &ResolveOptions,
+) {
 }
 
 #[cfg(target_os = "linux")]
-fn load_ld_so_preload(interp: &Option<String>) -> search_path::SearchPathVec {
-    if interp::is_glibc(interp) {
-        return ld_preload::parse_ld_so_preload(&Path::new("/etc/ld.so.preload"));
+fn load_ld_so_preload(elc: &ElfInfo, sysroot: Option<&Sysroot>) -> search_path::SearchPathVec {
+    if elc.is_glibc {
+        let rebase = |p: &str| sysroot.map_or(p.to_string(), |s| s.rebase(p));
+        return ld_preload::parse_ld_so_preload(&rebase("/etc/ld.so.preload"), rebase);
     }
     search_path::SearchPathVec::new()
 }
 #[cfg(all(target_family = "unix", not(target_os = "linux")))]
-fn load_ld_so_preload(_interp: &Option<String>) -> search_path::SearchPathVec {
+fn load_ld_so_preload(_elc: &ElfInfo, _sysroot: Option<&Sysroot>) -> search_path::SearchPathVec {
+    search_path::SearchPathVec::new()
+}
+
+#[cfg(target_os = "linux")]
+fn load_musl_path(
+    elc: &ElfInfo,
+    sysroot: Option<&Sysroot>,
+    config: bool,
+) -> search_path::SearchPathVec {
+    let rebase = |p: &str| sysroot.map_or(p.to_string(), |s| s.rebase(p));
+    ld_musl_path::parse_ld_musl_path(elc.interp.as_ref(), config, rebase)
+}
+#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+fn load_musl_path(
+    _elc: &ElfInfo,
+    _sysroot: Option<&Sysroot>,
+    _config: bool,
+) -> search_path::SearchPathVec {
     search_path::SearchPathVec::new()
 }
 
 // Returned from resolve_dependency_1 with resolved information.
 #[derive(Debug)]
-struct ResolvedDependency<'a> {
+struct ResolvedDependency {
     elc: ElfInfo,
-    path: &'a String,
+    path: String,
     mode: DepMode,
 }
 
+// INDEX is the position of DEPENDENCY in the DT_NEEDED list of ELC, if it comes from it.
 fn resolve_dependency(
     config: &Config,
     dependency: &String,
+    index: Option<usize>,
     elc: &ElfInfo,
     deptree: &mut DepTree,
     depp: usize,
     preload: bool,
-) {
-    if elc.is_musl && dependency == "libc.so" {
-        return;
+) -> Result<(), RlddError> {
+    config.limits.check(deptree.arena.len())?;
+    if config.limits.stopped() {
+        return Ok(());
+    }
+
+    if is_musl_loader(config, dependency) {
+        return Ok(());
+    }
+
+    // The interpreter is usually also a DT_NEEDED of libc.
+    if config.no_interp.as_ref() == Some(&pathutils::get_name(&Path::new(dependency))) {
+        return Ok(());
     }
 
     // If DF_1_NODEFLIB is set ignore the search cache in the case a dependency could
     // resolve the library.
     if !elc.nodeflibs {
-        if let Some(entry) = deptree.get(dependency) {
+        // A direct or mapped dependency is added to the tree with its real location.
+        let key = match config.mapped(dependency) {
+            _ if preload => dependency.to_string(),
+            Some(mapping) => config.direct_path(&mapping.path),
+            None => config.direct_path(dependency),
+        };
+        if let Some(entry) = deptree.get(&key) {
             if config.all {
-                deptree.addnode(
+                let name = pathutils::get_name(&Path::new(&key));
+                let loaded = Path::new(entry.path.as_deref().unwrap_or("")).join(&name);
+                let mut info = config.needed_info(index);
+                info.extend(resolved_elsewhere(
+                    config, dependency, elc, preload, &loaded,
+                ));
+                let idx = deptree.addnode(
                     DepNode {
                         path: entry.path,
-                        name: pathutils::get_name(&Path::new(dependency)),
+                        name,
                         mode: entry.mode,
                         found: true,
+                        warnings: Vec::new(),
+                        info,
+                        file_id: None,
+                        trace: SearchTrace::new(),
                     },
                     depp,
                 );
+                config.emit(deptree, idx);
             }
-            return;
+            return Ok(());
         }
     }
 
-    if let Some(mut dep) = resolve_dependency_1(dependency, config, elc, preload) {
-        let r = if dep.mode == DepMode::Direct {
+    let mut trace = SearchTrace::new();
+    if let Some(mut dep) = resolve_dependency_1(dependency, config, elc, preload, &mut trace) {
+        let r = if dep.mode == DepMode::Direct || dep.mode == DepMode::Mapped {
             // Decompose the direct object path in path and filename so when print the dependencies
             // only the file name is showed in default mode.
-            let p = Path::new(dependency);
+            let p = config.direct_path(&dep.path);
+            let p = Path::new(&p);
             (pathutils::get_path(&p), pathutils::get_name(&p))
         } else {
             (Some(dep.path.to_string()), pathutils::get_name(dependency))
         };
+
+        let fullpath = Path::new(r.0.as_deref().unwrap_or("")).join(&r.1);
+        let file_id = config.file_id(&fullpath);
+        if file_id.is_some()
+            && resolve_dependency_hardlink(config, deptree, depp, &r, index, file_id)
+        {
+            return Ok(());
+        }
+
+        let mut warnings = config.audit_rpath(&dep.elc);
+        if !matches!(
+            dep.mode,
+            DepMode::Direct | DepMode::Preload | DepMode::Mapped
+        ) {
+            warnings.extend(config.soname_mismatch(dependency, &dep.elc));
+            warnings.extend(config.shadowed(dependency, elc, &fullpath));
+        }
+
+        let trace = config.keep_trace(&r.1, trace);
         let c = deptree.addnode(
             DepNode {
                 path: r.0,
                 name: r.1,
                 mode: dep.mode,
                 found: false,
+                warnings,
+                info: [config.needed_info(index), config.object_info(&dep.elc)].concat(),
+                file_id,
+                trace,
             },
             depp,
         );
+        config.emit(deptree, c);
 
         // Use parent R_PATH if dependency does not define it.
         if dep.elc.rpath.is_empty() {
             dep.elc.rpath.extend(elc.rpath.clone());
         }
 
+        // Only the preloaded object itself is handled as a preload, its dependencies are
+        // resolved as usual.
         for sdep in &dep.elc.deps {
-            resolve_dependency(config, sdep, &dep.elc, deptree, c, preload);
+            let index = Some(sdep.index);
+            resolve_dependency(config, &sdep.name, index, &dep.elc, deptree, c, false)?;
         }
     } else {
         let path = Path::new(dependency);
-        deptree.addnode(
+        let name = pathutils::get_name(&path);
+        let mut info = config.needed_info(index);
+        info.extend(config.rejected_info(&trace));
+        let idx = deptree.addnode(
             DepNode {
                 path: pathutils::get_path(&path),
-                name: pathutils::get_name(&path),
+                trace: config.keep_trace(&name, trace),
+                name,
                 mode: DepMode::NotFound,
                 found: false,
+                warnings: Vec::new(),
+                info,
+                file_id: None,
+            },
+            depp,
+        );
+        config.limits.not_found();
+        config.emit(deptree, idx);
+    }
+    Ok(())
+}
+
+// The loader reuses the already loaded object LOADED for DEPENDENCY, even if the search
+// paths of ELC would find another one (for instance through a different rpath).  Return
+// the object it would otherwise resolve to, so it is clear which copy wins.
+fn resolved_elsewhere(
+    config: &Config,
+    dependency: &String,
+    elc: &ElfInfo,
+    preload: bool,
+    loaded: &Path,
+) -> Vec<String> {
+    let dep = match resolve_dependency_1(dependency, config, elc, preload, &mut SearchTrace::new())
+    {
+        Some(dep) => dep,
+        None => return Vec::new(),
+    };
+    // The direct and mapped dependencies paths are the file itself.
+    let other = if matches!(dep.mode, DepMode::Direct | DepMode::Mapped) {
+        Path::new(&dep.path).to_path_buf()
+    } else {
+        Path::new(&dep.path).join(pathutils::get_name(&Path::new(dependency)))
+    };
+    let same = match (fs::metadata(&other), fs::metadata(loaded)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    };
+    if same {
+        return Vec::new();
+    }
+    vec![format!(
+        "{{would resolve to {} {}}}",
+        other.display(),
+        dep.mode
+    )]
+}
+
+// If an object with the same device and inode (FILE_ID) as DEP is already in the tree,
+// handle the dependency as already found and annotate the object with the alias path.
+// Return whether it was handled.
+fn resolve_dependency_hardlink(
+    config: &Config,
+    deptree: &mut DepTree,
+    depp: usize,
+    dep: &(Option<String>, String),
+    index: Option<usize>,
+    file_id: Option<(u64, u64)>,
+) -> bool {
+    let (path, name) = dep;
+    let entry = match deptree.arena.iter_mut().find(|n| n.val.file_id == file_id) {
+        Some(entry) => &mut entry.val,
+        None => return false,
+    };
+
+    let alias = format!(
+        "{{alias: {}}}",
+        Path::new(path.as_deref().unwrap_or(""))
+            .join(name)
+            .display()
+    );
+    if !entry.info.contains(&alias) {
+        entry.info.push(alias);
+    }
+
+    if config.all {
+        let mode = entry.mode;
+        let idx = deptree.addnode(
+            DepNode {
+                path: path.clone(),
+                name: name.clone(),
+                mode,
+                found: true,
+                warnings: Vec::new(),
+                info: config.needed_info(index),
+                file_id: None,
+                trace: SearchTrace::new(),
             },
             depp,
         );
+        config.emit(deptree, idx);
     }
+    true
+}
+
+// Open the DTNEEDED candidate at PATH and record the attempt on TRACE.
+fn open_candidate<P: AsRef<Path>>(
+    path: &P,
+    mode: DepMode,
+    melc: &ElfInfo,
+    dtneeded: &String,
+    expand: &Expand,
+    preload: bool,
+    trace: &mut SearchTrace,
+) -> Option<ElfInfo> {
+    let elc = open_elf_file(path, Some(melc), Some(dtneeded), expand, preload);
+    let rejected = elc.as_ref().err().and_then(RlddError::rejection);
+    trace.push(SearchAttempt {
+        path: path.as_ref().to_string_lossy().to_string(),
+        mode,
+        found: elc.is_ok(),
+        rejected,
+    });
+    elc.ok()
 }
 
 fn resolve_dependency_1<'a>(
@@ -810,21 +2090,44 @@ fn resolve_dependency_1<'a>(
     config: &'a Config,
     elc: &'a ElfInfo,
     preload: bool,
-) -> Option<ResolvedDependency<'a>> {
+    trace: &mut SearchTrace,
+) -> Option<ResolvedDependency> {
+    // A --map entry replaces the whole search.  As for a DT_NEEDED with a slash, the file
+    // is only checked to be compatible with the requiring object, not its DT_SONAME.
+    if let Some(mapping) = config.mapped(dtneeded).filter(|_| !preload) {
+        let path = &mapping.path;
+        return open_candidate(
+            path,
+            DepMode::Mapped,
+            elc,
+            path,
+            &config.expand,
+            false,
+            trace,
+        )
+        .map(|elc| ResolvedDependency {
+            elc,
+            path: path.to_string(),
+            mode: DepMode::Mapped,
+        });
+    }
+
     let path = Path::new(&dtneeded);
 
-    // If the path is absolute skip the other modes.
-    if path.is_absolute() {
-        if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), config.platform, preload) {
-            return Some(ResolvedDependency {
-                elc,
-                path: dtneeded,
-                mode: if preload {
-                    DepMode::Preload
-                } else {
-                    DepMode::Direct
-                },
-            });
+    // If the name contains a slash skip the other modes, the loader opens it as-is (so a
+    // relative one is relative to the current directory and not to any search path).
+    // The preloaded libraries are host paths (the ld.so.preload entries are already
+    // relative to the --sysroot).
+    if dtneeded.contains('/') {
+        let (mode, path) = match config.expand.sysroot {
+            _ if preload => (DepMode::Preload, dtneeded.to_string()),
+            Some(sysroot) if path.is_absolute() => (DepMode::Direct, sysroot.rebase(dtneeded)),
+            _ => (DepMode::Direct, dtneeded.to_string()),
+        };
+        if let Some(elc) =
+            open_candidate(&path, mode, elc, dtneeded, &config.expand, preload, trace)
+        {
+            return Some(ResolvedDependency { elc, path, mode });
         }
         return None;
     }
@@ -833,11 +2136,18 @@ fn resolve_dependency_1<'a>(
     if elc.runpath.is_empty() {
         for searchpath in &elc.rpath {
             let path = Path::new(&searchpath.path).join(dtneeded);
-            if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), config.platform, false)
-            {
+            if let Some(elc) = open_candidate(
+                &path,
+                DepMode::DtRpath,
+                elc,
+                dtneeded,
+                &config.expand,
+                false,
+                trace,
+            ) {
                 return Some(ResolvedDependency {
                     elc,
-                    path: &searchpath.path,
+                    path: searchpath.path.clone(),
                     mode: DepMode::DtRpath,
                 });
             }
@@ -847,10 +2157,18 @@ fn resolve_dependency_1<'a>(
     // Check LD_LIBRARY_PATH paths.
     for searchpath in config.ld_library_path {
         let path = Path::new(&searchpath.path).join(dtneeded);
-        if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), config.platform, false) {
+        if let Some(elc) = open_candidate(
+            &path,
+            DepMode::LdLibraryPath,
+            elc,
+            dtneeded,
+            &config.expand,
+            false,
+            trace,
+        ) {
             return Some(ResolvedDependency {
                 elc,
-                path: &searchpath.path,
+                path: searchpath.path.clone(),
                 mode: DepMode::LdLibraryPath,
             });
         }
@@ -859,10 +2177,18 @@ fn resolve_dependency_1<'a>(
     // Check DT_RUNPATH.
     for searchpath in &elc.runpath {
         let path = Path::new(&searchpath.path).join(dtneeded);
-        if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), config.platform, false) {
+        if let Some(elc) = open_candidate(
+            &path,
+            DepMode::DtRunpath,
+            elc,
+            dtneeded,
+            &config.expand,
+            false,
+            trace,
+        ) {
             return Some(ResolvedDependency {
                 elc,
-                path: &searchpath.path,
+                path: searchpath.path.clone(),
                 mode: DepMode::DtRunpath,
             });
         }
@@ -875,7 +2201,9 @@ fn resolve_dependency_1<'a>(
 
     // Check the loader cache.
     if let Some(ld_cache) = config.ld_cache {
-        if let Some(dep) = resolve_dependency_ld_cache(dtneeded, ld_cache, config.platform, elc) {
+        if let Some(dep) =
+            resolve_dependency_ld_cache(dtneeded, ld_cache, &config.expand, elc, trace)
+        {
             return Some(dep);
         }
     }
@@ -883,15 +2211,43 @@ fn resolve_dependency_1<'a>(
     // Finally the system directories.
     for searchpath in &config.system_dirs {
         let path = Path::new(&searchpath.path).join(dtneeded);
-        if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), config.platform, false) {
+        if let Some(elc) = open_candidate(
+            &path,
+            DepMode::SystemDirs,
+            elc,
+            dtneeded,
+            &config.expand,
+            false,
+            trace,
+        ) {
             return Some(ResolvedDependency {
                 elc,
-                path: &searchpath.path,
+                path: searchpath.path.clone(),
                 mode: DepMode::SystemDirs,
             });
         }
     }
 
+    // And the user provided supplement directories, which the loader does not know about.
+    for searchpath in config.extra_dirs {
+        let path = Path::new(&searchpath.path).join(dtneeded);
+        if let Some(elc) = open_candidate(
+            &path,
+            DepMode::ExtraDirs,
+            elc,
+            dtneeded,
+            &config.expand,
+            false,
+            trace,
+        ) {
+            return Some(ResolvedDependency {
+                elc,
+                path: searchpath.path.clone(),
+                mode: DepMode::ExtraDirs,
+            });
+        }
+    }
+
     None
 }
 
@@ -899,18 +2255,27 @@ fn resolve_dependency_1<'a>(
 fn resolve_dependency_ld_cache<'a>(
     dtneeded: &'a String,
     ld_cache: &'a LoaderCache,
-    platform: Option<&String>,
+    expand: &Expand,
     elc: &'a ElfInfo,
-) -> Option<ResolvedDependency<'a>> {
+    trace: &mut SearchTrace,
+) -> Option<ResolvedDependency> {
     use std::path::PathBuf;
-    if let Some(path) = ld_cache.get(dtneeded) {
+    if let Some(path) = ld_cache.entries.get(dtneeded) {
         let mut pathbuf = PathBuf::new();
         pathbuf.push(path);
         pathbuf.push(dtneeded);
-        if let Ok(elc) = open_elf_file(&pathbuf, Some(elc), Some(dtneeded), platform, false) {
+        if let Some(elc) = open_candidate(
+            &pathbuf,
+            DepMode::LdCache,
+            elc,
+            dtneeded,
+            expand,
+            false,
+            trace,
+        ) {
             return Some(ResolvedDependency {
                 elc,
-                path,
+                path: path.clone(),
                 mode: DepMode::LdCache,
             });
         }
@@ -922,27 +2287,30 @@ fn resolve_dependency_ld_cache<'a>(
 fn resolve_dependency_ld_cache<'a>(
     dtneeded: &'a String,
     ld_cache: &'a LoaderCache,
-    platform: Option<&String>,
+    expand: &Expand,
     elc: &'a ElfInfo,
-) -> Option<ResolvedDependency<'a>> {
+    trace: &mut SearchTrace,
+) -> Option<ResolvedDependency> {
     // The constraint function is used to instruct the compiler with a higher-ranked trait
     // bounds (for <...>) that the closure must return a reference of the same lifetime as
     // the argument.  Otherwise it complains that the closure arguments has a different
     // lifetime than result.
     fn constraint<F>(f: F) -> F
     where
-        F: for<'a> Fn(&'a ld_config_txt::NamespaceConfig) -> Option<ResolvedDependency<'a>>,
+        F: for<'a> FnMut(&'a ld_config_txt::NamespaceConfig) -> Option<ResolvedDependency>,
     {
         f
     }
 
-    let search_namespace = constraint(|namespace: &ld_config_txt::NamespaceConfig| {
+    let mut search_namespace = constraint(|namespace: &ld_config_txt::NamespaceConfig| {
         for searchpath in &namespace.search_paths {
             let path = Path::new(&searchpath.path).join(dtneeded);
-            if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), platform, false) {
+            if let Some(elc) =
+                open_candidate(&path, DepMode::LdCache, elc, dtneeded, expand, false, trace)
+            {
                 return Some(ResolvedDependency {
                     elc,
-                    path: &searchpath.path,
+                    path: searchpath.path.clone(),
                     mode: DepMode::LdCache,
                 });
             }
@@ -980,18 +2348,1920 @@ fn resolve_dependency_ld_cache<'a>(
 fn resolve_dependency_ld_cache<'a>(
     dtneeded: &'a String,
     ld_cache: &'a LoaderCache,
-    platform: Option<&String>,
+    expand: &Expand,
     elc: &'a ElfInfo,
-) -> Option<ResolvedDependency<'a>> {
+    trace: &mut SearchTrace,
+) -> Option<ResolvedDependency> {
     for searchpath in ld_cache {
         let path = Path::new(&searchpath.path).join(dtneeded);
-        if let Ok(elc) = open_elf_file(&path, Some(elc), Some(dtneeded), platform, false) {
+        if let Some(elc) =
+            open_candidate(&path, DepMode::LdCache, elc, dtneeded, expand, false, trace)
+        {
             return Some(ResolvedDependency {
                 elc,
-                path: &searchpath.path,
+                path: searchpath.path.clone(),
                 mode: DepMode::LdCache,
             });
         }
     }
     None
 }
+
+// Return the directories from the loader cache that might provide DTNEEDED.
+#[cfg(target_os = "linux")]
+fn ld_cache_dirs(ld_cache: &LoaderCache, dtneeded: &String) -> Vec<String> {
+    ld_cache
+        .entries
+        .get(dtneeded)
+        .cloned()
+        .into_iter()
+        .collect()
+}
+#[cfg(target_os = "android")]
+fn ld_cache_dirs(ld_cache: &LoaderCache, _dtneeded: &String) -> Vec<String> {
+    match ld_cache.get_default_namespace() {
+        Some(namespace) => namespace
+            .search_paths
+            .iter()
+            .map(|s| s.path.clone())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+#[cfg(all(
+    target_family = "unix",
+    not(any(target_os = "linux", target_os = "android"))
+))]
+fn ld_cache_dirs(ld_cache: &LoaderCache, _dtneeded: &String) -> Vec<String> {
+    ld_cache.iter().map(|s| s.path.clone()).collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::io::Error;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    // Build an empty shared object DIR/NAME (with NAME as DT_SONAME) passing ARGS to
+    // the compiler driver, without linking against the C runtime.
+    fn build_library(dir: &Path, name: &str, args: &[&str]) -> Result<PathBuf, std::io::Error> {
+        let soname = format!("-Wl,-soname,{name}");
+        build_object(dir, name, &[&[soname.as_str()], args].concat())
+    }
+
+    // Build a shared library without DT_SONAME.
+    fn build_object(dir: &Path, name: &str, args: &[&str]) -> Result<PathBuf, std::io::Error> {
+        let output = dir.join(name);
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-nostdlib", "-Wl,--no-as-needed", "-o"])
+            .arg(&output)
+            .args(args)
+            .args(["-x", "c", "/dev/null"])
+            .status()?;
+        if !status.success() {
+            return Err(Error::other("failed to build test library"));
+        }
+        Ok(output)
+    }
+
+    // Build an executable without the C runtime (it is never run).
+    fn build_executable(dir: &Path, name: &str, args: &[&str]) -> Result<PathBuf, std::io::Error> {
+        let output = dir.join(name);
+        let status = Command::new("cc")
+            .args(["-nostdlib", "-Wl,-e,0", "-Wl,--no-as-needed", "-o"])
+            .arg(&output)
+            .args(args)
+            .args(["-x", "c", "/dev/null"])
+            .status()?;
+        if !status.success() {
+            return Err(Error::other("failed to build test executable"));
+        }
+        Ok(output)
+    }
+
+    fn resolve_options() -> ResolveOptions {
+        ResolveOptions {
+            ld_library_path: search_path::SearchPathVec::new(),
+            ld_preload: search_path::SearchPathVec::new(),
+            extra_dirs: search_path::SearchPathVec::new(),
+            platform: None,
+            also: Vec::new(),
+            isolated: false,
+            all: false,
+            explain: None,
+            verbose: false,
+            max_nodes: None,
+            timeout: None,
+            first_only: false,
+            audit_rpath: false,
+            security: false,
+            show_arch: false,
+            merge_hardlinks: false,
+            metadata: false,
+            no_canonicalize: false,
+            check_symbols: false,
+            unused: false,
+            deep: false,
+            cache: None,
+            warn_shadow: false,
+            warn_conflict: false,
+            warn_interpose: false,
+            lint: false,
+            object_type: false,
+            needed_index: false,
+            hwcap: None,
+            no_interp: false,
+            simulate: None,
+            libc: None,
+            glibc_interp: Vec::new(),
+            map: Vec::new(),
+            on_node: None,
+            sysroot: None,
+            relaxed_soname: false,
+        }
+    }
+
+    // Write a source file that sets INTERP as the PT_INTERP, which the linker creates from
+    // the .interp section.
+    fn interp_source(dir: &Path, interp: &str) -> Result<PathBuf, std::io::Error> {
+        let output = dir.join("interp.c");
+        fs::write(
+            &output,
+            format!("const char interp[] __attribute__((section(\".interp\"))) = \"{interp}\";\n"),
+        )?;
+        Ok(output)
+    }
+
+    fn find_node<'a>(deptree: &'a DepTree, name: &str) -> Option<&'a DepNode> {
+        deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .find(|n| n.name == name && !n.found)
+    }
+
+    fn check_library_origin(new_dtags: &str, mode: DepMode) -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[new_dtags, "-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+
+        // Resolve from another directory, so $ORIGIN can not be confused with the CWD.
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+
+        let root = &deptree.arena[0].val;
+        assert_eq!(root.name, "libfoo.so");
+        assert_eq!(root.mode, DepMode::Executable);
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, mode);
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+        Ok(())
+    }
+
+    #[test]
+    fn replace_dyn_str_boundary() {
+        assert_eq!(
+            replace_dyn_str("$ORIGIN/lib:${ORIGIN}/x:$ORIGINAL/lib", "ORIGIN", "/opt"),
+            "/opt/lib:/opt/x:$ORIGINAL/lib"
+        );
+        assert_eq!(
+            replace_dyn_str("$ORIGIN_extra:$ORIGIN1:$$ORIGIN", "ORIGIN", "/opt"),
+            "$ORIGIN_extra:$ORIGIN1:$/opt"
+        );
+        assert_eq!(replace_dyn_str("$ORIGIN", "ORIGIN", "/opt"), "/opt");
+        assert_eq!(
+            replace_dyn_str("/usr/$LIB-x", "LIB", "lib64"),
+            "/usr/lib64-x"
+        );
+    }
+
+    #[test]
+    fn library_origin_rpath() -> Result<(), std::io::Error> {
+        check_library_origin("-Wl,--disable-new-dtags", DepMode::DtRpath)
+    }
+
+    #[test]
+    fn library_origin_runpath() -> Result<(), std::io::Error> {
+        check_library_origin("-Wl,--enable-new-dtags", DepMode::DtRunpath)
+    }
+
+    #[test]
+    fn pie_origin_rpath() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let rootdir = tmpdir.path().canonicalize()?;
+        let bindir = rootdir.join("bin");
+        fs::create_dir(&bindir)?;
+        let libdir = rootdir.join("lib");
+        fs::create_dir(&libdir)?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+
+        // A PIE is ET_DYN as the libraries, so $ORIGIN should still be its directory.
+        let prog = build_executable(
+            &bindir,
+            "prog",
+            &[
+                "-pie",
+                "-fPIE",
+                "-Wl,--disable-new-dtags",
+                "-Wl,-rpath,$ORIGIN/../lib",
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), prog.to_str().unwrap())?;
+
+        let root = &deptree.arena[0].val;
+        assert_eq!(root.name, "prog");
+        assert_eq!(root.mode, DepMode::Executable);
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::DtRpath);
+        let path = Path::new(dep.path.as_deref().unwrap()).canonicalize()?;
+        assert_eq!(path, libdir);
+        Ok(())
+    }
+
+    #[test]
+    fn simulate() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        build_library(&libdir, "libbar.so", &[])?;
+
+        let opts = ResolveOptions {
+            simulate: Some(Simulate {
+                like: None,
+                rpath: Some(libdir.to_str().unwrap().to_string()),
+            }),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, "libbar.so,libmissing.so")?;
+
+        let root = &deptree.arena[0].val;
+        assert_eq!(root.name, "<libbar.so,libmissing.so>");
+        assert_eq!(root.path, None);
+        assert_eq!(root.mode, DepMode::Executable);
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::DtRpath);
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+
+        let dep = find_node(&deptree, "libmissing.so").expect("libmissing.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn already_found_elsewhere() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let dir1 = libdir.join("dir1");
+        fs::create_dir(&dir1)?;
+        let dir2 = libdir.join("dir2");
+        fs::create_dir(&dir2)?;
+
+        let libbar1 = build_library(&dir1, "libbar.so", &[])?;
+        let libbar2 = build_library(&dir2, "libbar.so", &[])?;
+        let libbaz = build_library(
+            &libdir,
+            "libbaz.so",
+            &["-Wl,-rpath,$ORIGIN/dir2", libbar2.to_str().unwrap()],
+        )?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN/dir1:$ORIGIN",
+                libbar1.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            all: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let found = deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .find(|n| n.name == "libbar.so" && n.found)
+            .expect("libbar.so not found twice");
+        assert_eq!(found.path.as_deref(), dir1.to_str());
+        assert_eq!(found.info.len(), 1);
+        assert!(found.info[0].contains(libbar2.to_str().unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn library_platform_rpath() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let platdir = libdir.join("testplat");
+        fs::create_dir(&platdir)?;
+
+        let libbar = build_library(&platdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,--disable-new-dtags",
+                "-Wl,-rpath,$ORIGIN/$PLATFORM",
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            platform: Some("testplat".to_string()),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::DtRpath);
+        assert_eq!(dep.path.as_deref(), platdir.to_str());
+        Ok(())
+    }
+
+    #[test]
+    fn dynamic_section_fallback() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+
+        // Remove the program headers by clearing the ELF64 e_phnum, so only the .dynamic
+        // section is available.
+        let mut data = fs::read(&libfoo)?;
+        data[56..58].copy_from_slice(&[0, 0]);
+        fs::write(&libfoo, data)?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+        Ok(())
+    }
+
+    // A library has no PT_INTERP, so the glibc ld.so.cache is used as the fallback.
+    #[test]
+    fn library_ld_cache() -> Result<(), std::io::Error> {
+        // The sbin directories might not be in PATH, and the test is skipped without it.
+        let path = std::env::var("PATH").unwrap_or_default();
+        let ldconfig = match path
+            .split(':')
+            .chain(["/sbin", "/usr/sbin"])
+            .map(|dir| Path::new(dir).join("ldconfig"))
+            .find(|ldconfig| ldconfig.is_file())
+        {
+            Some(ldconfig) => ldconfig,
+            None => {
+                eprintln!("ldconfig not found, skipping");
+                return Ok(());
+            }
+        };
+
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let cachedir = root.join("cached");
+        fs::create_dir(&cachedir)?;
+
+        let libbar = build_library(&cachedir, "libbar.so.1", &[])?;
+        let libfoo = build_library(&root, "libfoo.so", &[libbar.to_str().unwrap()])?;
+        let conf = root.join("ld.so.conf");
+        fs::write(&conf, format!("{}\n", cachedir.to_str().unwrap()))?;
+        let cache = root.join("ld.so.cache");
+        let status = Command::new(ldconfig)
+            .arg("-X")
+            .arg("-C")
+            .arg(&cache)
+            .arg("-f")
+            .arg(&conf)
+            .status()?;
+        if !status.success() {
+            return Err(Error::other("failed to build test cache"));
+        }
+
+        let opts = ResolveOptions {
+            cache: Some(cache.to_string_lossy().to_string()),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::LdCache);
+        assert_eq!(dep.path.as_deref(), cachedir.to_str());
+
+        // The musl loader does not use the cache.
+        let opts = ResolveOptions {
+            libc: Some(Libc::Musl),
+            ..opts
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sysroot() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let optdir = root.join("opt/lib");
+        fs::create_dir_all(&optdir)?;
+        let usrdir = root.join("usr/local/lib64");
+        fs::create_dir_all(&usrdir)?;
+
+        let libbar = build_library(&optdir, "libbar.so", &[])?;
+        let libbaz = build_library(&usrdir, "libbaz.so", &[])?;
+        let libfoo = build_library(
+            &root,
+            "libfoo.so",
+            &[
+                "-Wl,--enable-new-dtags,-rpath,/opt/lib",
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            sysroot: Some(Sysroot {
+                root: root.to_string_lossy().to_string(),
+                cache_path: None,
+                usr_prefix: Some("/usr/local".to_string()),
+            }),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::DtRunpath);
+        assert_eq!(dep.path.as_deref(), optdir.to_str());
+        let dep = find_node(&deptree, "libbaz.so").expect("libbaz.so not in tree");
+        assert_eq!(dep.mode, DepMode::SystemDirs);
+        assert_eq!(dep.path.as_deref(), usrdir.to_str());
+        Ok(())
+    }
+
+    // The PT_INTERP loader is looked up inside the --sysroot and reported when missing.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sysroot_interp() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let libdir = root.join("lib64");
+        fs::create_dir_all(&libdir)?;
+        let exe = build_executable(
+            &root,
+            "exe",
+            &["-pie", "-Wl,--dynamic-linker=/lib64/ld-linux-x86-64.so.2"],
+        )?;
+
+        let opts = ResolveOptions {
+            sysroot: Some(Sysroot {
+                root: root.to_string_lossy().to_string(),
+                cache_path: None,
+                usr_prefix: None,
+            }),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, exe.to_str().unwrap())?;
+        let dep = find_node(&deptree, "ld-linux-x86-64.so.2").expect("loader not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+
+        build_library(&libdir, "ld-linux-x86-64.so.2", &[])?;
+        let deptree = resolve_binary(&mut None, &opts, exe.to_str().unwrap())?;
+        let dep = find_node(&deptree, "ld-linux-x86-64.so.2").expect("loader not in tree");
+        assert_eq!(dep.mode, DepMode::SystemDirs);
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+        Ok(())
+    }
+
+    // The x32 ABI (ELFCLASS32 on x86_64) uses /libx32 both as the system directory and
+    // as the $LIB expansion.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn x32_abi() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let libdir = root.join("libx32");
+        fs::create_dir_all(&libdir)?;
+        let lib64dir = root.join("lib64");
+        fs::create_dir_all(&lib64dir)?;
+        let optdir = root.join("opt/libx32");
+        fs::create_dir_all(&optdir)?;
+
+        let libbar = build_library(&libdir, "libbar.so", &["-mx32"])?;
+        let libbaz = build_library(&lib64dir, "libbaz.so", &["-mx32"])?;
+        let libqux = build_library(&optdir, "libqux.so", &["-mx32"])?;
+        let interpsrc = interp_source(&root, "/libx32/ld-linux-x32.so.2")?;
+        let libfoo = build_library(
+            &root,
+            "libfoo.so",
+            &[
+                "-mx32",
+                "-Wl,--enable-new-dtags,-rpath,/opt/$LIB",
+                interpsrc.to_str().unwrap(),
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+                libqux.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            sysroot: Some(Sysroot {
+                root: root.to_string_lossy().to_string(),
+                cache_path: None,
+                usr_prefix: None,
+            }),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::SystemDirs);
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+        // /lib64 is only searched for the x86_64 ABI.
+        let dep = find_node(&deptree, "libbaz.so").expect("libbaz.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        let dep = find_node(&deptree, "libqux.so").expect("libqux.so not in tree");
+        assert_eq!(dep.mode, DepMode::DtRunpath);
+        assert_eq!(dep.path.as_deref(), optdir.to_str());
+        Ok(())
+    }
+
+    #[test]
+    fn musl_path_file() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let optdir = root.join("opt/lib");
+        fs::create_dir_all(&optdir)?;
+        let libdir = root.join("lib64");
+        fs::create_dir_all(&libdir)?;
+        fs::create_dir_all(root.join("etc"))?;
+
+        // The glibc system directories are not used by musl.
+        let libbar = build_library(&optdir, "libbar.so", &[])?;
+        let libbaz = build_library(&libdir, "libbaz.so", &[])?;
+        let interpsrc = interp_source(&root, "/lib/ld-musl-x86_64.so.1")?;
+        let libfoo = build_library(
+            &root,
+            "libfoo.so",
+            &[
+                interpsrc.to_str().unwrap(),
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+        fs::write(root.join("etc/ld-musl-x86_64.path"), "/opt/lib\n")?;
+
+        let opts = ResolveOptions {
+            sysroot: Some(Sysroot {
+                root: root.to_string_lossy().to_string(),
+                cache_path: None,
+                usr_prefix: None,
+            }),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::SystemDirs);
+        assert_eq!(dep.path.as_deref(), optdir.to_str());
+        let dep = find_node(&deptree, "libbaz.so").expect("libbaz.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn audit_rpath_world_writable() -> Result<(), std::io::Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let unsafedir = libdir.join("unsafe");
+        fs::create_dir(&unsafedir)?;
+        fs::set_permissions(&unsafedir, fs::Permissions::from_mode(0o777))?;
+
+        let rpath = format!("-Wl,-rpath,{}:$ORIGIN", unsafedir.to_str().unwrap());
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,--enable-new-dtags", rpath.as_str()],
+        )?;
+
+        let mut opts = resolve_options();
+        opts.audit_rpath = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let root = &deptree.arena[0].val;
+        assert_eq!(
+            root.warnings,
+            vec![format!(
+                "DT_RUNPATH entry '{}' is world-writable",
+                unsafedir.to_str().unwrap()
+            )]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn security_features_cet() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_library(&libdir, "libfoo.so", &["-fcf-protection=full"])?;
+
+        let mut opts = resolve_options();
+        opts.security = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(deptree.arena[0].val.info, vec!["{CET: IBT,SHSTK}"]);
+        Ok(())
+    }
+
+    #[test]
+    fn align_up_overflow() {
+        assert_eq!(align_up(13, 8), Some(16));
+        assert_eq!(align_up(16, 4), Some(16));
+        assert_eq!(align_up(usize::MAX - 2, 8), None);
+    }
+
+    #[test]
+    fn short_gnu_property() {
+        let property = |datasz: u32, data: &[u8]| {
+            let mut desc = [
+                GNU_PROPERTY_X86_FEATURE_1_AND.to_le_bytes(),
+                datasz.to_le_bytes(),
+            ]
+            .concat();
+            desc.extend_from_slice(data);
+            desc.resize(align_up(desc.len(), 8).unwrap(), 0);
+            desc
+        };
+        let find = |desc: &[u8]| {
+            find_gnu_property(Endianness::Little, desc, 8, GNU_PROPERTY_X86_FEATURE_1_AND)
+        };
+
+        let ibt = GNU_PROPERTY_X86_FEATURE_1_IBT.to_le_bytes();
+        assert_eq!(
+            find(&property(4, &ibt)),
+            Some(GNU_PROPERTY_X86_FEATURE_1_IBT)
+        );
+        // The bitmask is not read past a property too short to hold it.
+        assert_eq!(find(&property(2, &[3, 0])), None);
+    }
+
+    #[test]
+    fn security_execstack() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_library(&libdir, "libfoo.so", &["-Wl,-z,execstack"])?;
+
+        let mut opts = resolve_options();
+        opts.security = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(deptree.arena[0]
+            .val
+            .info
+            .contains(&"{execstack}".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn show_arch_rejected() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+
+        // Change the libbar.so e_machine so it does not match libfoo.so anymore.
+        let mut data = fs::read(&libbar)?;
+        let orig_machine = u16::from_le_bytes([data[18], data[19]]);
+        let e_machine = if orig_machine == EM_AARCH64 {
+            EM_X86_64
+        } else {
+            EM_AARCH64
+        };
+        data[18..20].copy_from_slice(&e_machine.to_le_bytes());
+        fs::write(&libbar, data)?;
+
+        let mut opts = resolve_options();
+        opts.show_arch = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        assert_eq!(
+            dep.info,
+            vec![format!(
+                "{{rejected {}: e_machine mismatch ({} vs {})}}",
+                libbar.to_str().unwrap(),
+                arch::machine_name(e_machine),
+                arch::machine_name(orig_machine)
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn verbose_soname_mismatch() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+        // Replace libbar.so with an object with a different DT_SONAME.
+        build_library(&libdir, "libbaz.so", &[])?;
+        fs::rename(libdir.join("libbaz.so"), &libbar)?;
+
+        let mut opts = resolve_options();
+        opts.verbose = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        let attempt = dep
+            .trace
+            .iter()
+            .find(|t| t.path == libbar.to_str().unwrap())
+            .expect("libbar.so not in search trace");
+        assert_eq!(
+            attempt.rejected.as_deref(),
+            Some("DT_SONAME mismatch (libbaz.so vs libbar.so)")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn verbose_not_elf() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let subdir = libdir.join("sub");
+        fs::create_dir(&subdir)?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,--disable-new-dtags,-rpath,$ORIGIN/sub:$ORIGIN",
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+        // Replace libbar.so with a linker script, which the loader can not use.
+        fs::write(&libbar, "INPUT(-lbar)\n")?;
+
+        let mut opts = resolve_options();
+        opts.verbose = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        let attempt = |path: &Path| {
+            dep.trace
+                .iter()
+                .find(|t| t.path == path.to_str().unwrap())
+                .expect("path not in search trace")
+        };
+        // The search location without the file is not a rejection.
+        assert_eq!(attempt(&subdir.join("libbar.so")).rejected, None);
+        assert!(attempt(&libbar)
+            .rejected
+            .as_deref()
+            .is_some_and(|r| r.starts_with("not a valid object")));
+        Ok(())
+    }
+
+    #[test]
+    fn relaxed_soname() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_library(&libdir, "libbar.so.1", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+        // Replace libbar.so.1 with an object with a more specific DT_SONAME.
+        build_library(&libdir, "libbar.so.1.2", &[])?;
+        fs::rename(libdir.join("libbar.so.1.2"), &libbar)?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+
+        let opts = ResolveOptions {
+            relaxed_soname: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::DtRunpath);
+        assert_eq!(
+            dep.warnings,
+            vec!["DT_SONAME libbar.so.1.2 does not match DT_NEEDED libbar.so.1"]
+        );
+
+        assert!(is_soname_version_of("libbar.so.1", "libbar.so.1.2.3"));
+        assert!(!is_soname_version_of("libbar.so.1", "libbar.so.1"));
+        assert!(!is_soname_version_of("libbar.so.1", "libbar.so.10"));
+        assert!(!is_soname_version_of("libbar.so.1", "libbar.so.1.x"));
+        assert!(!is_soname_version_of("libbar.so.1.2", "libbar.so.1"));
+        Ok(())
+    }
+
+    #[test]
+    fn preload_dependencies() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        // Without a DT_SONAME the linker records the absolute path as DT_NEEDED.
+        let libbar = build_object(&libdir, "libbar.so", &[])?;
+
+        let libpre = build_library(&libdir, "libpre.so", &[libbar.to_str().unwrap()])?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[])?;
+
+        let mut opts = resolve_options();
+        opts.ld_preload = search_path::from_preload(libpre.to_str().unwrap());
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libpre.so").expect("libpre.so not in tree");
+        assert_eq!(dep.mode, DepMode::Preload);
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::Direct);
+        Ok(())
+    }
+
+    #[test]
+    fn needed_symlink_path() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let realdir = libdir.join("real");
+        fs::create_dir(&realdir)?;
+        let linkdir = libdir.join("link");
+        fs::create_dir(&linkdir)?;
+
+        // Without a DT_SONAME the linker records the symlink path as DT_NEEDED.
+        let libbar = build_object(&realdir, "libbar.so.1", &[])?;
+        let link = linkdir.join("libbar.so");
+        std::os::unix::fs::symlink(&libbar, &link)?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[link.to_str().unwrap()])?;
+        let libbaz = build_library(&libdir, "libbaz.so", &[link.to_str().unwrap()])?;
+        let libqux = build_library(
+            &libdir,
+            "libqux.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libfoo.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            all: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libqux.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::Direct);
+        assert_eq!(dep.path.as_deref(), realdir.to_str());
+
+        // The other DT_NEEDED for the symlink is the same object.
+        let found = deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .filter(|n| n.name == "libbar.so.1")
+            .collect::<Vec<_>>();
+        assert_eq!(found.len(), 2);
+        assert!(found[1].found);
+        assert_eq!(found[1].path.as_deref(), realdir.to_str());
+        Ok(())
+    }
+
+    #[test]
+    fn needed_relative_path() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let subdir = libdir.join("subdir");
+        fs::create_dir(&subdir)?;
+
+        // The DT_SONAME with a slash is recorded as the DT_NEEDED, which is only looked up
+        // relative to the current directory even though the rpath also provides it.
+        let libbar = build_object(&subdir, "libbar.so", &["-Wl,-soname,subdir/libbar.so"])?;
+        // And the same library named relative to the current directory.
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let relative = Path::new(&"../".repeat(cwd.components().count() - 1))
+            .join(libdir.strip_prefix("/").unwrap())
+            .join("subdir/libbaz.so");
+        let libbaz = build_object(
+            &subdir,
+            "libbaz.so",
+            &[&format!("-Wl,-soname,{}", relative.display())],
+        )?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+        let dep = find_node(&deptree, "libbaz.so").expect("libbaz.so not in tree");
+        assert_eq!(dep.mode, DepMode::Direct);
+        Ok(())
+    }
+
+    #[test]
+    fn no_interp() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libld = build_library(&libdir, "ld-test.so.1", &[])?;
+        let interpsrc = interp_source(&libdir, libld.to_str().unwrap())?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                interpsrc.to_str().unwrap(),
+                libld.to_str().unwrap(),
+            ],
+        )?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(find_node(&deptree, "ld-test.so.1").is_some());
+
+        let opts = ResolveOptions {
+            no_interp: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(find_node(&deptree, "ld-test.so.1").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn interp_mismatch() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libld = build_library(&libdir, "ld-test.so.1", &[])?;
+        let interpsrc = interp_source(&libdir, libld.to_str().unwrap())?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[interpsrc.to_str().unwrap()])?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+
+        // Change the loader e_machine so it can not run libfoo.so anymore.
+        let mut data = fs::read(&libld)?;
+        let orig_machine = u16::from_le_bytes([data[18], data[19]]);
+        let e_machine = if orig_machine == EM_AARCH64 {
+            EM_X86_64
+        } else {
+            EM_AARCH64
+        };
+        data[18..20].copy_from_slice(&e_machine.to_le_bytes());
+        fs::write(&libld, data)?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec![format!(
+                "program interpreter {} can not be used: e_machine mismatch ({} vs {})",
+                libld.to_str().unwrap(),
+                arch::machine_name(e_machine),
+                arch::machine_name(orig_machine)
+            )]
+        );
+
+        fs::remove_file(&libld)?;
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec![format!(
+                "program interpreter {} not found",
+                libld.to_str().unwrap()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn musl_libc_needed() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        // A libc link name other than libc.so is still provided by the loader, even if the
+        // rpath also has it.
+        let libc = build_library(&libdir, "libc.musl-x86_64.so.1", &[])?;
+        let interpsrc = interp_source(&libdir, "/lib/ld-musl-x86_64.so.1")?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                interpsrc.to_str().unwrap(),
+                libc.to_str().unwrap(),
+            ],
+        )?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        // The synthetic loader node is always shown as already found.
+        assert!(deptree.contains("/lib/ld-musl-x86_64.so.1"));
+        assert!(!deptree.contains("libc.musl-x86_64.so.1"));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_binding() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libnow = build_library(&libdir, "libnow.so", &["-Wl,-z,now"])?;
+        let liblazy = build_library(&libdir, "liblazy.so", &["-Wl,-z,lazy"])?;
+
+        let opts = ResolveOptions {
+            metadata: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libnow.to_str().unwrap())?;
+        assert!(deptree.arena[0]
+            .val
+            .info
+            .contains(&"{binding: now}".to_string()));
+        let deptree = resolve_binary(&mut None, &opts, liblazy.to_str().unwrap())?;
+        assert!(deptree.arena[0]
+            .val
+            .info
+            .contains(&"{binding: lazy}".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_flags_1() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-z,nodelete",
+                "-Wl,-z,global",
+                "-Wl,-z,initfirst",
+                "-Wl,-z,interpose",
+            ],
+        )?;
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+
+        let opts = ResolveOptions {
+            metadata: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let info = &deptree.arena[0].val.info;
+        for flag in [
+            "{DF_1_NODELETE}",
+            "{DF_1_GLOBAL}",
+            "{DF_1_INITFIRST}",
+            "{DF_1_INTERPOSE}",
+        ] {
+            assert!(info.contains(&flag.to_string()));
+        }
+        let deptree = resolve_binary(&mut None, &opts, libbar.to_str().unwrap())?;
+        assert!(!deptree.arena[0]
+            .val
+            .info
+            .iter()
+            .any(|i| i.starts_with("{DF_1_")));
+        Ok(())
+    }
+
+    #[test]
+    fn core_file() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[])?;
+
+        // Turn it into an ET_CORE file, the e_type is at the same offset for both classes.
+        let core = libdir.join("core");
+        let mut data = fs::read(&libfoo)?;
+        data[16..18].copy_from_slice(&ET_CORE.to_ne_bytes());
+        fs::write(&core, data)?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), core.to_str().unwrap())?;
+        assert_eq!(deptree.arena.len(), 1);
+        assert_eq!(
+            deptree.arena[0].val.info,
+            vec!["{core file, no dependencies}"]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn corrupted_dynstr() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[])?;
+        let data = fs::read(&libfoo)?;
+
+        // Rewrite the TAG value of the ELF64 PT_DYNAMIC entries.
+        let corrupt = |tag: u32, value: u64| -> Result<PathBuf, std::io::Error> {
+            let mut data = data.clone();
+            let elf = FileHeader64::<Endianness>::parse(&*data).unwrap();
+            let endian = elf.endian().unwrap();
+            let offset = elf
+                .program_headers(endian, &*data)
+                .unwrap()
+                .iter()
+                .find(|hdr| hdr.p_type(endian) == PT_DYNAMIC)
+                .map(|hdr| hdr.p_offset(endian) as usize)
+                .unwrap();
+            for entry in data[offset..].chunks_exact_mut(16) {
+                match u64::from_ne_bytes(entry[..8].try_into().unwrap()) {
+                    t if t == u64::from(DT_NULL) => break,
+                    t if t == u64::from(tag) => entry[8..].copy_from_slice(&value.to_ne_bytes()),
+                    _ => {}
+                }
+            }
+            let output = libdir.join(format!("libfoo-{tag}.so"));
+            fs::write(&output, data)?;
+            Ok(output)
+        };
+        let error = |path: PathBuf| match resolve_binary(
+            &mut None,
+            &resolve_options(),
+            path.to_str().unwrap(),
+        ) {
+            Err(RlddError::Parse(e)) => e,
+            _ => panic!("{} should not be parsed", path.display()),
+        };
+
+        let e = error(corrupt(DT_STRSZ, 1 << 40)?);
+        assert!(e.contains("DT_STRSZ 1099511627776: past the end of its segment"));
+        let e = error(corrupt(DT_STRTAB, 0xdead0000)?);
+        assert!(e.contains("DT_STRTAB 0xdead0000"));
+        assert!(e.contains("not mapped by any segment"));
+
+        // The size is shown with --metadata, only in verbose mode.
+        let mut opts = ResolveOptions {
+            metadata: true,
+            ..resolve_options()
+        };
+        let has_dynstr = |opts: &ResolveOptions| -> Result<bool, std::io::Error> {
+            let deptree = resolve_binary(&mut None, opts, libfoo.to_str().unwrap())?;
+            Ok(deptree.arena[0]
+                .val
+                .info
+                .iter()
+                .any(|i| i.starts_with("{dynstr: ") && i.ends_with(" bytes}")))
+        };
+        assert!(!has_dynstr(&opts)?);
+        opts.verbose = true;
+        assert!(has_dynstr(&opts)?);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_hardlinks() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        // Without a DT_SONAME the linker records the file name for libraries found with -l.
+        let libbar = build_object(&libdir, "libbar.so", &[])?;
+        let libbaz = libdir.join("libbaz.so");
+        fs::hard_link(&libbar, &libbaz)?;
+        let libdir_arg = format!("-L{}", libdir.to_str().unwrap());
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", &libdir_arg, "-lbar", "-lbaz"],
+        )?;
+
+        let mut opts = resolve_options();
+        opts.merge_hardlinks = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(
+            dep.info,
+            vec![format!("{{alias: {}}}", libbaz.to_str().unwrap())]
+        );
+        assert!(find_node(&deptree, "libbaz.so").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn no_canonicalize_origin() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?.join("lib");
+        let linkdir = tmpdir.path().canonicalize()?.join("link");
+        fs::create_dir(&libdir)?;
+        fs::create_dir(&linkdir)?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        build_library(&linkdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+        let symlink = linkdir.join("libfoo.so");
+        std::os::unix::fs::symlink(&libfoo, &symlink)?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), symlink.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+
+        let mut opts = resolve_options();
+        opts.no_canonicalize = true;
+        let deptree = resolve_binary(&mut None, &opts, symlink.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.path.as_deref(), linkdir.to_str());
+        Ok(())
+    }
+
+    #[test]
+    fn warn_shadow() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?.join("lib");
+        let sysdir = tmpdir.path().canonicalize()?.join("sys");
+        fs::create_dir(&libdir)?;
+        fs::create_dir(&sysdir)?;
+
+        let libbar = build_library(&libdir, "libbar.so", &[])?;
+        let shadowed = build_library(&sysdir, "libbar.so", &[])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &["-Wl,-rpath,$ORIGIN", libbar.to_str().unwrap()],
+        )?;
+
+        let mut opts = resolve_options();
+        opts.warn_shadow = true;
+        opts.extra_dirs = search_path::from_vec(&[sysdir.to_str().unwrap().to_string()]);
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.path.as_deref(), libdir.to_str());
+        assert_eq!(
+            dep.warnings,
+            vec![format!("shadows {}", shadowed.to_str().unwrap())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn warn_conflict() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        let (adir, bdir) = (root.join("a"), root.join("b"));
+        fs::create_dir(&adir)?;
+        fs::create_dir(&bdir)?;
+
+        // libbaz.so loads b/libbar.so.1 (through the DT_RPATH of libfoo.so), and the
+        // absolute path DT_NEEDED (from linking against an object without DT_SONAME)
+        // loads another libbar.so.1 DT_SONAME.
+        let libbar = build_library(&bdir, "libbar.so.1", &[])?;
+        let libbaz = build_library(&bdir, "libbaz.so", &[libbar.to_str().unwrap()])?;
+        let direct = build_object(&adir, "libbar.so.1", &[])?;
+        let libfoo = build_library(
+            &root,
+            "libfoo.so",
+            &[
+                "-Wl,--disable-new-dtags,-rpath,$ORIGIN/b",
+                libbaz.to_str().unwrap(),
+                direct.to_str().unwrap(),
+            ],
+        )?;
+        build_library(&adir, "libbar.so.1", &[])?;
+
+        let opts = ResolveOptions {
+            warn_conflict: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        let nodes: Vec<&DepNode> = deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .filter(|n| n.name == "libbar.so.1")
+            .collect();
+        assert_eq!(nodes.len(), 2);
+        for (node, other) in [(nodes[0], &direct), (nodes[1], &libbar)] {
+            assert_eq!(
+                node.warnings,
+                vec![format!(
+                    "DT_SONAME libbar.so.1 is also resolved to {}",
+                    other.to_str().unwrap()
+                )]
+            );
+        }
+        let dep = find_node(&deptree, "libbaz.so").expect("libbaz.so not in tree");
+        assert!(dep.warnings.is_empty());
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(deptree.arena.iter().all(|n| n.val.warnings.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn events() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbaz = build_library(&libdir, "libbaz.so", &[])?;
+        let libbar = build_library(
+            &libdir,
+            "libbar.so",
+            &["-Wl,-rpath,$ORIGIN", libbaz.to_str().unwrap()],
+        )?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let hook_events = std::rc::Rc::clone(&events);
+        let opts = ResolveOptions {
+            all: true,
+            on_node: Some(Box::new(move |node, parent| {
+                hook_events.borrow_mut().push((
+                    parent.map(|p| p.name.clone()),
+                    node.name.clone(),
+                    node.found,
+                ))
+            })),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+
+        // Every node is reported once, in the order it is added to the tree.
+        let expected: Vec<(Option<String>, String, bool)> = deptree
+            .arena
+            .iter()
+            .map(|n| {
+                (
+                    n.parent.map(|p| deptree.arena[p].val.name.clone()),
+                    n.val.name.clone(),
+                    n.val.found,
+                )
+            })
+            .collect();
+        assert_eq!(*events.borrow(), expected);
+        let events = events.borrow();
+        assert_eq!(events[0], (None, "libfoo.so".to_string(), false));
+        assert!(events.contains(&(
+            Some("libbar.so".to_string()),
+            "libbaz.so".to_string(),
+            false
+        )));
+        assert!(events.contains(&(Some("libfoo.so".to_string()), "libbaz.so".to_string(), true)));
+        Ok(())
+    }
+
+    #[test]
+    fn map_soname() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let subdir = libdir.join("sub");
+        fs::create_dir(&subdir)?;
+
+        // Both libfoo.so and libbaz.so require libbar.so.1, which is found through the
+        // DT_RUNPATH unless it is mapped.
+        let libbar = build_library(&libdir, "libbar.so.1", &[])?;
+        let libbaz = build_library(&libdir, "libbaz.so", &[libbar.to_str().unwrap()])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+        // The mapped file does not need to match the DT_SONAME.
+        let patched = build_object(&subdir, "libbar-patched.so", &[])?;
+
+        let opts = ResolveOptions {
+            all: true,
+            map: vec![format!("libbar.so.1={}", patched.display())
+                .parse()
+                .unwrap()],
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar-patched.so").expect("mapped file not in tree");
+        assert_eq!(dep.mode, DepMode::Mapped);
+        assert_eq!(dep.path.as_deref(), subdir.to_str());
+        // The second DT_NEEDED reuses the mapped object.
+        let nodes: Vec<&DepNode> = deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .filter(|n| n.name == "libbar-patched.so")
+            .collect();
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes[1].found);
+        // The mapped path is the file itself, so it is not reported as resolving elsewhere.
+        assert!(nodes[1].info.is_empty());
+        assert!(find_node(&deptree, "libbar.so.1").is_none());
+
+        // A missing mapped file is not searched elsewhere.
+        let opts = ResolveOptions {
+            map: vec!["libbar.so.1=/nonexistent/libbar.so.1".parse().unwrap()],
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let dep = find_node(&deptree, "libbar.so.1").expect("libbar.so.1 not in tree");
+        assert_eq!(dep.mode, DepMode::NotFound);
+
+        assert!("libbar.so.1".parse::<Mapping>().is_err());
+        assert!("=/lib/libbar.so.1".parse::<Mapping>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn needed_index() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbaz = build_library(&libdir, "libbaz.so", &[])?;
+        let libbar = build_library(&libdir, "libbar.so", &[libbaz.to_str().unwrap()])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libbar.to_str().unwrap(),
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            all: true,
+            needed_index: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        let index = |parent: &str, name: &str| {
+            deptree
+                .arena
+                .iter()
+                .find(|n| {
+                    n.val.name == name
+                        && n.parent.map(|p| deptree.arena[p].val.name.as_str()) == Some(parent)
+                })
+                .and_then(|n| n.val.info.first().cloned())
+        };
+        assert_eq!(
+            index("libfoo.so", "libbar.so"),
+            Some("{needed: 0}".to_string())
+        );
+        assert_eq!(
+            index("libfoo.so", "libbaz.so"),
+            Some("{needed: 1}".to_string())
+        );
+        assert_eq!(
+            index("libbar.so", "libbaz.so"),
+            Some("{needed: 0}".to_string())
+        );
+        assert!(deptree.arena[0].val.info.is_empty());
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(deptree.arena.iter().all(|n| n.val.info.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn libc_override() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let subdir = libdir.join("sub");
+        fs::create_dir(&subdir)?;
+
+        // A renamed loader is neither detected as glibc nor as musl.
+        let libld = build_library(&libdir, "ld-renamed.so", &[])?;
+        let interpsrc = interp_source(&libdir, libld.to_str().unwrap())?;
+        let libc = build_object(&subdir, "libc.so.6", &["-Wl,-soname,libc.so.6"])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[interpsrc.to_str().unwrap(), libc.to_str().unwrap()],
+        )?;
+        let has_node = |deptree: &DepTree, name: &str| {
+            deptree.arena.iter().skip(1).any(|n| n.val.name == name)
+        };
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(!has_node(&deptree, "ld-renamed.so"));
+        assert_eq!(
+            find_node(&deptree, "libc.so.6").map(|n| n.mode),
+            Some(DepMode::NotFound)
+        );
+
+        // glibc lists the loader from PT_INTERP.
+        let opts = ResolveOptions {
+            libc: Some(Libc::Glibc),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(has_node(&deptree, "ld-renamed.so"));
+
+        // The same with the renamed loader added to the glibc ones.
+        let opts = ResolveOptions {
+            glibc_interp: vec!["ld-renamed.so".to_string()],
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(has_node(&deptree, "ld-renamed.so"));
+
+        // The musl loader provides libc itself.
+        let opts = ResolveOptions {
+            libc: Some(Libc::Musl),
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(has_node(&deptree, "ld-renamed.so"));
+        assert!(!has_node(&deptree, "libc.so.6"));
+        Ok(())
+    }
+
+    #[test]
+    fn first_only() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let subdir = libdir.join("sub");
+        fs::create_dir(&subdir)?;
+
+        let libmissing = build_object(&subdir, "libmissing.so", &["-Wl,-soname,libmissing.so"])?;
+        let libbar = build_object(&libdir, "libbar.so", &["-Wl,-soname,libbar.so"])?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libmissing.to_str().unwrap(),
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(find_node(&deptree, "libbar.so").is_some());
+
+        let opts = ResolveOptions {
+            first_only: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(
+            find_node(&deptree, "libmissing.so").map(|n| n.mode),
+            Some(DepMode::NotFound)
+        );
+        assert!(find_node(&deptree, "libbar.so").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn object_type() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libbar = build_object(&libdir, "libbar.so", &[])?;
+
+        let libbar_path = libbar.to_str().unwrap();
+        let pie = build_executable(&libdir, "pie", &["-pie", "-fPIE", libbar_path])?;
+        let nopie = build_executable(&libdir, "nopie", &["-no-pie", libbar_path])?;
+
+        let opts = ResolveOptions {
+            object_type: true,
+            ..resolve_options()
+        };
+        for (object, kind) in [
+            (&pie, "{executable (PIE)}"),
+            (&nopie, "{executable (no-PIE)}"),
+            (&libbar, "{shared library}"),
+        ] {
+            let deptree = resolve_binary(&mut None, &opts, object.to_str().unwrap())?;
+            assert_eq!(deptree.arena[0].val.info, vec![kind]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lint_soname_mismatch() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let libfoo = build_object(&libdir, "libfoo.so", &["-Wl,-soname,libfoo.so.2"])?;
+        let libbar = build_object(&libdir, "libbar.so.2.1", &["-Wl,-soname,libbar.so.2"])?;
+
+        let mut opts = resolve_options();
+        opts.lint = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec!["file name does not match DT_SONAME libfoo.so.2"]
+        );
+        let deptree = resolve_binary(&mut None, &opts, libbar.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn lint_needed_path() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        // Without a DT_SONAME the linker records the library path as the DT_NEEDED.
+        let libbar = build_object(&libdir, "libbar.so", &[])?;
+        let libfoo = build_library(&libdir, "libfoo.so", &[libbar.to_str().unwrap()])?;
+
+        let mut opts = resolve_options();
+        opts.lint = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec![format!(
+                "DT_NEEDED {} is an absolute path",
+                libbar.display()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lint_rpath() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+        let librpath = build_object(
+            &libdir,
+            "librpath.so",
+            &["-Wl,--disable-new-dtags", "-Wl,-rpath,$ORIGIN"],
+        )?;
+        let librunpath = build_object(
+            &libdir,
+            "librunpath.so",
+            &["-Wl,--enable-new-dtags", "-Wl,-rpath,$ORIGIN"],
+        )?;
+
+        let mut opts = resolve_options();
+        opts.lint = true;
+        let deptree = resolve_binary(&mut None, &opts, librpath.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec![
+                "uses the deprecated DT_RPATH, which also applies to the dependencies \
+                (link with --enable-new-dtags for DT_RUNPATH)"
+            ]
+        );
+        let deptree = resolve_binary(&mut None, &opts, librunpath.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn check_symbols_hidden() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let barsrc = libdir.join("bar.c");
+        fs::write(
+            &barsrc,
+            "__attribute__((visibility(\"hidden\"))) void bar(void) {}\n",
+        )?;
+        let libbar = build_library(&libdir, "libbar.so", &[barsrc.to_str().unwrap()])?;
+
+        let foosrc = libdir.join("foo.c");
+        fs::write(&foosrc, "void bar(void); void foo(void) { bar(); }\n")?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                foosrc.to_str().unwrap(),
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+
+        let mut opts = resolve_options();
+        opts.check_symbols = true;
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec!["symbol bar is present in libbar.so but not exported"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn warn_interpose() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let library = |name: &str, code: &str, args: &[&str]| {
+            let source = libdir.join(format!("{name}.c"));
+            fs::write(&source, code)?;
+            let mut args = args.to_vec();
+            args.insert(0, source.to_str().unwrap());
+            build_library(&libdir, name, &args)
+        };
+
+        // liba.so and libb.so both define 'dup', and libweak.so has a weak 'weak' which is
+        // loaded before the libstrong.so one.  'single' is only defined by libb.so.
+        let liba = library("liba.so", "void dup(void) {}\n", &[])?;
+        let libb = library("libb.so", "void dup(void) {}\nvoid single(void) {}\n", &[])?;
+        let libweak = library(
+            "libweak.so",
+            "__attribute__((weak)) void weak(void) {}\n",
+            &[],
+        )?;
+        let libstrong = library("libstrong.so", "void weak(void) {}\n", &[])?;
+        let libfoo = library(
+            "libfoo.so",
+            "void dup(void); void single(void); void weak(void);\n\
+             void foo(void) { dup(); single(); weak(); }\n",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                liba.to_str().unwrap(),
+                libb.to_str().unwrap(),
+                libweak.to_str().unwrap(),
+                libstrong.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            all: true,
+            warn_interpose: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert_eq!(
+            deptree.arena[0].val.warnings,
+            vec![
+                "symbol dup binds to liba.so, also exported by libb.so",
+                "symbol weak binds to the weak definition in libweak.so, not to the one in libstrong.so",
+            ]
+        );
+
+        let deptree = resolve_binary(&mut None, &resolve_options(), libfoo.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+
+        // libv1.so has 'ver' only as the hidden V1 version, so the libbar.so reference
+        // to ver@@V2 does not bind to it.
+        let script = |name: &str, version: &str| -> Result<String, std::io::Error> {
+            let path = libdir.join(name);
+            fs::write(&path, format!("{version} {{ ver; }};\n"))?;
+            Ok(format!("-Wl,--version-script={}", path.display()))
+        };
+        let v1 = script("v1.map", "V1")?;
+        let v2 = script("v2.map", "V2")?;
+        let libv1 = library(
+            "libv1.so",
+            "void ver_v1(void) {}\n__asm__(\".symver ver_v1, ver@V1\");\n",
+            &[&v1],
+        )?;
+        let libv2 = library("libv2.so", "void ver(void) {}\n", &[&v2])?;
+        let libbar = library(
+            "libbar.so",
+            "void ver(void);\nvoid bar(void) { ver(); }\n",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                libv1.to_str().unwrap(),
+                libv2.to_str().unwrap(),
+            ],
+        )?;
+        let deptree = resolve_binary(&mut None, &opts, libbar.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+
+        // The executable has a copy of 'data' (through a R_*_COPY relocation), which the
+        // libuser.so reference binds to instead of the libdata.so definition.
+        let libdata = library("libdata.so", "int data = 1;\n", &[])?;
+        let libuser = library(
+            "libuser.so",
+            "extern int data;\nint user(void) { return data; }\n",
+            &["-Wl,-rpath,$ORIGIN", libdata.to_str().unwrap()],
+        )?;
+        let source = libdir.join("exe.c");
+        fs::write(
+            &source,
+            "extern int data;\nint main(void) { return data; }\n",
+        )?;
+        let exe = build_executable(
+            &libdir,
+            "exe",
+            &[
+                "-no-pie",
+                "-fno-pic",
+                "-Wl,-rpath,$ORIGIN",
+                source.to_str().unwrap(),
+                libuser.to_str().unwrap(),
+                libdata.to_str().unwrap(),
+            ],
+        )?;
+        let deptree = resolve_binary(&mut None, &opts, exe.to_str().unwrap())?;
+        assert!(deptree.arena.iter().all(|d| d.val.warnings.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn unused_deep() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let source = |name: &str, code: &str| -> Result<String, std::io::Error> {
+            let path = libdir.join(name);
+            fs::write(&path, code)?;
+            Ok(path.to_str().unwrap().to_string())
+        };
+
+        // libbaz.so is only needed by libqux.so, which libbar.so links without using it.
+        let libbaz = build_library(
+            &libdir,
+            "libbaz.so",
+            &[&source("baz.c", "void baz(void) {}\n")?],
+        )?;
+        let libqux = build_library(
+            &libdir,
+            "libqux.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                &source("qux.c", "void baz(void); void qux(void) { baz(); }\n")?,
+                libbaz.to_str().unwrap(),
+            ],
+        )?;
+        let libbar = build_library(
+            &libdir,
+            "libbar.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                &source("bar.c", "void bar(void) {}\n")?,
+                libqux.to_str().unwrap(),
+            ],
+        )?;
+        let libfoo = build_library(
+            &libdir,
+            "libfoo.so",
+            &[
+                "-Wl,-rpath,$ORIGIN",
+                &source("foo.c", "void bar(void); void foo(void) { bar(); }\n")?,
+                libbar.to_str().unwrap(),
+            ],
+        )?;
+
+        let opts = ResolveOptions {
+            all: true,
+            unused: true,
+            ..resolve_options()
+        };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        assert!(deptree.arena[0].val.warnings.is_empty());
+        let dep = find_node(&deptree, "libbar.so").expect("libbar.so not in tree");
+        assert_eq!(dep.warnings, vec!["DT_NEEDED libqux.so is unused"]);
+        let dep = find_node(&deptree, "libqux.so").expect("libqux.so not in tree");
+        assert!(dep.warnings.is_empty());
+
+        let opts = ResolveOptions { deep: true, ..opts };
+        let deptree = resolve_binary(&mut None, &opts, libfoo.to_str().unwrap())?;
+        for name in ["libqux.so", "libbaz.so"] {
+            let dep = find_node(&deptree, name).expect("library not in tree");
+            assert_eq!(
+                dep.warnings,
+                vec!["only loaded through unused DT_NEEDED entries"]
+            );
+        }
+        Ok(())
+    }
+}
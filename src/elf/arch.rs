@@ -0,0 +1,63 @@
+// Maps the ELF identification fields to readable names, used to report the object
+// architecture.
+
+use object::elf::*;
+
+pub fn class_name(ei_class: u8) -> String {
+    match ei_class {
+        ELFCLASS32 => "ELF32".to_string(),
+        ELFCLASS64 => "ELF64".to_string(),
+        _ => format!("ELFCLASS({ei_class})"),
+    }
+}
+
+pub fn machine_name(e_machine: u16) -> String {
+    let r = match e_machine {
+        EM_386 => "i386",
+        EM_X86_64 => "x86-64",
+        EM_ARM => "ARM",
+        EM_AARCH64 => "AArch64",
+        EM_ALPHA => "Alpha",
+        EM_IA_64 => "IA-64",
+        EM_LOONGARCH => "LoongArch",
+        EM_68K => "m68k",
+        EM_MIPS | EM_MIPS_RS3_LE => "MIPS",
+        EM_PARISC => "PA-RISC",
+        EM_PPC => "PowerPC",
+        EM_PPC64 => "PowerPC64",
+        EM_RISCV => "RISC-V",
+        EM_S390 => "S/390",
+        EM_SH => "SuperH",
+        EM_SPARC | EM_SPARC32PLUS => "SPARC",
+        EM_SPARCV9 => "SPARCv9",
+        _ => return format!("EM({e_machine})"),
+    };
+    r.to_string()
+}
+
+pub fn osabi_name(ei_osabi: u8) -> String {
+    let r = match ei_osabi {
+        ELFOSABI_SYSV => "SYSV",
+        ELFOSABI_HPUX => "HPUX",
+        ELFOSABI_NETBSD => "NetBSD",
+        ELFOSABI_GNU => "GNU",
+        ELFOSABI_SOLARIS => "Solaris",
+        ELFOSABI_FREEBSD => "FreeBSD",
+        ELFOSABI_OPENBSD => "OpenBSD",
+        ELFOSABI_ARM_AEABI => "ARM-AEABI",
+        ELFOSABI_ARM => "ARM",
+        ELFOSABI_STANDALONE => "Standalone",
+        _ => return format!("OSABI({ei_osabi})"),
+    };
+    r.to_string()
+}
+
+// Return the object architecture as 'class/machine/osabi', for instance 'ELF64/x86-64/SYSV'.
+pub fn get(ei_class: u8, e_machine: u16, ei_osabi: u8) -> String {
+    format!(
+        "{}/{}/{}",
+        class_name(ei_class),
+        machine_name(e_machine),
+        osabi_name(ei_osabi)
+    )
+}
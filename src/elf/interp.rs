@@ -31,15 +31,11 @@ const GLIBC_INTERP: &[&str] = &[
     "ld64.so.2",                     // powerpc64 ELFv2.
 ];
 
-pub fn is_glibc(interp: &Option<String>) -> bool {
-    /*
-    if let Some(interp) = get_interp_name(interp) {
-        return GLIBC_INTERP.contains(&interp);
-    }
-    false
-    */
+// The EXTRA names (--glibc-interp) are checked along with the built-in ones.
+pub fn is_glibc(interp: &Option<String>, extra: &[String]) -> bool {
     if let Some(interp) = interp {
-        return GLIBC_INTERP.contains(&pathutils::get_name(&Path::new(interp)).as_str());
+        let name = pathutils::get_name(&Path::new(interp));
+        return GLIBC_INTERP.contains(&name.as_str()) || extra.contains(&name);
     }
     false
 }
@@ -123,6 +119,23 @@ pub fn is_musl(interp: &Option<String>) -> bool {
     false
 }
 
+// The musl loader is also the libc, so it resolves both its own name and the libraries
+// merged into libc (libc, libpthread, librt, libm, libdl, libutil, and libxnet, with any
+// suffix) to itself instead of searching for them.
+const MUSL_RESERVED: &[&str] = &["c", "pthread", "rt", "m", "dl", "util", "xnet"];
+
+pub fn is_musl_self(name: &str, interp: &str) -> bool {
+    if let Some(name) = name.strip_prefix("lib") {
+        if MUSL_RESERVED
+            .iter()
+            .any(|r| name.strip_prefix(r).is_some_and(|s| s.starts_with('.')))
+        {
+            return true;
+        }
+    }
+    name == interp
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -140,4 +153,34 @@ mod tests {
         );
         assert_eq!(is_musl(&Some("/lib/ld-musl-x86_64.so.1".to_string())), true);
     }
+
+    #[test]
+    fn check_is_glibc() {
+        let extra = vec!["ld-linux-new.so.1".to_string()];
+        assert!(!is_glibc(&None, &extra));
+        assert!(is_glibc(
+            &Some("/lib64/ld-linux-x86-64.so.2".to_string()),
+            &[]
+        ));
+        assert!(!is_glibc(&Some("/lib/ld-linux-new.so.1".to_string()), &[]));
+        assert!(is_glibc(
+            &Some("/lib/ld-linux-new.so.1".to_string()),
+            &extra
+        ));
+        assert!(!is_glibc(
+            &Some("/lib/ld-musl-x86_64.so.1".to_string()),
+            &extra
+        ));
+    }
+
+    #[test]
+    fn check_is_musl_self() {
+        let interp = "ld-musl-x86_64.so.1";
+        assert!(is_musl_self("libc.so", interp));
+        assert!(is_musl_self("libc.musl-x86_64.so.1", interp));
+        assert!(is_musl_self("libpthread.so.0", interp));
+        assert!(is_musl_self("ld-musl-x86_64.so.1", interp));
+        assert!(!is_musl_self("libcrypto.so.3", interp));
+        assert!(!is_musl_self("libmagic.so.1", interp));
+    }
 }
@@ -30,7 +30,8 @@ const ELFHINTS_MAXFILESIZE: u64 = 16 * 1024;
 pub fn parse_ld_so_hints<P: AsRef<Path>>(filename: &P) -> Result<search_path::SearchPathVec> {
     let mut file = File::open(filename)?;
 
-    if file.metadata()?.len() > ELFHINTS_MAXFILESIZE {
+    let hsize = file.metadata()?.len();
+    if hsize > ELFHINTS_MAXFILESIZE {
         return Err(Error::new(
             ErrorKind::Other,
             format!("File larger than {ELFHINTS_MAXFILESIZE}"),
@@ -50,9 +51,17 @@ pub fn parse_ld_so_hints<P: AsRef<Path>>(filename: &P) -> Result<search_path::Se
         return Err(Error::new(ErrorKind::Other, "Invalid elfhints_hdr version"));
     }
 
-    let mut dirlist: Vec<u8> = vec![0; hdr.dirlistlen as usize];
+    // The directory list offset and length are used as-is, so check they are within the
+    // file before reading it.
+    let dirlistoff = u64::from(hdr.strtab) + u64::from(hdr.dirlist);
+    if dirlistoff + u64::from(hdr.dirlistlen) > hsize {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Invalid directory list offset in hint file",
+        ));
+    }
 
-    let dirlistoff: u64 = (hdr.strtab + hdr.dirlist).into();
+    let mut dirlist: Vec<u8> = vec![0; hdr.dirlistlen as usize];
     file.seek(SeekFrom::Start(dirlistoff))?;
     file.read_exact(&mut dirlist)?;
 
@@ -140,6 +149,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_ld_so_hints_invalid_dirlist() -> Result<()> {
+        let tmpdir = TempDir::new()?;
+        let filepath = tmpdir.path().join("ld-elf.so.hints");
+
+        // Directory lists past the end of the file, including offsets that overflow.
+        for (strtab, dirlist, dirlistlen) in [
+            (0, ELFHINTS_HDR_LEN, 4096),
+            (0, 1 << 20, 0),
+            (u32::MAX, u32::MAX, u32::MAX),
+        ] {
+            let hdr = elfhints_hdr {
+                magic: ELFHINTS_MAGIC,
+                version: ELFHINTS_VERSION,
+                strtab,
+                strsize: 0,
+                dirlist,
+                dirlistlen,
+                spare: [0; 26usize],
+            };
+            let mut file = File::create(&filepath)?;
+            file.write_all(unsafe { any_as_u8_slice(&hdr) })?;
+            file.write_all(b"/lib\0")?;
+
+            let err = parse_ld_so_hints(&filepath).expect_err("invalid dirlist accepted");
+            assert_eq!(
+                err.to_string(),
+                "Invalid directory list offset in hint file"
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn parse_ld_so_hints_one() -> Result<()> {
         let tmpdir = TempDir::new()?;
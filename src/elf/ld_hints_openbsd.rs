@@ -49,7 +49,17 @@ pub fn parse_ld_so_hints<P: AsRef<Path>>(filename: &P) -> Result<search_path::Se
         return Err(Error::new(ErrorKind::Other, "Invalid elfhints_hdr version"));
     }
 
-    let dirlistoff: u64 = (hdr.hh_strtab + hdr.hh_dirlist) as u64;
+    // The string table and directory list offsets are used as-is, so check the directory
+    // list starts within the file before seeking.
+    let dirlistoff = match hdr.hh_strtab.checked_add(hdr.hh_dirlist) {
+        Some(off) if hdr.hh_strtab >= 0 && hdr.hh_dirlist >= 0 && off < hsize => off as u64,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Invalid directory list offset in hint file",
+            ))
+        }
+    };
     file.seek(SeekFrom::Start(dirlistoff))?;
 
     // OpenBSD header file does not specify the hh_dirlist len, but it encodes it as a
@@ -57,6 +67,12 @@ pub fn parse_ld_so_hints<P: AsRef<Path>>(filename: &P) -> Result<search_path::Se
     let mut reader = BufReader::new(file);
     let mut dirlist: Vec<u8> = Vec::<u8>::new();
     reader.read_until(b'\0', &mut dirlist)?;
+    if dirlist.last() != Some(&0) {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Unterminated directory list in hint file",
+        ));
+    }
 
     if let Some(dirlist) = str::from_utf8(&dirlist)
         .ok()
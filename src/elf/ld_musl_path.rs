@@ -0,0 +1,102 @@
+// musl loader path file parsing.  Instead of a cache, musl reads the default search path
+// from /etc/ld-musl-$(ARCH).path, with entries separated by ':' or newlines.
+
+use std::path::Path;
+
+use crate::pathutils;
+use crate::search_path::*;
+
+// Used if the path file does not exist.
+const MUSL_DEFAULT_PATH: &str = "/lib:/usr/local/lib:/usr/lib";
+
+// Return the path file for the musl loader INTERP (/lib/ld-musl-x86_64.so.1 reads
+// /etc/ld-musl-x86_64.path).
+fn path_file(interp: &str) -> Option<String> {
+    let name = pathutils::get_name(&Path::new(interp));
+    let arch = name.strip_prefix("ld-musl-")?.strip_suffix(".so.1")?;
+    Some(format!("/etc/ld-musl-{arch}.path"))
+}
+
+// Returns the default search path of the musl loader INTERP, with each entry (and the
+// path file itself) mapped with REBASE to handle a --sysroot.  If CONFIG is not set, or
+// if there is no path file, the built-in one is used.
+pub fn parse_ld_musl_path<F: Fn(&str) -> String>(
+    interp: Option<&String>,
+    config: bool,
+    rebase: F,
+) -> SearchPathVec {
+    let contents = interp
+        .filter(|_| config)
+        .and_then(|interp| path_file(interp))
+        .and_then(|file| std::fs::read_to_string(rebase(&file)).ok());
+
+    let mut r = SearchPathVec::new();
+    for entry in contents
+        .as_deref()
+        .unwrap_or(MUSL_DEFAULT_PATH)
+        .split(&[':', '\n'][..])
+        .filter(|e| !e.is_empty())
+    {
+        r.add_path(&rebase(entry));
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn check_path_file() {
+        assert_eq!(
+            path_file("/lib/ld-musl-x86_64.so.1"),
+            Some("/etc/ld-musl-x86_64.path".to_string())
+        );
+        assert_eq!(
+            path_file("ld-musl-armhf.so.1"),
+            Some("/etc/ld-musl-armhf.path".to_string())
+        );
+        assert_eq!(path_file("/lib64/ld-linux-x86-64.so.2"), None);
+    }
+
+    #[test]
+    fn parse_path_file() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let root = tmpdir.path().canonicalize()?;
+        for dir in ["etc", "lib", "usr/lib", "opt/lib", "opt/lib64"] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        let rebase = |p: &str| {
+            root.join(p.trim_start_matches('/'))
+                .to_string_lossy()
+                .to_string()
+        };
+        let interp = "/lib/ld-musl-x86_64.so.1".to_string();
+
+        // Without a path file, the built-in path is used (/usr/local/lib does not exist).
+        let paths = parse_ld_musl_path(Some(&interp), true, rebase);
+        assert_eq!(
+            paths,
+            [rebase("/lib").as_str(), rebase("/usr/lib").as_str()]
+        );
+
+        std::fs::write(
+            root.join("etc/ld-musl-x86_64.path"),
+            "/opt/lib:/opt/missing\n/opt/lib64\n",
+        )?;
+        let paths = parse_ld_musl_path(Some(&interp), true, rebase);
+        assert_eq!(
+            paths,
+            [rebase("/opt/lib").as_str(), rebase("/opt/lib64").as_str()]
+        );
+
+        // The host configuration is not used with --isolated.
+        let paths = parse_ld_musl_path(Some(&interp), false, rebase);
+        assert_eq!(
+            paths,
+            [rebase("/lib").as_str(), rebase("/usr/lib").as_str()]
+        );
+        Ok(())
+    }
+}
@@ -33,8 +33,12 @@ fn parse_line(line: &str) -> Option<String> {
 }
 
 // Returns a vector of libraries read from file FILENAME.  The file contains names of
-// libraries to be loaded, separated by white spaces or `:'.
-pub fn parse_ld_so_preload<P: AsRef<Path>>(filename: &P) -> SearchPathVec {
+// libraries to be loaded, separated by white spaces or `:', and each one is mapped
+// with REBASE (to handle a --sysroot).
+pub fn parse_ld_so_preload<P: AsRef<Path>, F: Fn(&str) -> String>(
+    filename: &P,
+    rebase: F,
+) -> SearchPathVec {
     let mut r = SearchPathVec::new();
 
     let mut lines = match read_lines(filename) {
@@ -49,8 +53,8 @@ pub fn parse_ld_so_preload<P: AsRef<Path>>(filename: &P) -> SearchPathVec {
             None => continue,
         };
 
-        for entry in line.split(&[':', ' ', '\t'][..]) {
-            r.add_path(entry);
+        for entry in line.split(&[':', ' ', '\t'][..]).filter(|e| !e.is_empty()) {
+            r.add_path(&rebase(entry));
         }
     }
 
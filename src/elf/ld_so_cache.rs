@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
 use std::mem::{align_of, size_of, transmute};
@@ -192,11 +193,25 @@ fn check_cache_new_endian(flags: u8) -> bool {
     flags == 0 || (flags & cache_file_new_flags_endian_big) == cache_file_new_flags_endian_current
 }
 
+// The offsets are read from the file, so check they are within the cache before using them
+// to avoid seeking outside the file on a malformed cache.
+fn check_range(offset: i64, len: usize, cache_size: usize) -> Result<()> {
+    match usize::try_from(offset)
+        .ok()
+        .and_then(|o| o.checked_add(len))
+    {
+        Some(end) if end <= cache_size => Ok(()),
+        _ => Err(Error::other("Invalid cache offset")),
+    }
+}
+
 fn read_string<R: Read + Seek>(
     reader: &mut BufReader<R>,
     prev_off: &mut i64,
     cur: i64,
+    cache_size: usize,
 ) -> Result<String> {
+    check_range(cur, 1, cache_size)?;
     let mut value: Vec<u8> = Vec::<u8>::new();
     reader.seek_relative(cur - *prev_off)?;
     let size = reader.read_until(b'\0', &mut value)?;
@@ -210,7 +225,7 @@ fn read_string<R: Read + Seek>(
 // Read a u32 value in native endianess format.
 fn read_u32<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<u32> {
     let mut buffer = [0; 4];
-    reader.read_exact(&mut buffer[..]).unwrap();
+    reader.read_exact(&mut buffer[..])?;
     Ok(u32::from_ne_bytes(buffer))
 }
 
@@ -218,7 +233,33 @@ fn align_cache(value: usize) -> usize {
     (value + (align_of::<cache_file_new>() - 1)) & !(align_of::<cache_file_new>() - 1)
 }
 
-pub type LdCache = HashMap<String, String>;
+// The cache layout that was parsed, reported with --verbose.
+pub enum CacheFormat {
+    // Only the 'ld.so-1.7.0' entries.
+    Old,
+    // The 'glibc-ld.so.cache1.1' entries, either standalone or after the old ones (COMPAT),
+    // with the number of glibc-hwcaps subdirectories listed in the extension.
+    New { compat: bool, hwcaps: usize },
+}
+
+impl fmt::Display for CacheFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheFormat::Old => write!(f, "old format"),
+            CacheFormat::New { compat, hwcaps } => write!(
+                f,
+                "new format{} with {hwcaps} glibc-hwcaps subdirectories",
+                if *compat { " (after the old one)" } else { "" }
+            ),
+        }
+    }
+}
+
+pub struct LdCache {
+    // Library name to the directory it is in.
+    pub entries: HashMap<String, String>,
+    pub format: CacheFormat,
+}
 
 fn parse_ld_so_cache_old<R: Read + Seek>(
     reader: &mut BufReader<R>,
@@ -226,6 +267,7 @@ fn parse_ld_so_cache_old<R: Read + Seek>(
     ei_class: u8,
     e_machine: u16,
     e_flags: u32,
+    hwcap: Option<&[&str]>,
 ) -> Result<LdCache> {
     let hdr: cache_file = {
         let mut h = [0u8; CACHE_FILE_LEN];
@@ -239,7 +281,19 @@ fn parse_ld_so_cache_old<R: Read + Seek>(
 
     let offset = align_cache(CACHE_FILE_LEN + (hdr.nlibs as usize * FILE_ENTRY_LEN));
     if cache_size > (offset + CACHE_FILE_NEW_LEN) {
-        return parse_ld_so_cache_new(reader, offset, ei_class, e_machine, e_flags);
+        return parse_ld_so_cache_new(
+            reader, offset, cache_size, ei_class, e_machine, e_flags, hwcap,
+        )
+        .map(|c| LdCache {
+            format: match c.format {
+                CacheFormat::New { hwcaps, .. } => CacheFormat::New {
+                    compat: true,
+                    hwcaps,
+                },
+                format => format,
+            },
+            ..c
+        });
     }
 
     if hdr.magic != CACHEMAGIC.as_bytes() {
@@ -259,27 +313,35 @@ fn parse_ld_so_cache_old<R: Read + Seek>(
         if !check_file_entry_flags(entry.flags, ei_class, e_machine, e_flags) {
             continue;
         }
-        offsets.push((entry.key + cache_off, entry.value + cache_off));
+        offsets.push((
+            entry.key.saturating_add(cache_off),
+            entry.value.saturating_add(cache_off),
+        ));
     }
 
     let mut prev_off = cache_off as i64;
 
-    let mut ldsocache = LdCache::new();
+    let mut ldsocache = HashMap::new();
     for off in offsets {
-        let key = read_string(reader, &mut prev_off, off.0 as i64)?;
-        let value = read_string(reader, &mut prev_off, off.1 as i64)?;
+        let key = read_string(reader, &mut prev_off, off.0 as i64, cache_size)?;
+        let value = read_string(reader, &mut prev_off, off.1 as i64, cache_size)?;
 
         ldsocache.insert(key, value);
     }
-    Ok(ldsocache)
+    Ok(LdCache {
+        entries: ldsocache,
+        format: CacheFormat::Old,
+    })
 }
 
 fn parse_ld_so_cache_new<R: Read + Seek>(
     reader: &mut BufReader<R>,
     initial: usize,
+    cache_size: usize,
     ei_class: u8,
     e_machine: u16,
     e_flags: u32,
+    hwcap: Option<&[&str]>,
 ) -> Result<LdCache> {
     reader.seek(SeekFrom::Start(initial as u64))?;
     let hdr: cache_file_new = {
@@ -298,6 +360,14 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
         return Err(Error::new(ErrorKind::Other, "Invalid new cache endianness"));
     }
 
+    // The string offsets are relative to the new header.
+    let cache_size = cache_size.saturating_sub(initial);
+    check_range(
+        CACHE_FILE_NEW_LEN as i64,
+        hdr.nlibs as usize * FILE_ENTRY_NEW_LEN,
+        cache_size,
+    )?;
+
     // To optimize file read, create a list of file entries offset (name and path)
     // and then read the filaname and path.  Also keep track of hwcap index value used for
     // glibc-hwcap support.
@@ -314,25 +384,30 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
         if !check_file_entry_flags(entry.flags, ei_class, e_machine, e_flags) {
             continue;
         }
+        // Without the machine hwcaps only the baseline entries are used, so the result does
+        // not depend on the host CPU.
+        let hwcapidx = check_cache_hwcap_extension(entry.hwcap);
+        if hwcap.is_none() && hwcapidx.is_some() {
+            continue;
+        }
 
-        offsets.push((
-            entry.key,
-            entry.value,
-            check_cache_hwcap_extension(entry.hwcap),
-        ));
+        offsets.push((entry.key, entry.value, hwcapidx));
     }
 
     let mut prev_off = CACHE_FILE_NEW_LEN as i64 + hdr.nlibs as i64 * FILE_ENTRY_NEW_LEN as i64;
 
     // Return vector of defined glibc-hwcap subfolder defined in the extension headers.  For
     // instance on x86_64 it mught return [x86-64-v2, x86-64-v3].
-    let hwcap_idxs =
-        parse_ld_so_cache_glibc_hwcap(reader, &mut prev_off, hdr.extension_offset as i64)?;
+    let hwcap_idxs = parse_ld_so_cache_glibc_hwcap(
+        reader,
+        &mut prev_off,
+        hdr.extension_offset as i64,
+        cache_size,
+    )?;
 
-    // And obtain the current machine supported glibc-hwcap subfolder.
-    let hwcap_supported = hwcap::hwcap_supported()?;
+    let hwcap_supported = hwcap.unwrap_or_default();
 
-    let mut ldsocache = LdCache::new();
+    let mut ldsocache = HashMap::new();
     // Keep track of the last glibc-hwcap value for the entry to allow check if the new entry is
     // new best-fit value.  Using an extra map avoid the need to add an extra field on the
     // returned ldsocache map.
@@ -340,8 +415,8 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
 
     // Now read all library entries
     for off in offsets {
-        let key = read_string(reader, &mut prev_off, off.0 as i64)?;
-        let value = read_string(reader, &mut prev_off, off.1 as i64)?;
+        let key = read_string(reader, &mut prev_off, off.0 as i64, cache_size)?;
+        let value = read_string(reader, &mut prev_off, off.1 as i64, cache_size)?;
 
         // First check if there is an already found glibc-hwcap option for the entry.  In this case,
         // also check if the newer entry has a glibc-hwcap index associated and if it is also the case
@@ -351,7 +426,7 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
         if let Some(seen_idx) = hwcapseen.get(&key) {
             // It only makes sense to possible update a new entry if there is also a glibc-hwcap
             // entry associated.
-            if let Some(new_idx) = check_hwcap_index(&off.2, &hwcap_idxs, &hwcap_supported) {
+            if let Some(new_idx) = check_hwcap_index(&off.2, &hwcap_idxs, hwcap_supported) {
                 if new_idx < *seen_idx {
                     // If the entry is a newer best fit, update both the cache and the seen map.
                     hwcapseen.insert(key.to_string(), new_idx);
@@ -363,7 +438,7 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
                 }
             }
         } else {
-            if let Some(idx) = check_hwcap_index(&off.2, &hwcap_idxs, &hwcap_supported) {
+            if let Some(idx) = check_hwcap_index(&off.2, &hwcap_idxs, hwcap_supported) {
                 hwcapseen.insert(key.to_string(), idx);
             }
             ldsocache.insert(
@@ -374,18 +449,23 @@ fn parse_ld_so_cache_new<R: Read + Seek>(
         }
     }
 
-    Ok(ldsocache)
+    Ok(LdCache {
+        entries: ldsocache,
+        format: CacheFormat::New {
+            compat: false,
+            hwcaps: hwcap_idxs.len(),
+        },
+    })
 }
 
 // Return a new best-fit index for HWCAP_SUPPORTED if the HWCAPIDX contains a valid value.
 fn check_hwcap_index(
     hwcapidx: &Option<u32>,
     hwcap_idxs: &[String],
-    hwcap_supported: &[&'static str],
+    hwcap_supported: &[&str],
 ) -> Option<usize> {
-    if let Some(hwidx) = hwcapidx {
-        let hwcap_value = hwcap_idxs[*hwidx as usize].to_string();
-        if let Some(new_idx) = hwcap_supported.iter().position(|&r| r == hwcap_value) {
+    if let Some(hwcap_value) = hwcapidx.and_then(|i| hwcap_idxs.get(i as usize)) {
+        if let Some(new_idx) = hwcap_supported.iter().position(|r| r == hwcap_value) {
             return Some(new_idx);
         }
     }
@@ -414,10 +494,12 @@ fn parse_ld_so_cache_glibc_hwcap<R: Read + Seek>(
     reader: &mut BufReader<R>,
     prev_off: &mut i64,
     cur: i64,
+    cache_size: usize,
 ) -> Result<Vec<String>> {
     if cur == 0 {
         return Ok(Vec::<String>::new());
     }
+    check_range(cur, CACHE_EXTENSION_LEN, cache_size)?;
     reader.seek_relative(cur - *prev_off)?;
     let ext: cache_extension = {
         let mut h = [0u8; CACHE_EXTENSION_LEN];
@@ -444,6 +526,7 @@ fn parse_ld_so_cache_glibc_hwcap<R: Read + Seek>(
         *prev_off += CACHE_EXTENSION_SECTION_LEN as i64;
 
         if ext_sec.tag == CACHE_EXTENSION_TAG_GLIBC_HWCAPS {
+            check_range(ext_sec.offset as i64, ext_sec.size as usize, cache_size)?;
             reader.seek_relative(ext_sec.offset as i64 - *prev_off)?;
 
             let idxslen: usize = ext_sec.size as usize / 4;
@@ -455,7 +538,7 @@ fn parse_ld_so_cache_glibc_hwcap<R: Read + Seek>(
 
             *prev_off = ext_sec.offset as i64 + ext_sec.size as i64;
             for idx in &idxs {
-                r.push(read_string(reader, prev_off, *idx as i64)?);
+                r.push(read_string(reader, prev_off, *idx as i64, cache_size)?);
             }
         }
     }
@@ -467,19 +550,169 @@ pub fn parse_ld_so_cache<P: AsRef<Path>>(
     ei_class: u8,
     e_machine: u16,
     e_flags: u32,
+    hwcap: bool,
+    hwcap_override: Option<&[String]>,
 ) -> Result<LdCache> {
     let file = File::open(filename)?;
     let size = file.metadata()?.len() as usize;
 
     let mut reader = BufReader::new(file);
 
+    // Obtain the current machine supported glibc-hwcap subfolders, or the ones of the target
+    // machine if overridden.
+    let hwcap_supported: Option<Vec<&str>> = match (hwcap, hwcap_override) {
+        (false, _) => None,
+        (true, Some(hwcaps)) => Some(hwcaps.iter().map(String::as_str).collect()),
+        (true, None) => Some(hwcap::hwcap_supported()?),
+    };
+    let hwcap = hwcap_supported.as_deref();
+
     let mut magic = [0u8; CACHEMAGIC.len()];
     reader.read_exact(&mut magic[..])?;
     reader.rewind()?;
 
     if magic == CACHEMAGIC.as_bytes() {
-        parse_ld_so_cache_old(&mut reader, size, ei_class, e_machine, e_flags)
+        parse_ld_so_cache_old(&mut reader, size, ei_class, e_machine, e_flags, hwcap)
     } else {
-        parse_ld_so_cache_new(&mut reader, 0, ei_class, e_machine, e_flags)
+        parse_ld_so_cache_new(&mut reader, 0, size, ei_class, e_machine, e_flags, hwcap)
+    }
+}
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
+        ::std::slice::from_raw_parts((p as *const T) as *const u8, ::std::mem::size_of::<T>())
+    }
+
+    // Write a new format cache with a single libfoo.so.1 entry with FLAGS, truncated to LEN
+    // bytes if specified.
+    fn write_ld_so_cache(
+        file: &mut File,
+        nlibs: u32,
+        flags: i32,
+        len: Option<usize>,
+    ) -> Result<()> {
+        let key = b"libfoo.so.1\0";
+        let value = b"/usr/lib/libfoo.so.1\0";
+        let strings_off = (CACHE_FILE_NEW_LEN + FILE_ENTRY_NEW_LEN) as u32;
+
+        let hdr = cache_file_new {
+            magic: CACHEMAGIC_NEW.as_bytes().try_into().unwrap(),
+            version: CACHE_VERSION.as_bytes().try_into().unwrap(),
+            nlibs,
+            len_strings: (key.len() + value.len()) as u32,
+            flags: cache_file_new_flags_endian_current,
+            padding_unsed: [0; 3],
+            extension_offset: 0,
+            unused: [0; 3],
+        };
+        let entry = file_entry_new {
+            flags,
+            key: strings_off,
+            value: strings_off + key.len() as u32,
+            osversion_unused: 0,
+            hwcap: 0,
+        };
+
+        let mut data = Vec::<u8>::new();
+        data.extend_from_slice(unsafe { any_as_u8_slice(&hdr) });
+        data.extend_from_slice(unsafe { any_as_u8_slice(&entry) });
+        data.extend_from_slice(key);
+        data.extend_from_slice(value);
+        data.truncate(len.unwrap_or(data.len()));
+        file.write_all(&data)
+    }
+
+    const X8664_FLAGS: i32 = FLAG_ELF_LIBC6 | FLAG_X8664_LIB64;
+    const X32_FLAGS: i32 = FLAG_ELF_LIBC6 | FLAG_X8664_LIBX32;
+
+    fn parse(filepath: &Path) -> Result<LdCache> {
+        parse_ld_so_cache(&filepath, ELFCLASS64, EM_X86_64, 0, true, None)
+    }
+
+    #[test]
+    fn parse_ld_so_cache_one() -> Result<()> {
+        let tmpdir = TempDir::new()?;
+        let filepath = tmpdir.path().join("ld.so.cache");
+        write_ld_so_cache(&mut File::create(&filepath)?, 1, X8664_FLAGS, None)?;
+
+        let cache = parse(&filepath)?;
+        assert!(matches!(
+            cache.format,
+            CacheFormat::New {
+                compat: false,
+                hwcaps: 0
+            }
+        ));
+        let entries = cache.entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries["libfoo.so.1"], "/usr/lib");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ld_so_cache_x32() -> Result<()> {
+        let tmpdir = TempDir::new()?;
+        let filepath = tmpdir.path().join("ld.so.cache");
+        let parse_x32 =
+            |filepath: &Path| parse_ld_so_cache(&filepath, ELFCLASS32, EM_X86_64, 0, true, None);
+
+        // The x86_64 entries are not used by x32 binaries, and vice versa.
+        write_ld_so_cache(&mut File::create(&filepath)?, 1, X8664_FLAGS, None)?;
+        assert!(parse_x32(&filepath)?.entries.is_empty());
+
+        write_ld_so_cache(&mut File::create(&filepath)?, 1, X32_FLAGS, None)?;
+        assert_eq!(parse_x32(&filepath)?.entries["libfoo.so.1"], "/usr/lib");
+        assert!(parse(&filepath)?.entries.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ld_so_cache_truncated() -> Result<()> {
+        let tmpdir = TempDir::new()?;
+        let filepath = tmpdir.path().join("ld.so.cache");
+        // Truncated in the header, in the entries, and in the strings.
+        for len in [
+            CACHE_FILE_NEW_LEN - 4,
+            CACHE_FILE_NEW_LEN + 8,
+            CACHE_FILE_NEW_LEN + FILE_ENTRY_NEW_LEN + 4,
+        ] {
+            write_ld_so_cache(&mut File::create(&filepath)?, 1, X8664_FLAGS, Some(len))?;
+            assert!(parse(&filepath).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ld_so_cache_invalid_nlibs() -> Result<()> {
+        let tmpdir = TempDir::new()?;
+        let filepath = tmpdir.path().join("ld.so.cache");
+        write_ld_so_cache(&mut File::create(&filepath)?, u32::MAX, X8664_FLAGS, None)?;
+
+        assert!(parse(&filepath).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn check_hwcap_index_override() {
+        let hwcap_idxs = vec!["x86-64-v2".to_string(), "x86-64-v3".to_string()];
+        let hwcap_supported = ["x86-64-v2"];
+
+        assert_eq!(
+            check_hwcap_index(&Some(0), &hwcap_idxs, &hwcap_supported),
+            Some(0)
+        );
+        assert_eq!(
+            check_hwcap_index(&Some(1), &hwcap_idxs, &hwcap_supported),
+            None
+        );
+        assert_eq!(
+            check_hwcap_index(&None, &hwcap_idxs, &hwcap_supported),
+            None
+        );
     }
 }
@@ -9,6 +9,7 @@ use object::elf::*;
 // For some architectures the kernel exports the AT_PLATFORM from a pre-defined value, while
 // for other it depends either on the kernel configuration and/or the CPU.  For the later the
 // function returns a common value.
+#[cfg(not(any(target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
 pub fn get(e_machine: u16, ei_endian: u8) -> String {
     let r = match e_machine {
         // Alpha returns either "ev4", "ev5", "ev56", "ev6", or "ev67" depending of the CPU,
@@ -72,3 +73,99 @@ pub fn get(e_machine: u16, ei_endian: u8) -> String {
 
     r.to_string()
 }
+
+// FreeBSD and OpenBSD rtld expand $PLATFORM to the uname machine, which is the kernel
+// name for the architecture rather than the processor.
+#[cfg(target_os = "freebsd")]
+pub fn get(e_machine: u16, _ei_endian: u8) -> String {
+    match e_machine {
+        EM_AARCH64 => "arm64",
+        EM_ARM => "arm",
+        EM_386 => "i386",
+        EM_PPC | EM_PPC64 => "powerpc",
+        EM_RISCV => "riscv",
+        EM_X86_64 => "amd64",
+        _ => "",
+    }
+    .to_string()
+}
+
+#[cfg(target_os = "openbsd")]
+pub fn get(e_machine: u16, _ei_endian: u8) -> String {
+    match e_machine {
+        EM_AARCH64 => "arm64",
+        EM_ARM => "armv7",
+        EM_386 => "i386",
+        EM_MIPS => "octeon",
+        EM_PPC => "macppc",
+        EM_PPC64 => "powerpc64",
+        EM_RISCV => "riscv64",
+        EM_SPARCV9 => "sparc64",
+        EM_X86_64 => "amd64",
+        _ => "",
+    }
+    .to_string()
+}
+
+// NetBSD ld.elf_so uses the hw.machine_arch sysctl instead, which also encodes the ABI
+// (for instance the endianness and float ABI on ARM).
+#[cfg(target_os = "netbsd")]
+pub fn get(e_machine: u16, ei_endian: u8) -> String {
+    match e_machine {
+        EM_AARCH64 => match ei_endian {
+            ELFDATA2MSB => "aarch64eb",
+            _ => "aarch64",
+        },
+        EM_ARM => match ei_endian {
+            ELFDATA2MSB => "earmv7hfeb",
+            _ => "earmv7hf",
+        },
+        EM_386 => "i386",
+        EM_MIPS => match ei_endian {
+            ELFDATA2MSB => "mipseb",
+            _ => "mipsel",
+        },
+        EM_PPC => "powerpc",
+        EM_PPC64 => "powerpc64",
+        EM_RISCV => "riscv64",
+        EM_SPARC => "sparc",
+        EM_SPARCV9 => "sparc64",
+        EM_X86_64 => "x86_64",
+        _ => "",
+    }
+    .to_string()
+}
+
+// The $OSNAME expansion (the uname sysname), only supported by the BSD and illumos
+// loaders.
+#[cfg(target_os = "freebsd")]
+pub const OSNAME: Option<&str> = Some("FreeBSD");
+#[cfg(target_os = "netbsd")]
+pub const OSNAME: Option<&str> = Some("NetBSD");
+#[cfg(target_os = "openbsd")]
+pub const OSNAME: Option<&str> = Some("OpenBSD");
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub const OSNAME: Option<&str> = Some("SunOS");
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const OSNAME: Option<&str> = None;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn platform_linux() {
+        assert_eq!(get(EM_X86_64, ELFDATA2LSB), "x86_64");
+        assert_eq!(get(EM_AARCH64, ELFDATA2MSB), "aarch64_be");
+        assert_eq!(OSNAME, None);
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn platform_freebsd() {
+        assert_eq!(get(EM_X86_64, ELFDATA2LSB), "amd64");
+        assert_eq!(get(EM_AARCH64, ELFDATA2LSB), "arm64");
+        assert_eq!(OSNAME, Some("FreeBSD"));
+    }
+}
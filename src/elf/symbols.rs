@@ -0,0 +1,502 @@
+// Symbol analysis used by --check-symbols, --unused, and --warn-interpose: the undefined
+// symbols each object needs and the ones it exports, taking the binding (st_info) and
+// visibility (st_other) into account.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use object::elf::*;
+use object::read::elf::*;
+use object::Endianness;
+
+use crate::deptree::*;
+
+#[derive(Default)]
+struct Symbols {
+    // STB_GLOBAL undefined symbols, weak references are allowed to be unresolved.
+    undefined: Vec<String>,
+    // STB_WEAK undefined symbols, which are still bound if some object exports them.
+    weak: Vec<String>,
+    // Defined STB_GLOBAL, STB_WEAK, or STB_GNU_UNIQUE symbols with default or protected
+    // visibility, the only ones the loader binds to.
+    exported: HashSet<String>,
+    // The exported symbols by name, along with their version and binding.
+    definitions: HashMap<String, Vec<Definition>>,
+    // The undefined symbols (either binding) along with the version they require.
+    references: Vec<(String, Option<String>)>,
+    // The STT_OBJECT symbols the object holds a copy of (through a R_*_COPY relocation).
+    copied: HashSet<String>,
+    // Defined symbols that are not exported: STB_LOCAL, or STV_HIDDEN/STV_INTERNAL
+    // visibility.  These are only available if the object has a .symtab.
+    local: HashSet<String>,
+}
+
+// An exported symbol definition, with its GNU version (None if unversioned or the base
+// version).  A hidden version (foo@V1, as opposed to the default foo@@V1) is only bound
+// by references to that version.
+struct Definition {
+    version: Option<String>,
+    hidden: bool,
+    weak: bool,
+}
+
+impl Definition {
+    // Whether the loader binds a reference requiring VERSION to this definition: an
+    // unversioned definition satisfies any reference, and an unversioned reference binds
+    // to the default version.
+    fn binds(&self, version: &Option<String>) -> bool {
+        match (version, &self.version) {
+            (_, None) => true,
+            (Some(required), Some(version)) => required == version,
+            (None, Some(_)) => !self.hidden,
+        }
+    }
+}
+
+fn is_exported(bind: u8, visibility: u8) -> bool {
+    matches!(bind, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE)
+        && matches!(visibility, STV_DEFAULT | STV_PROTECTED)
+}
+
+// The loader only finds the symbols reachable from the hash table, so a .dynsym entry
+// outside it is not exported.  With DT_GNU_HASH the symbols before the symbol base are
+// not hashed (the linker places the local and undefined ones there), while DT_HASH
+// covers the whole table.
+fn hashed_symbols<Elf: FileHeader<Endian = Endianness>>(
+    endian: Endianness,
+    data: &[u8],
+    sections: &SectionTable<Elf>,
+    count: usize,
+) -> Range<usize> {
+    if let Ok(Some((hash, _))) = sections.gnu_hash(endian, data) {
+        let end = hash
+            .symbol_table_length(endian)
+            .map_or(count, |l| l as usize);
+        return hash.symbol_base() as usize..end;
+    }
+    if let Ok(Some((hash, _))) = sections.hash(endian, data) {
+        return 0..hash.symbol_table_length() as usize;
+    }
+    0..count
+}
+
+fn symbol_version<Elf: FileHeader<Endian = Endianness>>(
+    endian: Endianness,
+    versions: Option<&VersionTable<Elf>>,
+    index: usize,
+) -> (Option<String>, bool) {
+    let versions = match versions {
+        Some(versions) => versions,
+        None => return (None, false),
+    };
+    let index = versions.version_index(endian, index);
+    match versions.version(index) {
+        Ok(Some(version)) => (
+            Some(String::from_utf8_lossy(version.name()).to_string()),
+            index.is_hidden(),
+        ),
+        _ => (None, false),
+    }
+}
+
+fn parse_symbol_table<Elf: FileHeader<Endian = Endianness>>(
+    endian: Endianness,
+    table: &SymbolTable<Elf>,
+    symbols: &mut Symbols,
+    dynamic: Option<Range<usize>>,
+    versions: Option<&VersionTable<Elf>>,
+) {
+    for (index, sym) in table.symbols().iter().enumerate() {
+        let name = match sym.name(endian, table.strings()) {
+            Ok(name) if !name.is_empty() => String::from_utf8_lossy(name).to_string(),
+            _ => continue,
+        };
+        let bind = sym.st_bind();
+        let visibility = sym.st_visibility();
+        let hashed = dynamic.as_ref().map(|r| r.contains(&index));
+        if sym.st_shndx(endian) == SHN_UNDEF {
+            if hashed.is_some() && matches!(bind, STB_GLOBAL | STB_WEAK) {
+                let (version, _) = symbol_version(endian, versions, index);
+                symbols.references.push((name.clone(), version));
+            }
+            if hashed.is_some() && bind == STB_GLOBAL {
+                symbols.undefined.push(name);
+            } else if hashed.is_some() && bind == STB_WEAK {
+                symbols.weak.push(name);
+            }
+        } else if hashed == Some(true) && is_exported(bind, visibility) {
+            let (version, hidden) = symbol_version(endian, versions, index);
+            symbols
+                .definitions
+                .entry(name.clone())
+                .or_default()
+                .push(Definition {
+                    version,
+                    hidden,
+                    weak: bind == STB_WEAK,
+                });
+            symbols.exported.insert(name);
+        } else if hashed == Some(false) || !is_exported(bind, visibility) {
+            symbols.local.insert(name);
+        }
+    }
+}
+
+fn parse_elf<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> Option<Symbols> {
+    let elf = Elf::parse(data).ok()?;
+    let endian = elf.endian().ok()?;
+    let sections = elf.sections(endian, data).ok()?;
+
+    let mut symbols = Symbols::default();
+    if let Ok(table) = sections.symbols(endian, data, SHT_DYNSYM) {
+        let hashed = hashed_symbols(endian, data, &sections, table.len());
+        let versions = sections.versions(endian, data).ok().flatten();
+        parse_symbol_table(
+            endian,
+            &table,
+            &mut symbols,
+            Some(hashed),
+            versions.as_ref(),
+        );
+        if let Some(copy) = copy_relocation(elf.e_machine(endian)) {
+            let mips64el = elf.is_mips64el(endian);
+            symbols.copied = copied_symbols(endian, data, &sections, &table, copy, mips64el);
+        }
+    }
+    if let Ok(table) = sections.symbols(endian, data, SHT_SYMTAB) {
+        parse_symbol_table(endian, &table, &mut symbols, None, None);
+    }
+    Some(symbols)
+}
+
+// The R_*_COPY relocation type of the MACHINE, if it has one.
+fn copy_relocation(machine: u16) -> Option<u32> {
+    match machine {
+        EM_386 => Some(R_386_COPY),
+        EM_X86_64 => Some(R_X86_64_COPY),
+        EM_AARCH64 => Some(R_AARCH64_COPY),
+        EM_ARM => Some(R_ARM_COPY),
+        EM_PPC => Some(R_PPC_COPY),
+        EM_PPC64 => Some(R_PPC64_COPY),
+        EM_S390 => Some(R_390_COPY),
+        EM_SPARC | EM_SPARCV9 => Some(R_SPARC_COPY),
+        EM_MIPS => Some(R_MIPS_COPY),
+        EM_RISCV => Some(R_RISCV_COPY),
+        EM_LOONGARCH => Some(R_LARCH_COPY),
+        EM_SH => Some(R_SH_COPY),
+        EM_ALPHA => Some(R_ALPHA_COPY),
+        EM_68K => Some(R_68K_COPY),
+        _ => None,
+    }
+}
+
+// The STT_OBJECT symbols of the dynamic symbol TABLE referenced by a COPY relocation.
+fn copied_symbols<Elf: FileHeader<Endian = Endianness>>(
+    endian: Endianness,
+    data: &[u8],
+    sections: &SectionTable<Elf>,
+    table: &SymbolTable<Elf>,
+    copy: u32,
+    mips64el: bool,
+) -> HashSet<String> {
+    let mut indexes = Vec::new();
+    for section in sections.iter() {
+        if section.sh_link(endian) as usize != table.section().0 {
+            continue;
+        }
+        if let Ok(Some((relocs, _))) = section.rela(endian, data) {
+            indexes.extend(
+                relocs
+                    .iter()
+                    .filter(|r| r.r_type(endian, mips64el) == copy)
+                    .map(|r| r.r_sym(endian, mips64el)),
+            );
+        } else if let Ok(Some((relocs, _))) = section.rel(endian, data) {
+            indexes.extend(
+                relocs
+                    .iter()
+                    .filter(|r| r.r_type(endian) == copy)
+                    .map(|r| r.r_sym(endian)),
+            );
+        }
+    }
+    indexes
+        .into_iter()
+        .filter_map(|index| table.symbol(index as usize).ok())
+        .filter(|sym| sym.st_type() == STT_OBJECT)
+        .filter_map(|sym| sym.name(endian, table.strings()).ok())
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .collect()
+}
+
+fn parse_symbols<P: AsRef<Path>>(filename: &P) -> Option<Symbols> {
+    let file = fs::File::open(filename).ok()?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+    match object::FileKind::parse(&*mmap).ok()? {
+        object::FileKind::Elf32 => parse_elf::<FileHeader32<Endianness>>(&mmap),
+        object::FileKind::Elf64 => parse_elf::<FileHeader64<Endianness>>(&mmap),
+        _ => None,
+    }
+}
+
+// Check that the undefined symbols of every object in DEPTREE are exported by some
+// loaded object, adding a warning for each one that is not.  A symbol defined but not
+// exported (for instance with hidden visibility) shows up with 'nm' but the loader does
+// not bind to it, so it is reported along with the object that defines it.
+pub fn check_symbols(deptree: &mut DepTree) {
+    let objects: Vec<(usize, Symbols)> = deptree
+        .arena
+        .iter()
+        .filter(|d| !d.val.found && d.val.mode != DepMode::NotFound)
+        .filter_map(|d| {
+            let path = Path::new(d.val.path.as_deref().unwrap_or("")).join(&d.val.name);
+            parse_symbols(&path).map(|s| (d.idx, s))
+        })
+        .collect();
+
+    let exported: HashSet<&String> = objects.iter().flat_map(|(_, s)| &s.exported).collect();
+    let mut local = HashMap::<&String, usize>::new();
+    for (idx, symbols) in &objects {
+        for sym in &symbols.local {
+            local.entry(sym).or_insert(*idx);
+        }
+    }
+
+    for (idx, symbols) in &objects {
+        for sym in symbols.undefined.iter().filter(|s| !exported.contains(s)) {
+            let warning = match local.get(sym) {
+                Some(provider) => format!(
+                    "symbol {sym} is present in {} but not exported",
+                    deptree.arena[*provider].val.name
+                ),
+                None => format!("undefined symbol {sym}"),
+            };
+            deptree.arena[*idx].val.warnings.push(warning);
+        }
+    }
+}
+
+// The objects of DEPTREE (the indexes of the not already found nodes) in the loader global
+// scope order: breadth-first from the executable, which also places the preloaded ones
+// right after it.
+fn load_order(deptree: &DepTree, objects: &HashMap<&String, usize>) -> Vec<usize> {
+    let mut order = vec![0];
+    let mut seen = HashSet::from([0]);
+    let mut i = 0;
+    while i < order.len() {
+        for c in &deptree.arena[order[i]].children {
+            if let Some(&idx) = objects.get(&deptree.arena[*c].val.name) {
+                if seen.insert(idx) {
+                    order.push(idx);
+                }
+            }
+        }
+        i += 1;
+    }
+    order
+}
+
+// The loaded objects of DEPTREE by name, the indexes of their nodes in load order, and
+// the symbols of the ones that can be parsed.
+struct Scope<'a> {
+    objects: HashMap<&'a String, usize>,
+    order: Vec<usize>,
+    symbols: HashMap<usize, Symbols>,
+}
+
+fn global_scope(deptree: &DepTree) -> Scope<'_> {
+    let objects: HashMap<&String, usize> = deptree
+        .arena
+        .iter()
+        .filter(|d| !d.val.found && d.val.mode != DepMode::NotFound)
+        .map(|d| (&d.val.name, d.idx))
+        .collect();
+    let order = load_order(deptree, &objects);
+
+    let symbols: HashMap<usize, Symbols> = order
+        .iter()
+        .filter_map(|idx| {
+            let dep = &deptree.arena[*idx].val;
+            let path = Path::new(dep.path.as_deref().unwrap_or("")).join(&dep.name);
+            parse_symbols(&path).map(|s| (*idx, s))
+        })
+        .collect();
+
+    Scope {
+        objects,
+        order,
+        symbols,
+    }
+}
+
+// Report the DT_NEEDED entries of every object in DEPTREE that it does not bind any
+// symbol to, as a warning on the object.  Each symbol binds to the first object in the
+// global scope that exports it, so a dependency providing only symbols already exported
+// by an earlier one is also unused.  With DEEP, also report the objects that are only
+// loaded because of unused entries: the ones not reachable from the executable, the
+// preloaded objects, or the program interpreter INTERP by following what each object
+// binds to.  It requires the already found dependencies to be in the tree.
+pub fn check_unused(deptree: &mut DepTree, deep: bool, interp: Option<&String>) {
+    let Scope {
+        objects,
+        order,
+        symbols,
+    } = global_scope(deptree);
+
+    let mut provider = HashMap::<&String, usize>::new();
+    for idx in &order {
+        if let Some(symbols) = symbols.get(idx) {
+            for sym in &symbols.exported {
+                provider.entry(sym).or_insert(*idx);
+            }
+        }
+    }
+
+    // The objects each one binds at least one symbol to.  Without symbols to check all
+    // its dependencies are considered used.
+    let children = |idx: usize| -> HashSet<usize> {
+        deptree.arena[idx]
+            .children
+            .iter()
+            .filter_map(|c| objects.get(&deptree.arena[*c].val.name).copied())
+            .collect()
+    };
+    let binds: HashMap<usize, HashSet<usize>> = order
+        .iter()
+        .map(|idx| match symbols.get(idx) {
+            Some(symbols) => (
+                *idx,
+                symbols
+                    .undefined
+                    .iter()
+                    .chain(&symbols.weak)
+                    .filter_map(|sym| provider.get(sym).copied())
+                    .filter(|p| p != idx)
+                    .collect(),
+            ),
+            None => (*idx, children(*idx)),
+        })
+        .collect();
+
+    // The preloaded objects and the program interpreter are not DT_NEEDED entries.
+    let implicit: Vec<usize> = deptree
+        .arena
+        .iter()
+        .filter(|d| d.val.mode == DepMode::Preload || Some(&d.val.name) == interp)
+        .filter_map(|d| objects.get(&d.val.name).copied())
+        .collect();
+
+    let mut warnings = Vec::<(usize, String)>::new();
+    for idx in &order {
+        for c in &deptree.arena[*idx].children {
+            let name = &deptree.arena[*c].val.name;
+            match objects.get(name) {
+                Some(dep) if !implicit.contains(dep) && !binds[idx].contains(dep) => {
+                    warnings.push((*idx, format!("DT_NEEDED {name} is unused")));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if deep {
+        let mut needed = HashSet::new();
+        let mut pending = vec![0];
+        pending.extend(&implicit);
+        while let Some(idx) = pending.pop() {
+            if needed.insert(idx) {
+                pending.extend(&binds[&idx]);
+            }
+        }
+        for idx in order.iter().filter(|idx| !needed.contains(idx)) {
+            warnings.push((
+                *idx,
+                "only loaded through unused DT_NEEDED entries".to_string(),
+            ));
+        }
+    }
+
+    for (idx, warning) in warnings {
+        deptree.arena[idx].val.warnings.push(warning);
+    }
+}
+
+// Report the symbols needed by each object of DEPTREE that more than one loaded object
+// exports, as a warning on the object needing them along with the one it binds to.  The
+// loader binds to the first object in load order exporting the symbol whatever its
+// binding (glibc only skips the STB_WEAK definitions with LD_DYNAMIC_WEAK), so it is
+// reported if there are multiple STB_GLOBAL definitions or if a STB_WEAK one loaded first
+// takes precedence over a STB_GLOBAL one.  The definitions of other versions of the
+// symbol are not considered, nor the data the executable holds a copy of, since binding
+// to the copy is how copy relocations work.  It requires the already found dependencies
+// to be in the tree.
+pub fn check_interpose(deptree: &mut DepTree) {
+    let Scope { order, symbols, .. } = global_scope(deptree);
+
+    // The objects in load order with a definition REFERENCE binds to, and whether it is
+    // weak.
+    let providers = |(sym, version): &(String, Option<String>)| -> Vec<(usize, bool)> {
+        order
+            .iter()
+            .filter_map(|idx| {
+                let definitions = symbols.get(idx)?.definitions.get(sym)?;
+                let definition = definitions.iter().find(|d| d.binds(version))?;
+                Some((*idx, definition.weak))
+            })
+            .collect()
+    };
+
+    let name = |idx: &usize| deptree.arena[*idx].val.name.as_str();
+    let mut warnings = Vec::<(usize, String)>::new();
+    for idx in &order {
+        let needed = match symbols.get(idx) {
+            Some(needed) => needed,
+            None => continue,
+        };
+        let mut references: Vec<&(String, Option<String>)> = needed.references.iter().collect();
+        references.sort();
+        references.dedup();
+        for reference in references {
+            let providers = providers(reference);
+            let ((first, weak), others) = match providers.split_first() {
+                Some(providers) => providers,
+                None => continue,
+            };
+            if *first == 0 && symbols[first].copied.contains(&reference.0) {
+                continue;
+            }
+            let sym = match reference {
+                (sym, Some(version)) => format!("{sym}@{version}"),
+                (sym, None) => sym.clone(),
+            };
+            let strong: Vec<&str> = others
+                .iter()
+                .filter(|(_, weak)| !weak)
+                .map(|(p, _)| name(p))
+                .collect();
+            if strong.is_empty() {
+                continue;
+            }
+            let warning = if *weak {
+                format!(
+                    "symbol {sym} binds to the weak definition in {}, not to the one in {}",
+                    name(first),
+                    strong.join(", ")
+                )
+            } else {
+                format!(
+                    "symbol {sym} binds to {}, also exported by {}",
+                    name(first),
+                    strong.join(", ")
+                )
+            };
+            warnings.push((*idx, warning));
+        }
+    }
+
+    for (idx, warning) in warnings {
+        deptree.arena[idx].val.warnings.push(warning);
+    }
+}
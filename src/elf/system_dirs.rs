@@ -6,6 +6,7 @@
 ))]
 use object::elf::*;
 
+use crate::options::Sysroot;
 use crate::search_path;
 
 #[allow(dead_code)]
@@ -51,21 +52,25 @@ pub fn get_system_dirs(
     _interp: &Option<String>,
     e_machine: u16,
     ei_class: u8,
+    sysroot: Option<&Sysroot>,
 ) -> Result<search_path::SearchPathVec, std::io::Error> {
     let path = get_slibdir(e_machine, ei_class)?;
+    // The '/usr' part is configurable on glibc install, however there is no direct
+    // way to obtain it on runtime (--usr-prefix overrides it).
+    let usrpath = format!("{}{path}", sysroot.map_or("/usr", Sysroot::usr_prefix));
+    let rebase = |p: &str| sysroot.map_or(p.to_string(), |s| s.rebase(p));
     Ok(vec![
         search_path::SearchPath {
-            path: path.to_string(),
+            path: rebase(path),
             dev: 0,
             ino: 0,
+            mode: 0,
         },
-        // The '/usr' part is configurable on glibc install, however there is no direct
-        // way to obtain it on runtime.
-        // TODO: Add an option to override it.
         search_path::SearchPath {
-            path: format!("/usr/{path}"),
+            path: rebase(&usrpath),
             dev: 0,
             ino: 0,
+            mode: 0,
         },
     ])
 }
@@ -75,6 +80,7 @@ pub fn get_system_dirs(
     interp: &Option<String>,
     e_machine: u16,
     ei_class: u8,
+    _sysroot: Option<&Sysroot>,
 ) -> Result<search_path::SearchPathVec, std::io::Error> {
     use crate::elf::android;
 
@@ -106,18 +112,21 @@ pub fn get_system_dirs(
                 path,
                 dev: 0,
                 ino: 0,
+                mode: 0,
             });
         }
         r.push(search_path::SearchPath {
             path: format!("/system/lib{suffix}"),
             dev: 0,
             ino: 0,
+            mode: 0,
         });
         if is_asan && add_odm {
             r.push(search_path::SearchPath {
                 path: format!("/data/asan/odm/lib{suffix}"),
                 dev: 0,
                 ino: 0,
+                mode: 0,
             });
         }
         if add_odm {
@@ -125,6 +134,7 @@ pub fn get_system_dirs(
                 path: format!("/odm/lib{suffix}"),
                 dev: 0,
                 ino: 0,
+                mode: 0,
             });
         }
         if is_asan {
@@ -138,12 +148,14 @@ pub fn get_system_dirs(
                 path,
                 dev: 0,
                 ino: 0,
+                mode: 0,
             });
         }
         r.push(search_path::SearchPath {
             path: format!("/vendor/lib{suffix}"),
             dev: 0,
             ino: 0,
+            mode: 0,
         });
         Ok(r)
     }
@@ -170,11 +182,13 @@ pub fn get_system_dirs(
     _interp: &Option<String>,
     _e_machine: u16,
     _ei_class: u8,
+    _sysroot: Option<&Sysroot>,
 ) -> Result<search_path::SearchPathVec, std::io::Error> {
     Ok(vec![search_path::SearchPath {
         path: "/lib".to_string(),
         dev: 0,
         ino: 0,
+        mode: 0,
     }])
 }
 
@@ -183,45 +197,47 @@ pub fn get_system_dirs(
     _interp: &Option<String>,
     _e_machine: u16,
     _ei_class: u8,
+    _sysroot: Option<&Sysroot>,
 ) -> Result<search_path::SearchPathVec, std::io::Error> {
     Ok(vec![search_path::SearchPath {
         path: "/usr/lib".to_string(),
         dev: 0,
         ino: 0,
+        mode: 0,
     }])
 }
 
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+fn system_dir(path: &str) -> search_path::SearchPath {
+    search_path::SearchPath {
+        path: path.to_string(),
+        dev: 0,
+        ino: 0,
+        mode: 0,
+    }
+}
+
+// The 64-bit objects are also searched in the instruction set architecture subdirectory
+// (/usr/lib/64 is usually a symlink to /usr/lib/amd64 or /usr/lib/sparcv9).
 #[cfg(any(target_os = "illumos", target_os = "solaris"))]
 pub fn get_system_dirs(
     _interp: &Option<String>,
     e_machine: u16,
     _ei_class: u8,
+    _sysroot: Option<&Sysroot>,
 ) -> Result<search_path::SearchPathVec, std::io::Error> {
-    match e_machine {
-        EM_386 => Ok(vec![
-            search_path::SearchPath {
-                path: "/lib".to_string(),
-                dev: 0,
-                ino: 0,
-            },
-            search_path::SearchPath {
-                path: "/usr/lib".to_string(),
-                dev: 0,
-                ino: 0,
-            },
-        ]),
-        EM_X86_64 => Ok(vec![
-            search_path::SearchPath {
-                path: "/lib64".to_string(),
-                dev: 0,
-                ino: 0,
-            },
-            search_path::SearchPath {
-                path: "/usr/lib/64".to_string(),
-                dev: 0,
-                ino: 0,
-            },
-        ]),
-        _ => return_error(),
-    }
+    let isa = match e_machine {
+        EM_386 | EM_SPARC | EM_SPARC32PLUS => {
+            return Ok(vec![system_dir("/lib"), system_dir("/usr/lib")])
+        }
+        EM_X86_64 => "amd64",
+        EM_SPARCV9 => "sparcv9",
+        _ => return return_error(),
+    };
+    Ok(vec![
+        system_dir("/lib64"),
+        system_dir("/usr/lib/64"),
+        system_dir(&format!("/lib/{isa}")),
+        system_dir(&format!("/usr/lib/{isa}")),
+    ])
 }
@@ -0,0 +1,92 @@
+// Errors from opening and resolving the objects, so callers can tell an input that is
+// not a binary from one that can not be read.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum RlddError {
+    // The file can not be opened, mapped, or read.
+    Io(io::Error),
+    // The file is not a supported object (ELF, Mach-O, or dyld shared cache).
+    Parse(String),
+    // The file is an ELF core dump, which has no dependencies.
+    CoreFile,
+    // The object is for another class, machine, or ABI than the one loading it.
+    ArchMismatch(String),
+    // The object DT_SONAME does not match the DT_NEEDED used to find it.
+    SonameMismatch(String),
+    // The resolution was aborted by --max-nodes or --timeout.
+    Limit(String),
+    // The object location can not be derived from the path (no parent directory, or not
+    // valid UTF-8).
+    InvalidPath(String),
+}
+
+impl RlddError {
+    // Why a dependency candidate was found but can not be used, which the search trace
+    // reports as rejected.  Only a missing file means there is no candidate at all, a file
+    // that is not a valid object still shadows the name in the search location.
+    pub fn rejection(&self) -> Option<String> {
+        match self {
+            RlddError::Io(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                None
+            }
+            RlddError::Io(e) => Some(format!("can not be read ({e})")),
+            RlddError::Parse(e) => Some(format!("not a valid object ({e})")),
+            RlddError::CoreFile => Some("core file".to_string()),
+            RlddError::ArchMismatch(e) | RlddError::SonameMismatch(e) => Some(e.to_string()),
+            RlddError::Limit(_) | RlddError::InvalidPath(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RlddError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RlddError::Io(e) => write!(f, "{e}"),
+            RlddError::Parse(e) => write!(f, "{e}"),
+            RlddError::CoreFile => write!(f, "core file, no dependencies"),
+            RlddError::ArchMismatch(e) | RlddError::SonameMismatch(e) | RlddError::Limit(e) => {
+                write!(f, "{e}")
+            }
+            RlddError::InvalidPath(e) => write!(f, "{e} has no valid parent directory"),
+        }
+    }
+}
+
+impl std::error::Error for RlddError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RlddError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RlddError {
+    fn from(e: io::Error) -> RlddError {
+        RlddError::Io(e)
+    }
+}
+
+// Keep the io::Error kinds for the callers that only deal with those.
+impl From<RlddError> for io::Error {
+    fn from(e: RlddError) -> io::Error {
+        match e {
+            RlddError::Io(e) => e,
+            RlddError::Parse(_) | RlddError::CoreFile | RlddError::InvalidPath(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+            }
+            RlddError::ArchMismatch(_) | RlddError::SonameMismatch(_) => {
+                io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+            }
+            RlddError::Limit(_) => io::Error::other(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Minimal JSON encoding for the machine-readable outputs.
+
+use crate::deptree::*;
+
+// Return S as a JSON string, with the required escapes.
+pub fn string(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+fn optional_string(s: &Option<String>) -> String {
+    s.as_deref().map_or("null".to_string(), string)
+}
+
+fn string_array(v: &[String]) -> String {
+    let v: Vec<String> = v.iter().map(|s| string(s)).collect();
+    format!("[{}]", v.join(","))
+}
+
+// The mode without the brackets used in the tree output (for instance 'rpath').
+pub fn mode(mode: &DepMode) -> String {
+    match mode {
+        DepMode::Executable => "executable".to_string(),
+        DepMode::NotFound => "not found".to_string(),
+        mode => mode.to_string().trim_matches(&['[', ']'][..]).to_string(),
+    }
+}
+
+// Return the NODE at arena index IDX as a JSON object, PARENT being the arena index of
+// the object that requires it.
+pub fn node(idx: usize, parent: Option<usize>, node: &DepNode) -> String {
+    format!(
+        "{{\"id\":{idx},\"parent\":{},\"name\":{},\"path\":{},\"mode\":{},\"found\":{},\"info\":{},\"warnings\":{}}}",
+        parent.map_or("null".to_string(), |p| p.to_string()),
+        string(&node.name),
+        optional_string(&node.path),
+        string(&mode(&node.mode)),
+        node.found,
+        string_array(&node.info),
+        string_array(&node.warnings),
+    )
+}
+
+// Return NODE as a --events JSON object, identified by the name of the PARENT object
+// that requires it (null for the input object) since the arena indexes are not printed.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+pub fn event(node: &DepNode, parent: Option<&DepNode>) -> String {
+    format!(
+        "{{\"parent\":{},\"name\":{},\"path\":{},\"mode\":{},\"found\":{},\"info\":{},\"warnings\":{}}}",
+        parent.map_or("null".to_string(), |p| string(&p.name)),
+        string(&node.name),
+        optional_string(&node.path),
+        string(&mode(&node.mode)),
+        node.found,
+        string_array(&node.info),
+        string_array(&node.warnings),
+    )
+}
+
+// Return the whole resolution of FILE as a single line JSON object.
+pub fn deptree(file: &str, arch: Option<&str>, deptree: &DepTree) -> String {
+    let nodes: Vec<String> = deptree
+        .arena
+        .iter()
+        .map(|n| node(n.idx, n.parent, &n.val))
+        .collect();
+    let arch = arch.map_or(String::new(), |a| format!(",\"arch\":{}", string(a)));
+    format!(
+        "{{\"file\":{}{arch},\"nodes\":[{}]}}",
+        string(file),
+        nodes.join(",")
+    )
+}
+
+// Return the --version information: the rldd version, the target OS and architecture,
+// the object FORMAT, the loader configuration used, and the target specific options.
+pub fn version(format: &str, capabilities: &[&str]) -> String {
+    let capabilities: Vec<String> = capabilities.iter().map(|c| c.to_string()).collect();
+    format!(
+        "{{\"name\":{},\"version\":{},\"os\":{},\"arch\":{},\"format\":{},\"loader_cache\":{},\"capabilities\":{}}}",
+        string(env!("CARGO_PKG_NAME")),
+        string(env!("CARGO_PKG_VERSION")),
+        string(std::env::consts::OS),
+        string(std::env::consts::ARCH),
+        string(format),
+        string(&mode(&DepMode::LdCache)),
+        string_array(&capabilities),
+    )
+}
+
+pub fn error(file: &str, error: &str) -> String {
+    format!("{{\"file\":{},\"error\":{}}}", string(file), string(error))
+}
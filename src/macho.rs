@@ -1,5 +1,4 @@
-use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 use std::{fmt, fs, str};
 
@@ -10,27 +9,47 @@ use object::read::macho::*;
 use object::Endianness;
 
 use crate::deptree::*;
+use crate::error::RlddError;
+use crate::options::{Limits, ResolveOptions};
 use crate::pathutils;
 use crate::search_path;
 use crate::search_path::*;
 
 mod dydlcache;
 
-type ImagesMap = HashMap<String, Option<u64>>;
+// The image file offset along with the cache file index (0 for the main cache file and
+// N for the subcache N).
+type ImagesMap = HashMap<String, Option<(usize, u64)>>;
 
 #[derive(Default)]
 pub struct DyldCache {
     images: ImagesMap,
     mmap: Option<Mmap>,
+    subcaches: Vec<Mmap>,
 }
 
 type MachObj = MachOInfo;
-type DepsVec = Vec<String>;
 
+// A LC_LOAD_DYLIB (and variants) entry.
+#[derive(Default, Debug)]
+struct MachODep {
+    name: String,
+    compat_version: u32,
+}
+
+type DepsVec = Vec<MachODep>;
+
+// A parsed Mach-O object:
+// - rpath: LC_RPATH search paths.
+// - deps: dependencies from the LC_LOAD_DYLIB load commands.
+// - id_dylib: the LC_ID_DYLIB install name, if the object is a dylib.
+// - current_version: the LC_ID_DYLIB current version, if the object is a dylib.
 #[derive(Default, Debug)]
 struct MachOInfo {
     rpath: search_path::SearchPathVec,
     deps: DepsVec,
+    id_dylib: Option<String>,
+    current_version: Option<u32>,
 }
 
 // Return type for the parse_* functions.
@@ -46,11 +65,21 @@ enum OpenMachOFileResult {
 }
 
 impl DyldCache {
+    // The install names of the images in the cache (including the subcaches), the
+    // equivalent of 'dyld_shared_cache_util -list'.
+    pub fn images(&self) -> BTreeSet<&String> {
+        self.images.keys().collect()
+    }
+
     // Retrieve a dynamic object information from the dyld system cache.
     fn get(&self, name: &String, executable_path: &String) -> Option<MachOInfo> {
         if let (Some(mmap), Some(offset)) = (self.mmap.as_ref(), self.images.get(name)) {
-            if let Some(offset) = offset {
-                return match parse_object(mmap, *offset, executable_path) {
+            if let Some((idx, offset)) = offset {
+                let data = match idx {
+                    0 => mmap,
+                    idx => &self.subcaches[idx - 1],
+                };
+                return match parse_object(data, *offset, executable_path, None) {
                     Ok(ParseObjectResult::Object(obj)) => Some(obj),
                     _ => None,
                 };
@@ -62,16 +91,77 @@ impl DyldCache {
         };
         None
     }
+
+    // Starting with Monterey the cache is split in multiple files, where the main one
+    // only contains the header and mappings and the images are on the subcaches files.
+    // The subcache images do not have a file offset on the main cache file, so the
+    // subcaches are mapped and the images offsets are updated.
+    fn load_subcaches(&mut self, path: &str) -> Option<()> {
+        let mmap = self.mmap.as_ref()?;
+        let header = DyldCacheHeader::<Endianness>::parse(&**mmap).handle_err()?;
+        let (_, endian) = header.parse_magic().handle_err()?;
+        let count = header
+            .subcaches(endian, &**mmap)
+            .handle_err()?
+            .map_or(0, |s| s.len());
+        if count == 0 {
+            return None;
+        }
+
+        let mut subcaches = Vec::new();
+        for i in 1..=count {
+            // macOS Ventura uses a two digits suffix.
+            let subcache =
+                map_file(&format!("{path}.{i}")).or_else(|| map_file(&format!("{path}.{i:02}")))?;
+            subcaches.push(subcache);
+        }
+        if header.symbols_subcache_uuid(endian).is_some() {
+            subcaches.push(map_file(&format!("{path}.symbols"))?);
+        }
+
+        let data: Vec<&[u8]> = std::iter::once(&**mmap)
+            .chain(subcaches.iter().map(|m| &**m))
+            .collect();
+        let cache = object::read::macho::DyldCache::<Endianness>::parse(data[0], &data[1..])
+            .handle_err()?;
+        for image in cache.images() {
+            if let (Ok(name), Ok((image_data, offset))) =
+                (image.path(), image.image_data_and_offset())
+            {
+                if let Some(idx) = data
+                    .iter()
+                    .position(|d| std::ptr::eq(d.as_ptr(), image_data.as_ptr()))
+                {
+                    self.images.insert(name.to_string(), Some((idx, offset)));
+                }
+            }
+        }
+
+        self.subcaches = subcaches;
+        Some(())
+    }
 }
 
+fn map_file(path: &str) -> Option<Mmap> {
+    let file = fs::File::open(path).ok()?;
+    unsafe { memmap2::Mmap::map(&file) }.ok()
+}
+
+// Resolution state shared between the inputs.
+pub type Context = DyldCache;
+
 // macOS starting with BigSur only provides a generated cache of all built in dynamic
 // libraries, so file does not exist in the file system it is then checked against the
-// cache.
-pub fn create_context() -> DyldCache {
+// cache.  With --isolated the host cache is not used.
+pub fn create_context(opts: &ResolveOptions) -> Context {
+    if opts.isolated {
+        return DyldCache::default();
+    }
     if let Some(path) = dydlcache::path() {
-        if let Ok(OpenMachOFileResult::Cache(cache)) =
-            open_macho_file(&Path::new(path), &String::new())
+        if let Ok(OpenMachOFileResult::Cache(mut cache)) =
+            open_macho_file(&Path::new(path), &String::new(), None)
         {
+            cache.load_subcaches(path);
             return cache;
         }
     }
@@ -79,29 +169,70 @@ pub fn create_context() -> DyldCache {
     DyldCache::default()
 }
 
+// The dyld shared cache is only used for lookups, there is nothing to report about it.
+pub fn describe_context(_ctx: &Context) -> Option<String> {
+    None
+}
+
 pub fn resolve_binary(
     cache: &mut DyldCache,
-    preload: &search_path::SearchPathVec,
-    library_path: &search_path::SearchPathVec,
-    _platform: &Option<String>,
-    all: bool,
+    opts: &ResolveOptions,
     arg: &str,
-) -> Result<DepTree, std::io::Error> {
+) -> Result<DepTree, RlddError> {
     let filename = Path::new(arg).canonicalize()?;
+    let executable_path = get_executable_path(&filename)?;
 
-    let executable_path = pathutils::get_path(&filename).ok_or(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        format!("failed to get path of input file {arg}"),
-    ))?;
-
-    let omf = match open_macho_file(&filename, &executable_path)? {
+    let omf = match open_macho_file(&filename, &executable_path, opts.arch.as_deref())? {
         OpenMachOFileResult::Object(obj) => obj,
-        _ => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("Invalid MachO file: {arg}"),
-            ))
-        }
+        _ => return Err(RlddError::Parse("Invalid MachO file".to_string())),
+    };
+
+    resolve_object(cache, opts, &filename, &executable_path, &omf)
+}
+
+// Resolve the dependencies of each architecture slice of an universal binary (or the
+// only architecture for a thin one), returning each architecture name along with its
+// dependency tree.
+pub fn resolve_binary_arches(
+    cache: &mut DyldCache,
+    opts: &ResolveOptions,
+    arg: &str,
+) -> Result<Vec<(String, DepTree)>, RlddError> {
+    let filename = Path::new(arg).canonicalize()?;
+    let executable_path = get_executable_path(&filename)?;
+
+    open_macho_file_arches(&filename, &executable_path)?
+        .iter()
+        .map(|(arch, omf)| {
+            resolve_object(cache, opts, &filename, &executable_path, omf)
+                .map(|deptree| (arch.to_string(), deptree))
+        })
+        .collect()
+}
+
+fn get_executable_path(filename: &Path) -> Result<String, RlddError> {
+    pathutils::get_path(&filename)
+        .ok_or_else(|| RlddError::InvalidPath(filename.display().to_string()))
+}
+
+fn resolve_object(
+    cache: &DyldCache,
+    opts: &ResolveOptions,
+    filename: &Path,
+    executable_path: &String,
+    omf: &MachObj,
+) -> Result<DepTree, RlddError> {
+    let config = Config {
+        cache,
+        library_path: &opts.ld_library_path,
+        extra_dirs: &opts.extra_dirs,
+        executable_path,
+        all: opts.all,
+        explain: opts.explain.as_ref(),
+        verbose: opts.verbose,
+        install_name: opts.install_name,
+        arch: opts.arch.as_deref(),
+        limits: Limits::new(opts),
     };
 
     let mut deptree = DepTree::new();
@@ -110,37 +241,54 @@ pub fn resolve_binary(
         name: pathutils::get_name(&filename),
         mode: DepMode::Executable,
         found: false,
+        warnings: Vec::new(),
+        info: config.object_info(omf),
+        file_id: None,
+        trace: SearchTrace::new(),
     });
 
-    let config = Config {
-        cache,
-        library_path,
-        executable_path: &executable_path,
-        all,
-    };
-
-    for pload in preload {
+    for pload in &opts.ld_preload {
+        let pload = MachODep {
+            name: pload.path.clone(),
+            compat_version: 0,
+        };
         resolve_dependency(
             &config,
-            &executable_path,
+            executable_path,
             &omf.rpath,
-            &pload.path,
+            &pload,
             &mut deptree,
             depp,
             true,
-        );
+        )?;
     }
 
     for dep in &omf.deps {
         resolve_dependency(
             &config,
-            &executable_path,
+            executable_path,
             &omf.rpath,
             dep,
             &mut deptree,
             depp,
             false,
-        );
+        )?;
+    }
+
+    for also in &opts.also {
+        let also = MachODep {
+            name: also.clone(),
+            compat_version: 0,
+        };
+        resolve_dependency(
+            &config,
+            executable_path,
+            &omf.rpath,
+            &also,
+            &mut deptree,
+            depp,
+            false,
+        )?;
     }
 
     Ok(deptree)
@@ -149,72 +297,148 @@ pub fn resolve_binary(
 struct Config<'a> {
     cache: &'a DyldCache,
     library_path: &'a search_path::SearchPathVec,
+    extra_dirs: &'a search_path::SearchPathVec,
     executable_path: &'a String,
     all: bool,
+    explain: Option<&'a String>,
+    verbose: bool,
+    install_name: bool,
+    // Architecture slice to use for universal binaries instead of the host one.
+    arch: Option<&'a str>,
+    limits: Limits,
+}
+
+impl Config<'_> {
+    // The search trace is only kept for the dependency being explained, or for all of
+    // them in verbose mode.
+    fn keep_trace(&self, name: &str, trace: &mut SearchTrace) -> SearchTrace {
+        match self.explain {
+            _ if self.verbose => std::mem::take(trace),
+            Some(explain) if explain == name => std::mem::take(trace),
+            _ => SearchTrace::new(),
+        }
+    }
+
+    // The LC_ID_DYLIB install name is what the objects linked against the dylib record
+    // as the dependency, the Mach-O counterpart of the ELF DT_SONAME.
+    fn object_info(&self, omf: &MachOInfo) -> Vec<String> {
+        match &omf.id_dylib {
+            Some(id_dylib) if self.install_name => vec![format!("{{install name: {id_dylib}}}")],
+            _ => Vec::new(),
+        }
+    }
 }
 
 fn resolve_dependency(
     config: &Config,
     loader_path: &str,
     rpaths: &search_path::SearchPathVec,
-    dependency: &str,
+    dependency: &MachODep,
     deptree: &mut DepTree,
     depp: usize,
     preload: bool,
-) {
-    let mut dependency = dependency.replace("@executable_path", config.executable_path);
-    dependency = dependency.replace("@loader_path", loader_path);
+) -> Result<(), RlddError> {
+    config.limits.check(deptree.arena.len())?;
+    if config.limits.stopped() {
+        return Ok(());
+    }
+
+    let mut name = dependency
+        .name
+        .replace("@executable_path", config.executable_path);
+    name = name.replace("@loader_path", loader_path);
 
-    if dependency.contains("@rpath") {
+    let mut trace = SearchTrace::new();
+
+    let resolved = if name.contains("@rpath") {
+        let dependency = name.clone();
+        let mut resolved = None;
         for rpath in rpaths {
-            let mut newdependency = dependency.replace("@rpath", rpath.path.as_str());
-            if resolve_dependency_1(config, &mut newdependency, true, deptree, depp, preload) {
-                return;
+            name = dependency.replace("@rpath", rpath.path.as_str());
+            resolved =
+                resolve_dependency_1(config, &mut name, true, deptree, depp, preload, &mut trace);
+            if resolved.is_some() {
+                break;
             }
         }
-        return;
-    }
+        resolved
+    } else {
+        resolve_dependency_1(config, &mut name, false, deptree, depp, preload, &mut trace)
+    };
 
-    resolve_dependency_1(config, &mut dependency, false, deptree, depp, preload);
-}
+    if let Some((elc, depd)) = resolved {
+        deptree.arena[depd].val.info = config.object_info(&elc);
+        if let Some(warning) = check_compatibility_version(dependency, &elc) {
+            deptree.arena[depd].val.warnings.push(warning);
+        }
 
-fn resolve_dependency_1(
-    config: &Config,
-    dependency: &mut String,
-    rpath: bool,
-    deptree: &mut DepTree,
-    depp: usize,
-    preload: bool,
-) -> bool {
-    let elc = resolve_dependency_2(config, dependency, rpath, deptree, depp, preload);
-    if let Some((elc, depd)) = elc {
-        let path = pathutils::get_path(&dependency).unwrap_or(String::new());
+        // Only the inserted library itself is handled as a preload, its dependencies are
+        // resolved as usual.
+        let path = pathutils::get_path(&name).unwrap_or(String::new());
         for dep in &elc.deps {
-            resolve_dependency(config, &path, &elc.rpath, dep, deptree, depd, preload);
+            resolve_dependency(config, &path, &elc.rpath, dep, deptree, depd, false)?;
         }
-        true
-    } else {
-        false
     }
+    Ok(())
 }
 
-fn resolve_overrides<P: AsRef<Path>>(
-    library_path: &search_path::SearchPathVec,
-    executable_path: &String,
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        version >> 16,
+        (version >> 8) & 0xff,
+        version & 0xff
+    )
+}
+
+// dyld refuses to load a dylib whose current version is lower than the compatibility
+// version recorded by the object that depends on it.
+fn check_compatibility_version(dependency: &MachODep, elc: &MachOInfo) -> Option<String> {
+    match elc.current_version {
+        Some(current_version) if current_version < dependency.compat_version => Some(format!(
+            "incompatible version: compatibility version {} required, current version is {}",
+            format_version(dependency.compat_version),
+            format_version(current_version)
+        )),
+        _ => None,
+    }
+}
+
+// Search the dependency file name on SEARCHPATHS, used for both the DYLD_LIBRARY_PATH
+// overrides and the extra directories.
+fn resolve_search_paths<P: AsRef<Path>>(
+    config: &Config,
+    searchpaths: &search_path::SearchPathVec,
+    mode: DepMode,
     path: &P,
     deptree: &mut DepTree,
     depp: usize,
+    trace: &mut SearchTrace,
 ) -> Option<(MachOInfo, usize)> {
     let filename = pathutils::get_name(&path);
-    for searchpath in library_path {
+    for searchpath in searchpaths {
         let newpath = Path::new(&searchpath.path).join(&filename);
-        if let Ok(OpenMachOFileResult::Object(elc)) = open_macho_file(&newpath, executable_path) {
+        let elc = match open_macho_file(&newpath, config.executable_path, config.arch) {
+            Ok(OpenMachOFileResult::Object(elc)) => Some(elc),
+            _ => None,
+        };
+        trace.push(SearchAttempt {
+            path: newpath.to_string_lossy().to_string(),
+            mode,
+            found: elc.is_some(),
+            rejected: None,
+        });
+        if let Some(elc) = elc {
             let depd = deptree.addnode(
                 DepNode {
                     path: pathutils::get_path(&newpath),
+                    trace: config.keep_trace(&filename, trace),
                     name: filename,
-                    mode: DepMode::LdLibraryPath,
+                    mode,
                     found: false,
+                    warnings: Vec::new(),
+                    info: Vec::new(),
+                    file_id: None,
                 },
                 depp,
             );
@@ -224,13 +448,14 @@ fn resolve_overrides<P: AsRef<Path>>(
     None
 }
 
-fn resolve_dependency_2(
+fn resolve_dependency_1(
     config: &Config,
     dependency: &mut String,
     rpath: bool,
     deptree: &mut DepTree,
     depp: usize,
     preload: bool,
+    trace: &mut SearchTrace,
 ) -> Option<(MachOInfo, usize)> {
     // To avoid circular dependencies, check if deptree already containts the dependency.
     if deptree.contains(dependency) {
@@ -240,18 +465,27 @@ fn resolve_dependency_2(
     let path = Path::new(&dependency);
 
     // First check overrides: DYLD_LIBRARY_PATH paths.
-    if let Some((elc, depd)) = resolve_overrides(
+    if let Some((elc, depd)) = resolve_search_paths(
+        config,
         config.library_path,
-        config.executable_path,
+        DepMode::LdLibraryPath,
         &path,
         deptree,
         depp,
+        trace,
     ) {
         return Some((elc, depd));
     }
 
     // Then try the dyld system cache, if existent.
-    if let Some(elc) = config.cache.get(dependency, config.executable_path) {
+    let elc = config.cache.get(dependency, config.executable_path);
+    trace.push(SearchAttempt {
+        path: dependency.to_string(),
+        mode: DepMode::LdCache,
+        found: elc.is_some(),
+        rejected: None,
+    });
+    if let Some(elc) = elc {
         if resolve_dependency_check_found(dependency, deptree, depp, config.all) {
             return None;
         }
@@ -259,37 +493,73 @@ fn resolve_dependency_2(
         let depd = deptree.addnode(
             DepNode {
                 path: pathutils::get_path(&path),
+                trace: config.keep_trace(&name, trace),
                 name,
                 mode: DepMode::LdCache,
                 found: false,
+                warnings: Vec::new(),
+                info: Vec::new(),
+                file_id: None,
             },
             depp,
         );
         return Some((elc, depd));
     }
 
+    let mode = if preload {
+        DepMode::Preload
+    } else {
+        DepMode::Direct
+    };
+
     // The try filesystem.
     let elc = if path.is_absolute() {
-        match open_macho_file(&path, config.executable_path).ok() {
+        let elc = match open_macho_file(&path, config.executable_path, config.arch).ok() {
             Some(OpenMachOFileResult::Object(obj)) => Some(obj),
             _ => None,
-        }
+        };
+        trace.push(SearchAttempt {
+            path: dependency.to_string(),
+            mode,
+            found: elc.is_some(),
+            rejected: None,
+        });
+        elc
     } else {
         None
     };
 
     let path = if elc.is_none() {
-        // The dependency library does not exist.
         if !rpath {
+            // Finally the extra directories, which dyld does not know about.
+            if let Some((elc, depd)) = resolve_search_paths(
+                config,
+                config.extra_dirs,
+                DepMode::ExtraDirs,
+                &path,
+                deptree,
+                depp,
+                trace,
+            ) {
+                return Some((elc, depd));
+            }
+
+            // The dependency library does not exist.
+            let name = pathutils::get_name(&path);
             deptree.addnode(
                 DepNode {
                     path: pathutils::get_path(&path),
-                    name: pathutils::get_name(&path),
+                    trace: config.keep_trace(&name, trace),
+                    name,
                     mode: DepMode::NotFound,
                     found: false,
+                    warnings: Vec::new(),
+                    info: Vec::new(),
+                    file_id: None,
                 },
                 depp,
             );
+            config.limits.not_found();
         }
         return None;
     } else {
@@ -299,16 +569,17 @@ fn resolve_dependency_2(
     // Update the dependency path for the case of rpath substitution.
     *dependency = path.to_string_lossy().to_string();
 
+    let name = pathutils::get_name(&path);
     let depd = deptree.addnode(
         DepNode {
             path: pathutils::get_path(&path),
-            name: pathutils::get_name(&path),
-            mode: if preload {
-                DepMode::Preload
-            } else {
-                DepMode::Direct
-            },
+            trace: config.keep_trace(&name, trace),
+            name,
+            mode,
             found: false,
+            warnings: Vec::new(),
+            info: Vec::new(),
+            file_id: None,
         },
         depp,
     );
@@ -330,6 +601,10 @@ fn resolve_dependency_check_found(
                     name: entry.name,
                     mode: entry.mode,
                     found: true,
+                    warnings: Vec::new(),
+                    info: Vec::new(),
+                    file_id: None,
+                    trace: SearchTrace::new(),
                 },
                 depp,
             );
@@ -343,41 +618,70 @@ fn resolve_dependency_check_found(
 fn open_macho_file<P: AsRef<Path>>(
     filename: &P,
     executable_path: &String,
-) -> Result<OpenMachOFileResult, std::io::Error> {
+    arch: Option<&str>,
+) -> Result<OpenMachOFileResult, RlddError> {
     let file = fs::File::open(filename)?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }?;
 
-    let mmap = match unsafe { memmap2::Mmap::map(&file) } {
-        Ok(mmap) => mmap,
-        Err(_) => return Err(Error::new(ErrorKind::Other, "Failed to map file")),
-    };
-
-    match parse_object(&mmap, 0, executable_path) {
-        Ok(ParseObjectResult::Object(omf)) => Ok(OpenMachOFileResult::Object(omf)),
-        Ok(ParseObjectResult::Cache(images)) => Ok(OpenMachOFileResult::Cache(DyldCache {
+    // Not a supported object (RlddError::Parse), which --recursive skips silently.
+    match parse_object(&mmap, 0, executable_path, arch)? {
+        ParseObjectResult::Object(omf) => Ok(OpenMachOFileResult::Object(omf)),
+        ParseObjectResult::Cache(images) => Ok(OpenMachOFileResult::Cache(DyldCache {
             images,
             mmap: Some(mmap),
+            subcaches: Vec::new(),
         })),
-        Err(e) => Err(Error::new(ErrorKind::Other, e)),
     }
 }
 
+// Open the file and parse all the architecture slices of an universal binary.
+fn open_macho_file_arches<P: AsRef<Path>>(
+    filename: &P,
+    executable_path: &String,
+) -> Result<Vec<(&'static str, MachObj)>, RlddError> {
+    let file = fs::File::open(filename)?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }?;
+
+    let r = match object::FileKind::parse(&*mmap) {
+        Ok(object::FileKind::MachOFat32) => FatHeader::parse_arch32(&*mmap)
+            .handle_err()
+            .map(|arches| parse_macho_fat_arches(&mmap, arches, executable_path)),
+        Ok(object::FileKind::MachOFat64) => FatHeader::parse_arch64(&*mmap)
+            .handle_err()
+            .map(|arches| parse_macho_fat_arches(&mmap, arches, executable_path)),
+        Ok(object::FileKind::MachO32) | Ok(object::FileKind::MachO64) => {
+            let arch = object::File::parse(&*mmap)
+                .map(|f| arch_name(object::Object::architecture(&f)))
+                .unwrap_or("unknown");
+            match parse_object(&mmap, 0, executable_path, None) {
+                Ok(ParseObjectResult::Object(omf)) => Some(vec![(arch, omf)]),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    r.ok_or(RlddError::Parse("Invalid MachO file".to_string()))
+}
+
 fn parse_object(
     data: &[u8],
     offset: u64,
     executable_path: &String,
-) -> Result<ParseObjectResult, &'static str> {
+    arch: Option<&str>,
+) -> Result<ParseObjectResult, RlddError> {
     let kind = match object::FileKind::parse_at(data, offset) {
         Ok(file) => file,
-        Err(_err) => return Err("Failed to parse file"),
+        Err(_err) => return Err(RlddError::Parse("Failed to parse file".to_string())),
     };
 
     match kind {
         object::FileKind::MachO32 => parse_macho32(data, offset, executable_path),
         object::FileKind::MachO64 => parse_macho64(data, offset, executable_path),
-        object::FileKind::MachOFat32 => parse_macho_fat32(data, executable_path),
-        object::FileKind::MachOFat64 => parse_macho_fat64(data, executable_path),
+        object::FileKind::MachOFat32 => parse_macho_fat32(data, executable_path, arch),
+        object::FileKind::MachOFat64 => parse_macho_fat64(data, executable_path, arch),
         object::FileKind::DyldCache => parse_dyld_cache(data),
-        _ => Err("Invalid object"),
+        _ => Err(RlddError::Parse("Invalid object".to_string())),
     }
 }
 
@@ -398,42 +702,44 @@ fn parse_macho32(
     data: &[u8],
     offset: u64,
     executable_path: &str,
-) -> Result<ParseObjectResult, &'static str> {
+) -> Result<ParseObjectResult, RlddError> {
     if let Some(macho) = MachHeader32::parse(data, offset).handle_err() {
         return parse_macho(macho, data, offset, executable_path);
     }
-    Err("Invalid Mach-O 32 object")
+    Err(RlddError::Parse("Invalid Mach-O 32 object".to_string()))
 }
 
 fn parse_macho64(
     data: &[u8],
     offset: u64,
     executable_path: &str,
-) -> Result<ParseObjectResult, &'static str> {
+) -> Result<ParseObjectResult, RlddError> {
     if let Some(macho) = MachHeader64::parse(data, offset).handle_err() {
         return parse_macho(macho, data, offset, executable_path);
     }
-    Err("Invalid Mach-O 64 object")
+    Err(RlddError::Parse("Invalid Mach-O 64 object".to_string()))
 }
 
 fn parse_macho_fat32(
     data: &[u8],
     executable_path: &String,
-) -> Result<ParseObjectResult, &'static str> {
+    arch: Option<&str>,
+) -> Result<ParseObjectResult, RlddError> {
     if let Some(arches) = FatHeader::parse_arch32(data).handle_err() {
-        return parse_macho_fat(data, arches, executable_path);
+        return parse_macho_fat(data, arches, executable_path, arch);
     }
-    Err("Invalid FAT Mach-O 32 object")
+    Err(RlddError::Parse("Invalid FAT Mach-O 32 object".to_string()))
 }
 
 fn parse_macho_fat64(
     data: &[u8],
     executable_path: &String,
-) -> Result<ParseObjectResult, &'static str> {
+    arch: Option<&str>,
+) -> Result<ParseObjectResult, RlddError> {
     if let Some(arches) = FatHeader::parse_arch64(data).handle_err() {
-        return parse_macho_fat(data, arches, executable_path);
+        return parse_macho_fat(data, arches, executable_path, arch);
     }
-    Err("Invalid FAT Mach-O 64 object")
+    Err(RlddError::Parse("Invalid FAT Mach-O 64 object".to_string()))
 }
 
 fn check_current_arch(arch: object::Architecture) -> bool {
@@ -449,19 +755,63 @@ fn check_current_arch(arch: object::Architecture) -> bool {
         }
 }
 
-fn parse_macho_fat<FatArch: object::read::macho::FatArch>(
+fn arch_name(arch: object::Architecture) -> &'static str {
+    match arch {
+        object::Architecture::Aarch64 => "arm64",
+        object::Architecture::Arm => "arm",
+        object::Architecture::X86_64 => "x86_64",
+        object::Architecture::I386 => "i386",
+        object::Architecture::PowerPc64 => "ppc64",
+        object::Architecture::PowerPc => "ppc",
+        _ => "unknown",
+    }
+}
+
+fn parse_macho_fat_arches<FatArch: object::read::macho::FatArch>(
     data: &[u8],
     arches: &[FatArch],
     executable_path: &String,
-) -> Result<ParseObjectResult, &'static str> {
+) -> Vec<(&'static str, MachObj)> {
+    let mut r = Vec::new();
     for arch in arches {
-        if check_current_arch(arch.architecture()) {
-            if let Some(fatdata) = arch.data(data).handle_err() {
-                return parse_object(fatdata, 0, executable_path);
+        if let Some(fatdata) = arch.data(data).handle_err() {
+            if let Ok(ParseObjectResult::Object(omf)) =
+                parse_object(fatdata, 0, executable_path, None)
+            {
+                r.push((arch_name(arch.architecture()), omf));
             }
         }
     }
-    Err("Invalid FAT Mach-O architecture")
+    r
+}
+
+// Select the slice for the host architecture, or the one named ARCH (as printed by
+// --all-arches) if set.
+fn parse_macho_fat<FatArch: object::read::macho::FatArch>(
+    data: &[u8],
+    arches: &[FatArch],
+    executable_path: &String,
+    arch: Option<&str>,
+) -> Result<ParseObjectResult, RlddError> {
+    for fatarch in arches {
+        let selected = match arch {
+            Some(name) => arch_name(fatarch.architecture()) == name,
+            None => check_current_arch(fatarch.architecture()),
+        };
+        if selected {
+            if let Some(fatdata) = fatarch.data(data).handle_err() {
+                return parse_object(fatdata, 0, executable_path, None);
+            }
+        }
+    }
+    match arch {
+        Some(_) => Err(RlddError::Parse(
+            "Architecture not found in FAT Mach-O object".to_string(),
+        )),
+        None => Err(RlddError::Parse(
+            "Invalid FAT Mach-O architecture".to_string(),
+        )),
+    }
 }
 
 fn parse_macho<Mach: MachHeader<Endian = Endianness>>(
@@ -469,16 +819,25 @@ fn parse_macho<Mach: MachHeader<Endian = Endianness>>(
     data: &[u8],
     offset: u64,
     executable_path: &str,
-) -> Result<ParseObjectResult, &'static str> {
+) -> Result<ParseObjectResult, RlddError> {
     let mut deps = DepsVec::new();
     let mut rpath = search_path::SearchPathVec::new();
+    let mut id_dylib = None;
+    let mut current_version = None;
 
     if let Ok(endian) = header.endian() {
         if let Ok(mut commands) = header.load_commands(endian, data, offset) {
             while let Ok(Some(command)) = commands.next() {
                 match parse_load_command::<Mach>(endian, command) {
-                    Some((LoadCommand::Dylib, dylib)) => deps.push(dylib),
-                    Some((LoadCommand::Rpath, path)) => {
+                    Some(LoadCommand::Dylib(name, compat_version)) => deps.push(MachODep {
+                        name,
+                        compat_version,
+                    }),
+                    Some(LoadCommand::IdDylib(name, version)) => {
+                        id_dylib = Some(name);
+                        current_version = Some(version);
+                    }
+                    Some(LoadCommand::Rpath(path)) => {
                         let path = path.replace("@executable_path", executable_path);
                         rpath.add_path(path.as_str());
                     }
@@ -488,10 +847,15 @@ fn parse_macho<Mach: MachHeader<Endian = Endianness>>(
         }
     }
 
-    Ok(ParseObjectResult::Object(MachOInfo { rpath, deps }))
+    Ok(ParseObjectResult::Object(MachOInfo {
+        rpath,
+        deps,
+        id_dylib,
+        current_version,
+    }))
 }
 
-fn parse_dyld_cache(data: &[u8]) -> Result<ParseObjectResult, &'static str> {
+fn parse_dyld_cache(data: &[u8]) -> Result<ParseObjectResult, RlddError> {
     if let Some(header) = DyldCacheHeader::<Endianness>::parse(data).handle_err() {
         if let Some((_, endian)) = header.parse_magic().handle_err() {
             if let Some(images) = header.images(endian, data).handle_err() {
@@ -501,7 +865,7 @@ fn parse_dyld_cache(data: &[u8]) -> Result<ParseObjectResult, &'static str> {
         }
     }
 
-    Err("Invalid dyld cache")
+    Err(RlddError::Parse("Invalid dyld cache".to_string()))
 }
 
 fn parse_dyld_cache_images(
@@ -509,7 +873,7 @@ fn parse_dyld_cache_images(
     data: &[u8],
     mappings: Option<&[DyldCacheMappingInfo<Endianness>]>,
     images: &[DyldCacheImageInfo<Endianness>],
-) -> Result<ParseObjectResult, &'static str> {
+) -> Result<ParseObjectResult, RlddError> {
     let mut cache = ImagesMap::new();
 
     for image in images {
@@ -517,7 +881,9 @@ fn parse_dyld_cache_images(
             .path(endian, data)
             .ok()
             .and_then(|s| str::from_utf8(s).ok().map(|s| s.to_string()));
-        let offset = mappings.and_then(|mappings| image.file_offset(endian, mappings).ok());
+        let offset = mappings
+            .and_then(|mappings| image.file_offset(endian, mappings).ok())
+            .map(|offset| (0, offset));
         if let Some(path) = path {
             cache.insert(path, offset);
         }
@@ -527,8 +893,9 @@ fn parse_dyld_cache_images(
 }
 
 enum LoadCommand {
-    Dylib,
-    Rpath,
+    Dylib(String, u32),   // Name and compatibility version.
+    IdDylib(String, u32), // Name and current version.
+    Rpath(String),
 }
 
 fn parse_string(data: Option<&[u8]>) -> Option<String> {
@@ -538,18 +905,26 @@ fn parse_string(data: Option<&[u8]>) -> Option<String> {
 fn parse_load_command<Mach: MachHeader>(
     endian: Mach::Endian,
     command: LoadCommandData<Mach::Endian>,
-) -> Option<(LoadCommand, String)> {
+) -> Option<LoadCommand> {
     if let Ok(variant) = command.variant() {
         match variant {
-            LoadCommandVariant::Dylib(x) | LoadCommandVariant::IdDylib(x) => {
+            LoadCommandVariant::Dylib(x) => {
                 if let Some(dylib) = parse_string(command.string(endian, x.dylib.name).ok()) {
-                    return Some((LoadCommand::Dylib, dylib));
+                    let compat_version = x.dylib.compatibility_version.get(endian);
+                    return Some(LoadCommand::Dylib(dylib, compat_version));
+                };
+                None
+            }
+            LoadCommandVariant::IdDylib(x) => {
+                if let Some(dylib) = parse_string(command.string(endian, x.dylib.name).ok()) {
+                    let current_version = x.dylib.current_version.get(endian);
+                    return Some(LoadCommand::IdDylib(dylib, current_version));
                 };
                 None
             }
             LoadCommandVariant::Rpath(x) => {
                 if let Some(rpath) = parse_string(command.string(endian, x.path).ok()) {
-                    return Some((LoadCommand::Rpath, rpath));
+                    return Some(LoadCommand::Rpath(rpath));
                 };
                 None
             }
@@ -559,3 +934,76 @@ fn parse_load_command<Mach: MachHeader>(
         None
     }
 }
+
+#[cfg(all(test, target_os = "macos"))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    // Build an empty dylib DIR/NAME, with its path as install name, passing ARGS to the
+    // compiler driver.
+    fn build_dylib(dir: &Path, name: &str, args: &[&str]) -> Result<PathBuf, std::io::Error> {
+        let output = dir.join(name);
+        let status = Command::new("cc")
+            .args(["-dynamiclib", "-o"])
+            .arg(&output)
+            .arg(format!("-Wl,-install_name,{}", output.display()))
+            .args(args)
+            .args(["-x", "c", "/dev/null"])
+            .status()?;
+        if !status.success() {
+            return Err(std::io::Error::other("failed to build test library"));
+        }
+        Ok(output)
+    }
+
+    fn resolve_options() -> ResolveOptions {
+        ResolveOptions {
+            ld_library_path: search_path::SearchPathVec::new(),
+            ld_preload: search_path::SearchPathVec::new(),
+            extra_dirs: search_path::SearchPathVec::new(),
+            platform: None,
+            also: Vec::new(),
+            isolated: true,
+            all: false,
+            explain: None,
+            verbose: false,
+            max_nodes: None,
+            timeout: None,
+            first_only: false,
+            install_name: false,
+            arch: None,
+        }
+    }
+
+    fn find_node<'a>(deptree: &'a DepTree, name: &str) -> Option<&'a DepNode> {
+        deptree
+            .arena
+            .iter()
+            .map(|n| &n.val)
+            .find(|n| n.name == name && !n.found)
+    }
+
+    #[test]
+    fn preload_dependencies() -> Result<(), std::io::Error> {
+        let tmpdir = TempDir::new()?;
+        let libdir = tmpdir.path().canonicalize()?;
+
+        let libbar = build_dylib(&libdir, "libbar.dylib", &[])?;
+        let libpre = build_dylib(&libdir, "libpre.dylib", &[libbar.to_str().unwrap()])?;
+        let libfoo = build_dylib(&libdir, "libfoo.dylib", &[])?;
+
+        let mut opts = resolve_options();
+        opts.ld_preload = search_path::from_preload(libpre.to_str().unwrap());
+        let mut cache = create_context(&opts);
+        let deptree = resolve_binary(&mut cache, &opts, libfoo.to_str().unwrap())?;
+
+        let dep = find_node(&deptree, "libpre.dylib").expect("libpre.dylib not in tree");
+        assert_eq!(dep.mode, DepMode::Preload);
+        let dep = find_node(&deptree, "libbar.dylib").expect("libbar.dylib not in tree");
+        assert_eq!(dep.mode, DepMode::Direct);
+        Ok(())
+    }
+}
@@ -1,12 +1,24 @@
 use argh::FromArgs;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
 
 mod printer;
 use printer::*;
 mod deptree;
+mod error;
+mod json;
+mod options;
 mod pathutils;
 mod search_path;
 use deptree::*;
+use error::RlddError;
+use options::ResolveOptions;
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+use options::{Libc, Mapping, Simulate, Sysroot};
 
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+mod archive;
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
 mod elf;
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
@@ -17,49 +29,246 @@ mod macho;
 #[cfg(target_os = "macos")]
 use macho::*;
 
-fn print_deps(p: &Printer, deps: &DepTree) {
+// Return which nodes should be printed: the ones KEEP selects plus their ancestors, so
+// the selected nodes are still shown in the tree context.
+fn filter_deps<F: Fn(&DepNode) -> bool>(deps: &DepTree, keep: F) -> Vec<bool> {
+    let mut visible = vec![false; deps.arena.len()];
+    // Children are always added to the arena after their parent.
+    for node in deps.arena.iter().rev() {
+        visible[node.idx] = keep(&node.val) || node.children.iter().any(|c| visible[*c]);
+    }
+    visible
+}
+
+// Order used to print the dependencies: 'dfs' follows the resolution order (each
+// dependency followed by its own dependencies), while 'bfs' prints all the dependencies
+// of one level before the next one.
+enum Order {
+    Dfs,
+    Bfs,
+}
+
+impl std::str::FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dfs" => Ok(Order::Dfs),
+            "bfs" => Ok(Order::Bfs),
+            _ => Err(format!("invalid order '{s}' (expected dfs or bfs)")),
+        }
+    }
+}
+
+// Return the CHILDREN to print, optionally sorted by name (the resolution order is not
+// changed).
+fn visible_children(
+    deps: &DepTree,
+    visible: &[bool],
+    children: &[usize],
+    sort: bool,
+) -> Vec<usize> {
+    let mut children: Vec<usize> = children.iter().copied().filter(|c| visible[*c]).collect();
+    if sort {
+        children.sort_by(|a, b| deps.arena[*a].val.name.cmp(&deps.arena[*b].val.name));
+    }
+    children
+}
+
+// The name of DEP, followed by the number of its direct dependencies with --show-counts.
+// The already found and not found dependencies are not expanded, so they have no count.
+fn dep_name(dep: &DepNode, children: usize, counts: bool) -> String {
+    if counts && !dep.found && dep.mode != DepMode::NotFound {
+        format!("{} ({children})", dep.name)
+    } else {
+        dep.name.clone()
+    }
+}
+
+fn print_deps(
+    p: &Printer,
+    deps: &DepTree,
+    visible: &[bool],
+    order: &Order,
+    sort: bool,
+    counts: bool,
+) {
     let bin = deps.arena.first().unwrap();
-    p.print_executable(&bin.val.path, &bin.val.name);
+    if !visible[bin.idx] {
+        return;
+    }
+    let name = dep_name(&bin.val, bin.children.len(), counts);
+    p.print_executable(&bin.val.path, &name, &bin.val.info);
 
-    let mut deptrace = Vec::<bool>::new();
-    print_deps_children(p, deps, &bin.children, &mut deptrace);
+    if p.align() {
+        p.set_column(mode_column(p, deps, visible, counts));
+    }
+
+    match order {
+        Order::Dfs => {
+            let mut deptrace = Vec::<bool>::new();
+            print_deps_children(p, deps, visible, &bin.children, &mut deptrace, sort, counts);
+        }
+        Order::Bfs => print_deps_bfs(p, deps, visible, sort, counts),
+    }
+    // Write the whole tree at once.
+    p.flush();
+}
+
+// Return the column the resolution modes are aligned to: the width of the widest visible
+// entry, including the tree indentation.
+fn mode_column(p: &Printer, deps: &DepTree, visible: &[bool], counts: bool) -> usize {
+    let mut depth = vec![0; deps.arena.len()];
+    let mut column = 0;
+    for dep in deps.arena.iter().skip(1) {
+        depth[dep.idx] = dep.parent.map_or(0, |p| depth[p]) + 1;
+        if visible[dep.idx] {
+            let name = dep_name(&dep.val, dep.children.len(), counts);
+            let width = p.entry_width(&dep.val, depth[dep.idx]) + name.len() - dep.val.name.len();
+            column = column.max(width);
+        }
+    }
+    column
+}
+
+fn print_dep(p: &Printer, dep: &DepNode, name: &String, deptrace: &Vec<bool>) {
+    if p.has_format() {
+        p.print_formatted(dep, deptrace);
+    } else if dep.mode == deptree::DepMode::NotFound {
+        p.print_not_found(&dep.name, &dep.trace, deptrace);
+    } else if dep.found {
+        p.print_already_found(
+            &dep.name,
+            dep.path.as_ref().unwrap(),
+            &dep.mode.to_string(),
+            &dep.info,
+            deptrace,
+        );
+    } else {
+        p.print_dependency(
+            name,
+            dep.path.as_ref().unwrap(),
+            &dep.mode.to_string(),
+            &dep.info,
+            deptrace,
+        );
+    }
+}
+
+// The nodes are indented by their depth, but without the tree branches since siblings
+// are not printed together.
+fn print_deps_bfs(p: &Printer, deps: &DepTree, visible: &[bool], sort: bool, counts: bool) {
+    let mut queue = std::collections::VecDeque::from([(0, 0)]);
+    while let Some((idx, depth)) = queue.pop_front() {
+        if idx != 0 {
+            let dep = &deps.arena[idx];
+            let name = dep_name(&dep.val, dep.children.len(), counts);
+            print_dep(p, &dep.val, &name, &vec![false; depth]);
+        }
+        for c in visible_children(deps, visible, &deps.arena[idx].children, sort) {
+            queue.push_back((c, depth + 1));
+        }
+    }
 }
 
 fn print_deps_children(
     p: &Printer,
     deps: &DepTree,
-    children: &Vec<usize>,
+    visible: &[bool],
+    children: &[usize],
     deptrace: &mut Vec<bool>,
+    sort: bool,
+    counts: bool,
 ) {
+    let children = visible_children(deps, visible, children, sort);
     let mut iter = children.iter().peekable();
     while let Some(c) = iter.next() {
         let dep = &deps.arena[*c];
         deptrace.push(children.len() > 1);
-        if dep.val.mode == deptree::DepMode::NotFound {
-            p.print_not_found(&dep.val.name, deptrace);
-        } else if dep.val.found {
-            p.print_already_found(
-                &dep.val.name,
-                dep.val.path.as_ref().unwrap(),
-                &dep.val.mode.to_string(),
-                deptrace,
-            );
-        } else {
-            p.print_dependency(
-                &dep.val.name,
-                dep.val.path.as_ref().unwrap(),
-                &dep.val.mode.to_string(),
-                deptrace,
-            );
-        }
+        let name = dep_name(&dep.val, dep.children.len(), counts);
+        print_dep(p, &dep.val, &name, deptrace);
         deptrace.pop();
 
         deptrace.push(children.len() > 1 && iter.peek().is_some());
-        print_deps_children(p, deps, &dep.children, deptrace);
+        print_deps_children(p, deps, visible, &dep.children, deptrace, sort, counts);
         deptrace.pop();
     }
 }
 
+fn print_explain(p: &Printer, deps: &DepTree, name: &String) {
+    // The search trace is only kept on the node that first resolved the dependency.
+    match deps
+        .arena
+        .iter()
+        .find(|d| d.val.name == *name && !d.val.found && d.val.mode != DepMode::Executable)
+    {
+        Some(dep) => p.print_explain(name, &dep.val.trace),
+        None => p.print_explain_missing(name),
+    }
+}
+
+fn print_warnings(p: &Printer, deps: &DepTree) {
+    for dep in &deps.arena {
+        for warning in &dep.val.warnings {
+            p.print_warning(&dep.val.name, warning);
+        }
+    }
+}
+
+fn print_rejected(p: &Printer, deps: &DepTree) {
+    for dep in &deps.arena {
+        for attempt in &dep.val.trace {
+            if let Some(reason) = &attempt.rejected {
+                p.print_note(
+                    &dep.val.name,
+                    &format!("found {} but rejected: {reason}", attempt.path),
+                );
+            }
+        }
+    }
+}
+
+// The object format and the options only available for it, reported by --version so
+// scripts can check them before use.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+const FORMAT: &str = "elf";
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+const CAPABILITIES: &[&str] = &[
+    "events",
+    "audit-rpath",
+    "security",
+    "show-arch",
+    "merge-hardlinks",
+    "metadata",
+    "no-canonicalize",
+    "check-symbols",
+    "unused",
+    "deep",
+    "cache",
+    "hwcap",
+    "warn-shadow",
+    "warn-conflict",
+    "warn-interpose",
+    "relaxed-soname",
+    "lint",
+    "object-type",
+    "needed-index",
+    "no-interp",
+    "sysroot",
+    "usr-prefix",
+    "libc",
+    "glibc-interp",
+    "map",
+    "archive",
+    "simulate",
+    "simulate-rpath",
+    "simulate-like",
+];
+#[cfg(target_os = "macos")]
+const FORMAT: &str = "mach-o";
+#[cfg(target_os = "macos")]
+const CAPABILITIES: &[&str] = &["all-arches", "dump-dyld-cache", "install-name", "arch"];
+
 #[derive(FromArgs)]
 /// Print shared objects dependencies
 struct Options {
@@ -83,14 +292,33 @@ struct Options {
     #[argh(option, default = "\"\".to_string()")]
     preload: String,
 
+    /// add a directory to search after all the loader ones (may be repeated).
+    #[argh(option)]
+    extra_dir: Vec<String>,
+
     /// set the value of $PLATFORM in rpath/runpath expansion.
     #[argh(option)]
     platform: Option<String>,
 
+    /// resolve NAME as an additional dependency of the executable, for instance a
+    /// dlopen'ed plugin (may be repeated).
+    #[argh(option)]
+    also: Vec<String>,
+
+    /// ignore the host loader configuration (the loader cache and preload file, the CPU
+    /// hwcaps, and the dyld shared cache), so only the command line options are used.
+    #[argh(switch)]
+    isolated: bool,
+
     /// show the resolved path instead of the library SONAME.
     #[argh(switch, short = 'p')]
     path: bool,
 
+    /// show the absolute path of each object as its label, in a single column (overrides
+    /// -p).
+    #[argh(switch)]
+    absolute: bool,
+
     /// print already resolved dependencies.
     #[argh(switch, short = 'a')]
     all: bool,
@@ -99,29 +327,1004 @@ struct Options {
     #[argh(switch, short = 'l')]
     ldd: bool,
 
+    /// align the resolution modes of the tree in a single column.
+    #[argh(switch)]
+    align: bool,
+
+    /// use N columns for each level of the tree (3 by default, at least 2).
+    #[argh(option)]
+    indent: Option<usize>,
+
+    /// only print the dependencies not found, along with the dependencies that lead to them.
+    #[argh(switch, short = 'q')]
+    quiet: bool,
+
+    /// show the number of direct dependencies of each object after its name (not with
+    /// -l).
+    #[argh(switch)]
+    show_counts: bool,
+
+    /// only print the dependencies resolved with MODE (for instance rpath, runpath, or
+    /// ld.so.cache, as shown in the tree), along with the dependencies that lead to
+    /// them (may be repeated).
+    #[argh(option)]
+    only_mode: Vec<DepMode>,
+
+    /// abort the resolution of an input once its tree has more than N nodes.
+    #[argh(option)]
+    max_nodes: Option<usize>,
+
+    /// abort the resolution of an input once it takes more than SECONDS.
+    #[argh(option)]
+    timeout: Option<u64>,
+
+    /// stop at the first dependency not found, print only the dependencies that lead to
+    /// it (the tree is partial), and exit with an error.
+    #[argh(switch)]
+    first_only: bool,
+
+    /// only validate the inputs for scripts: print nothing if every dependency is found,
+    /// otherwise print the dependencies that lead to the missing ones (as -q), and exit
+    /// with an error if a dependency is not found or an input can not be resolved.
+    #[argh(switch)]
+    check: bool,
+
+    /// print the differences between the resolved libraries of two binaries.
+    #[argh(switch)]
+    diff: bool,
+
+    /// expand each file argument as a glob pattern (for instance '/usr/lib/*.so.*').
+    #[argh(switch)]
+    glob: bool,
+
+    /// resolve every binary under the directory arguments, skipping the files that are
+    /// not binaries.
+    #[argh(switch, short = 'r')]
+    recursive: bool,
+
+    /// print the result of each input as a JSON object on its own line, as soon as it
+    /// is resolved.
+    #[argh(switch)]
+    jsonl: bool,
+
+    /// print each dependency as a JSON object on its own line (with its parent name,
+    /// mode, and path) as soon as it is resolved, instead of the tree.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    events: bool,
+
+    /// only print the number of unique resolved dependencies of each input.
+    #[argh(switch)]
+    count: bool,
+
+    /// with --count, also count the dependencies not found.
+    #[argh(switch)]
+    count_missing: bool,
+
+    /// only print the absolute path of every file needed to run the inputs (including
+    /// themselves and the interpreter), sorted and one per line.
+    #[argh(switch)]
+    manifest: bool,
+
+    /// only print a SHA-256 digest of the resolved dependencies of each input (their
+    /// absolute paths and contents), which changes if any loaded library does.
+    #[argh(switch)]
+    closure_hash: bool,
+
+    /// only allow resolved libraries under DIR, reporting the others as violations
+    /// (may be repeated).
+    #[argh(option)]
+    allowed_dir: Vec<String>,
+
+    /// write the output to FILE instead of stdout.
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// colorize the output: auto (default, only if writing to a terminal), always, or never.
+    #[argh(option, default = "ColorMode::Auto")]
+    color: ColorMode,
+
+    /// template for each dependency line, with the fields name, path, dir, mode, and info
+    /// between braces (for instance '{{name}} {{mode}} {{path}}').
+    #[argh(option)]
+    format: Option<Format>,
+
+    /// show the file size of each dependency, and the total size of the unique ones.
+    #[argh(switch)]
+    size: bool,
+
+    /// show the number of unique libraries, of dependency edges, and the maximum depth of
+    /// each tree.
+    #[argh(switch)]
+    stats: bool,
+
+    /// instead of the tree, print each directory the dependencies were resolved from
+    /// followed by the libraries found there.
+    #[argh(switch)]
+    by_dir: bool,
+
+    /// order to print the dependencies: dfs (default, each dependency followed by its own
+    /// dependencies) or bfs (all dependencies of one level before the next one).
+    #[argh(option, default = "Order::Dfs")]
+    order: Order,
+
+    /// sort the dependencies of each object by name when printing.
+    #[argh(switch)]
+    sort: bool,
+
+    /// explain the resolution mode labels used in the output.
+    #[argh(switch)]
+    legend: bool,
+
+    /// show the search locations tried, in order, to resolve the dependency NAME.
+    #[argh(option)]
+    explain: Option<String>,
+
+    /// report the libraries found but rejected (and why), the directories searched for
+    /// each dependency not found, and the loader cache used.
+    #[argh(switch, short = 'v')]
+    verbose: bool,
+
+    /// warn about relative or world-writable rpath/runpath entries.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    audit_rpath: bool,
+
+    /// show the hardening features (x86 CET, AArch64 BTI/PAC) of each object, and whether
+    /// it has text relocations or an executable stack.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    security: bool,
+
+    /// show the ELF class, machine, and OS ABI of each object, and the rejected candidates
+    /// for dependencies not found.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    show_arch: bool,
+
+    /// handle hardlinks to an already resolved library as already found.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    merge_hardlinks: bool,
+
+    /// show the dynamic section metadata of each object, such as DT_RELR usage and the
+    /// DT_FLAGS_1 loader flags.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    metadata: bool,
+
+    /// do not resolve symlinks on the input path, so $ORIGIN is the directory where the
+    /// argument itself lives.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    no_canonicalize: bool,
+
+    /// warn about undefined symbols not exported by any loaded object, including the ones
+    /// defined but with local binding or hidden visibility.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    check_symbols: bool,
+
+    /// warn about the DT_NEEDED entries of each object that it does not bind any symbol
+    /// to (over-linking).
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    unused: bool,
+
+    /// with --unused, also warn about the libraries only loaded because of unused
+    /// DT_NEEDED entries, which could be removed along with them.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    deep: bool,
+
+    /// use the loader cache FILE (for instance ld.so.cache) instead of the system one.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    cache: Option<String>,
+
+    /// select the loader cache entries for the glibc-hwcaps subdirectories in LIST (comma
+    /// separated, best first, for instance x86-64-v3,x86-64-v2) instead of the host CPU ones.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    hwcap: Option<String>,
+
+    /// warn about libraries in later search locations shadowed by the resolved one.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    warn_shadow: bool,
+
+    /// warn about a DT_SONAME resolved to more than one distinct file in the tree.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    warn_conflict: bool,
+
+    /// warn about the symbols needed by an object that more than one loaded object
+    /// exports, showing the one the loader binds to in load order.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    warn_interpose: bool,
+
+    /// accept a dependency whose DT_SONAME has additional version components (for
+    /// instance libfoo.so.1.2 for libfoo.so.1), with a warning, instead of rejecting it.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    relaxed_soname: bool,
+
+    /// warn if the input file name does not match its DT_SONAME, or if it uses DT_RPATH.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    lint: bool,
+
+    /// show whether the input is a PIE executable, a non-PIE executable, or a shared
+    /// library.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    object_type: bool,
+
+    /// show the position (starting at 0) of each dependency in the DT_NEEDED list of the
+    /// object requiring it, which sets the loader load and initialization order.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    needed_index: bool,
+
+    /// omit the program interpreter (for instance ld-linux-x86-64.so.2) from the output.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    no_interp: bool,
+
+    /// resolve against the root file system DIR (for instance an extracted container
+    /// image) instead of the host one; --cache is then a path inside DIR.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    sysroot: Option<String>,
+
+    /// use PREFIX instead of /usr for the secondary system directories.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    usr_prefix: Option<String>,
+
+    /// assume the C library is LIBC (glibc or musl) instead of detecting it from the
+    /// program interpreter.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    libc: Option<Libc>,
+
+    /// also treat NAME as a glibc program interpreter (for instance a loader of a new
+    /// architecture or a renamed one), so the ld.so.cache is used (may be repeated).
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    glibc_interp: Vec<String>,
+
+    /// resolve the DT_NEEDED SONAME to the file PATH instead of searching for it, as
+    /// SONAME=PATH (may be repeated).
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    map: Vec<Mapping>,
+
+    /// resolve every binary inside the tar (optionally gzip compressed) or zip archive
+    /// arguments, with the archive root as the --sysroot.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    archive: bool,
+
+    /// resolve each input as an executable with the comma separated DT_NEEDED entries in
+    /// it (for instance libfoo.so.1,libbar.so.2) instead of a file.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(switch)]
+    simulate: bool,
+
+    /// use PATHS as the DT_RPATH of the --simulate executables, with $ORIGIN being the
+    /// current directory.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    simulate_rpath: Option<String>,
+
+    /// take the class, machine, and program interpreter of the --simulate executables
+    /// from FILE instead of the rldd executable.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[argh(option)]
+    simulate_like: Option<String>,
+
+    /// resolve the dependencies of every architecture of an universal binary.
+    #[cfg(target_os = "macos")]
+    #[argh(switch)]
+    all_arches: bool,
+
+    /// list the install names of the images in the dyld shared cache and exit.
+    #[cfg(target_os = "macos")]
+    #[argh(switch)]
+    dump_dyld_cache: bool,
+
+    /// show the install name (LC_ID_DYLIB) of the input and of each dependency.
+    #[cfg(target_os = "macos")]
+    #[argh(switch)]
+    install_name: bool,
+
+    /// resolve the NAME architecture of universal binaries (for instance arm64 or x86_64)
+    /// instead of the host one.
+    #[cfg(target_os = "macos")]
+    #[argh(option)]
+    arch: Option<String>,
+
+    /// print the version, the target, and the options supported by this build as a JSON
+    /// object.
+    #[argh(switch)]
+    version: bool,
+
     #[argh(positional, greedy)]
     args: Vec<String>,
 }
 
-fn print_error(arg: &String, err: std::io::Error) -> String {
-    match err.kind() {
-        std::io::ErrorKind::NotFound => format!("{arg}: no such file or directory"),
-        std::io::ErrorKind::PermissionDenied => format!("{arg}: permission denied"),
+fn print_deptree(p: &Printer, deptree: &DepTree, opts: &Options, ropts: &ResolveOptions) {
+    let quiet = opts.quiet || opts.first_only;
+    let mut visible = if quiet || !opts.only_mode.is_empty() {
+        filter_deps(deptree, |d| {
+            (!quiet || d.mode == DepMode::NotFound)
+                && (opts.only_mode.is_empty() || opts.only_mode.contains(&d.mode))
+                && (opts.all || !d.found)
+        })
+    } else {
+        vec![true; deptree.arena.len()]
+    };
+    // The already found dependencies are also resolved for --stats, but only shown with -a.
+    if !opts.all {
+        for dep in deptree.arena.iter().filter(|d| d.val.found) {
+            visible[dep.idx] = false;
+        }
+    }
+    print_deps(
+        p,
+        deptree,
+        &visible,
+        &opts.order,
+        opts.sort,
+        opts.show_counts && !opts.ldd,
+    );
+    print_warnings(p, deptree);
+    if ropts.verbose {
+        print_rejected(p, deptree);
+    }
+    if let Some(explain) = &ropts.explain {
+        print_explain(p, deptree, explain);
+    }
+}
+
+// The resolved dependencies outside all the ALLOWED directories, as name and path.  The
+// dependencies not found have no location to check.
+fn outside_allowed_dirs<'a>(
+    deptree: &'a DepTree,
+    allowed: &[String],
+) -> Vec<(&'a String, &'a String)> {
+    deptree
+        .arena
+        .iter()
+        .filter(|dep| {
+            !matches!(dep.val.mode, DepMode::Executable | DepMode::NotFound) && !dep.val.found
+        })
+        .filter_map(|dep| Some((&dep.val.name, dep.val.path.as_ref()?)))
+        .filter(|(_, path)| !path.is_empty())
+        .filter(|(_, path)| {
+            !allowed
+                .iter()
+                .any(|dir| std::path::Path::new(path).starts_with(dir))
+        })
+        .collect()
+}
+
+// Report the resolved dependencies outside all the ALLOWED directories, returning
+// whether any was found.
+fn check_allowed_dirs(p: &Printer, deptree: &DepTree, allowed: &[String]) -> bool {
+    let outside = outside_allowed_dirs(deptree, allowed);
+    for (name, path) in &outside {
+        p.print_violation(name, path);
+    }
+    !outside.is_empty()
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+// Annotate each resolved dependency with its file size, and record the unique files
+// (by their canonical path) in SIZES for the total.
+fn add_sizes(deptree: &mut DepTree, sizes: &mut BTreeMap<std::path::PathBuf, u64>) {
+    for dep in deptree.arena.iter_mut() {
+        let val = &mut dep.val;
+        if val.found || val.mode == DepMode::Executable || val.mode == DepMode::NotFound {
+            continue;
+        }
+        let path = std::path::Path::new(val.path.as_deref().unwrap_or("")).join(&val.name);
+        if let Ok(size) = std::fs::metadata(&path).map(|m| m.len()) {
+            val.info.push(format!("{{{}}}", format_size(size)));
+            sizes.insert(std::fs::canonicalize(&path).unwrap_or(path), size);
+        }
+    }
+}
+
+// Add the input and resolved files of DEPTREE to MANIFEST, keeping the symbolic links
+// (such as the DT_SONAME ones) since the loader opens them by that name.
+fn add_manifest(deptree: &DepTree, manifest: &mut BTreeSet<std::path::PathBuf>) {
+    for dep in &deptree.arena {
+        // The already found nodes are either duplicates or synthetic (such as the musl
+        // loader).  The --simulate executables are not files.
+        let val = &dep.val;
+        if val.mode == DepMode::NotFound || val.path.is_none() {
+            continue;
+        }
+        let path = std::path::Path::new(val.path.as_deref().unwrap_or("")).join(&val.name);
+        manifest.insert(std::path::absolute(&path).unwrap_or(path));
+    }
+}
+
+fn collect_missing(deptree: &DepTree, missing: &mut BTreeSet<String>) {
+    for dep in &deptree.arena {
+        if dep.val.mode == DepMode::NotFound {
+            missing.insert(dep.val.name.clone());
+        }
+    }
+}
+
+// Return the unique resolved libraries, along with the directory each was found.
+fn flatten_deps(deptree: &DepTree) -> BTreeMap<String, String> {
+    deptree
+        .arena
+        .iter()
+        .filter(|d| d.val.mode != DepMode::Executable && d.val.mode != DepMode::NotFound)
+        .map(|d| (d.val.name.clone(), d.val.path.clone().unwrap_or_default()))
+        .collect()
+}
+
+// Return the unique resolved libraries grouped by the directory they were found.
+fn group_by_dir(deptree: &DepTree) -> BTreeMap<String, BTreeSet<String>> {
+    let mut dirs = BTreeMap::<String, BTreeSet<String>>::new();
+    for dep in deptree.arena.iter().skip(1) {
+        if let Some(path) = dep
+            .val
+            .path
+            .as_ref()
+            .filter(|_| dep.val.mode != DepMode::NotFound)
+        {
+            dirs.entry(path.clone())
+                .or_default()
+                .insert(dep.val.name.clone());
+        }
+    }
+    dirs
+}
+
+// Print the number of unique libraries (including the ones not found), the number of
+// dependency edges (each DT_NEEDED occurrence), and the maximum depth of DEPTREE, which
+// requires the already found dependencies to be in the tree.
+fn print_stats(p: &Printer, deptree: &DepTree) {
+    let unique: BTreeSet<&String> = deptree
+        .arena
+        .iter()
+        .filter(|d| d.val.mode != DepMode::Executable)
+        .map(|d| &d.val.name)
+        .collect();
+    let mut depth = vec![0; deptree.arena.len()];
+    for dep in deptree.arena.iter().skip(1) {
+        depth[dep.idx] = dep.parent.map_or(0, |p| depth[p]) + 1;
+    }
+    p.print_stats(
+        unique.len(),
+        deptree.arena.len() - 1,
+        depth.into_iter().max().unwrap_or(0),
+    );
+}
+
+// Return the SHA-256 digest of the sorted absolute paths of the dependencies of DEPTREE
+// along with the SHA-256 digest of each file, and of the names of the ones not found.
+fn closure_hash(deptree: &DepTree) -> String {
+    let mut entries = BTreeSet::new();
+    for dep in deptree.arena.iter().skip(1) {
+        let val = &dep.val;
+        if val.mode == DepMode::NotFound {
+            entries.insert(format!("{}\0not found\n", val.name));
+            continue;
+        }
+        let path = std::path::Path::new(val.path.as_deref().unwrap_or("")).join(&val.name);
+        let path = std::path::absolute(&path).unwrap_or(path);
+        let content = match std::fs::read(&path) {
+            Ok(data) => format!("{:x}", Sha256::digest(data)),
+            Err(_) => "unreadable".to_string(),
+        };
+        entries.insert(format!("{}\0{content}\n", path.to_string_lossy()));
+    }
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(entry.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Print the dependency closure digest of DEPTREE, prefixed by the input ARG (and the
+// architecture) if there are multiple results.
+fn print_closure_hash(
+    p: &Printer,
+    opts: &Options,
+    arg: &str,
+    arch: Option<&str>,
+    deptree: &DepTree,
+) {
+    let digest = closure_hash(deptree);
+    match arch {
+        Some(arch) => p.print_line(&format!("{arg} [{arch}]: {digest}")),
+        None if opts.args.len() > 1 => p.print_line(&format!("{arg}: {digest}")),
+        None => p.print_line(&digest),
+    }
+}
+
+// Print the number of unique dependencies of DEPTREE, prefixed by the input ARG (and
+// the architecture) if there are multiple results.
+fn print_count(p: &Printer, opts: &Options, arg: &str, arch: Option<&str>, deptree: &DepTree) {
+    let mut count = flatten_deps(deptree).len();
+    if opts.count_missing {
+        let mut missing = BTreeSet::new();
+        collect_missing(deptree, &mut missing);
+        count += missing.len();
+    }
+    match arch {
+        Some(arch) => p.print_line(&format!("{arg} [{arch}]: {count}")),
+        None if opts.args.len() > 1 => p.print_line(&format!("{arg}: {count}")),
+        None => p.print_line(&count.to_string()),
+    }
+}
+
+// The --stats, --show-counts, --unused, and --warn-interpose analysis need the already
+// found dependencies in the tree, which are only printed with --all.
+fn resolve_all(opts: &Options) -> bool {
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    if opts.unused || opts.warn_interpose {
+        return true;
+    }
+    opts.all || ((opts.stats || opts.show_counts) && !opts.jsonl && !opts.count)
+}
+
+// With --events the dependencies are printed while resolving, so there is neither a tree
+// nor a summary to print afterwards.
+#[cfg_attr(target_os = "macos", allow(unused_variables))]
+fn events(opts: &Options) -> bool {
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    return opts.events;
+    #[cfg(not(all(target_family = "unix", not(target_os = "macos"))))]
+    return false;
+}
+
+fn print_diff(p: &Printer, ctx: &mut Context, ropts: &ResolveOptions, args: &[String]) {
+    if args.len() != 2 {
+        eprintln!("error: --diff expects two file arguments");
+        std::process::exit(1);
+    }
+
+    let mut deps = Vec::new();
+    for arg in args {
+        match resolve_binary(ctx, ropts, arg.as_str()) {
+            Ok(deptree) => deps.push(flatten_deps(&deptree)),
+            Err(e) => {
+                eprintln!("error: {}", print_error(arg, e));
+                std::process::exit(1);
+            }
+        }
+    }
+    p.print_diff(&deps[0], &deps[1]);
+}
+
+// State accumulated over all inputs.
+struct Summary {
+    // Unique not found dependencies.
+    missing: BTreeSet<String>,
+    // Unique resolved files, for --size.
+    sizes: BTreeMap<std::path::PathBuf, u64>,
+    // Whether a dependency is outside the --allowed-dir ones.
+    violation: bool,
+    // Whether an input could not be resolved, for --check.
+    failed: bool,
+    // Resolution modes shown, for --legend.
+    modes: Vec<DepMode>,
+    // Absolute paths of the inputs and of the resolved files, for --manifest.
+    manifest: BTreeSet<std::path::PathBuf>,
+}
+
+fn print_result(
+    p: &Printer,
+    opts: &Options,
+    ropts: &ResolveOptions,
+    arg: &str,
+    arch: Option<&str>,
+    mut deptree: DepTree,
+    summary: &mut Summary,
+) {
+    if opts.size {
+        add_sizes(&mut deptree, &mut summary.sizes);
+    }
+    if events(opts) {
+        return;
+    }
+    if opts.manifest {
+        add_manifest(&deptree, &mut summary.manifest);
+    } else if opts.closure_hash {
+        print_closure_hash(p, opts, arg, arch, &deptree);
+    } else if opts.count {
+        print_count(p, opts, arg, arch, &deptree);
+    } else if opts.jsonl {
+        p.print_json_line(&json::deptree(arg, arch, &deptree));
+    } else if opts.by_dir {
+        if let Some(arch) = arch {
+            p.print_arch(arch);
+        }
+        let bin = &deptree.arena[0].val;
+        p.print_executable(&bin.path, &bin.name, &bin.info);
+        let mut missing = BTreeSet::new();
+        collect_missing(&deptree, &mut missing);
+        p.print_by_dir(&group_by_dir(&deptree), &missing);
+    } else {
+        if let Some(arch) = arch {
+            p.print_arch(arch);
+        }
+        print_deptree(p, &deptree, opts, ropts);
+        if opts.stats {
+            print_stats(p, &deptree);
+        }
+    }
+    collect_missing(&deptree, &mut summary.missing);
+    for dep in &deptree.arena {
+        let labeled = !matches!(dep.val.mode, DepMode::Executable | DepMode::NotFound);
+        if labeled && !summary.modes.contains(&dep.val.mode) {
+            summary.modes.push(dep.val.mode);
+        }
+    }
+    if !opts.allowed_dir.is_empty() {
+        summary.violation |= check_allowed_dirs(p, &deptree, &opts.allowed_dir);
+    }
+    // Make the output of each input available as soon as it is resolved.
+    p.flush();
+}
+
+fn print_result_error(p: &Printer, opts: &Options, arg: &String, err: RlddError) {
+    if opts.jsonl || events(opts) {
+        p.print_json_line(&json::error(arg, &err.to_string()));
+    } else {
+        eprintln!("error: {}", print_error(arg, err));
+    }
+}
+
+// Expand the glob PATTERNS, returning the matched files along with the patterns that are
+// invalid or do not match anything.
+fn expand_globs(patterns: &[String]) -> (Vec<String>, Vec<(String, std::io::Error)>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for pattern in patterns {
+        let paths = match glob::glob(pattern) {
+            Ok(paths) => paths,
+            Err(e) => {
+                errors.push((pattern.clone(), std::io::Error::other(e.to_string())));
+                continue;
+            }
+        };
+        let matched: Vec<String> = paths
+            .filter_map(Result::ok)
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        if matched.is_empty() {
+            errors.push((pattern.clone(), std::io::Error::other("no files matched")));
+        }
+        files.extend(matched);
+    }
+    (files, errors)
+}
+
+// Return the regular files under the directories in ARGS (or the argument itself if it
+// is not a directory), sorted and without following symbolic links, along with the
+// directories that could not be read.
+fn walk_dirs(args: &[String]) -> (Vec<String>, Vec<(String, std::io::Error)>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut dirs: Vec<std::path::PathBuf> = Vec::new();
+    for arg in args {
+        if std::path::Path::new(arg).is_dir() {
+            dirs.push(arg.into());
+        } else {
+            files.push(arg.clone());
+        }
+    }
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push((dir.to_string_lossy().to_string(), e));
+                continue;
+            }
+        };
+        let mut entries: Vec<std::fs::DirEntry> = entries.filter_map(Result::ok).collect();
+        entries.sort_by_key(|e| e.path());
+        // Visit the subdirectories in order after the files.
+        for entry in entries.iter().rev() {
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => dirs.push(entry.path()),
+                _ => {}
+            }
+        }
+        for entry in entries {
+            match (entry.file_type(), entry.metadata()) {
+                (Ok(t), Ok(meta)) if t.is_file() && meta.len() > 0 => {
+                    files.push(entry.path().to_string_lossy().to_string())
+                }
+                _ => {}
+            }
+        }
+    }
+    (files, errors)
+}
+
+// An --archive argument extracted to a temporary directory, removed once dropped.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+struct Archive {
+    name: String,
+    root: tempfile::TempDir,
+    // Whether any of the extracted files is a binary.
+    binaries: std::cell::Cell<bool>,
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+impl Archive {
+    fn sysroot(&self, opts: &Options) -> Sysroot {
+        Sysroot {
+            root: self.root.path().to_string_lossy().to_string(),
+            cache_path: opts.cache.clone(),
+            usr_prefix: opts.usr_prefix.clone(),
+        }
+    }
+
+    // Return the name of the extracted FILE as ARCHIVE:/PATH, or None if FILE is not
+    // from this archive.
+    fn member(&self, file: &str) -> Option<String> {
+        std::path::Path::new(file)
+            .strip_prefix(self.root.path())
+            .ok()
+            .map(|path| format!("{}:/{}", self.name, path.to_string_lossy()))
+    }
+}
+
+// Extract each archive in ARGS and return them along with their regular files, and the
+// archives that could not be extracted.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+fn extract_archives(args: &[String]) -> (Vec<Archive>, Vec<String>, Vec<(String, std::io::Error)>) {
+    let mut archives = Vec::new();
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for arg in args {
+        let root = match tempfile::tempdir() {
+            Ok(root) => root,
+            Err(e) => {
+                errors.push((arg.clone(), e));
+                continue;
+            }
+        };
+        if let Err(e) = archive::extract(arg, root.path()) {
+            errors.push((arg.clone(), e.into()));
+            continue;
+        }
+        let (archive_files, archive_errors) =
+            walk_dirs(&[root.path().to_string_lossy().to_string()]);
+        files.extend(archive_files);
+        errors.extend(archive_errors);
+        archives.push(Archive {
+            name: arg.clone(),
+            root,
+            binaries: std::cell::Cell::new(false),
+        });
+    }
+    (archives, files, errors)
+}
+
+// A --usr-prefix without a --sysroot applies to the host root.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+fn sysroot(opts: &Options) -> Option<Sysroot> {
+    if opts.sysroot.is_none() && opts.usr_prefix.is_none() {
+        return None;
+    }
+    Some(Sysroot {
+        root: opts.sysroot.clone().unwrap_or("/".to_string()),
+        cache_path: opts.cache.clone(),
+        usr_prefix: opts.usr_prefix.clone(),
+    })
+}
+
+fn print_error(arg: &String, err: RlddError) -> String {
+    match err {
+        RlddError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            format!("{arg}: no such file or directory")
+        }
+        RlddError::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            format!("{arg}: permission denied")
+        }
         _ => format!("{arg}: {err}"),
     }
 }
 
 fn main() {
-    let opts: Options = argh::from_env();
+    let mut opts: Options = argh::from_env();
+    if opts.version {
+        println!("{}", json::version(FORMAT, CAPABILITIES));
+        return;
+    }
+    // --check is a preset of the quiet output plus the exit status.
+    if opts.check {
+        opts.quiet = true;
+    }
+
+    let mut arg_errors = Vec::new();
+    if opts.glob {
+        (opts.args, arg_errors) = expand_globs(&opts.args);
+    }
+    if opts.recursive {
+        let (files, errors) = walk_dirs(&opts.args);
+        opts.args = files;
+        arg_errors.extend(errors);
+    }
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    let archives = if opts.archive {
+        if opts.sysroot.is_some() {
+            eprintln!("error: --archive can not be used with --sysroot");
+            std::process::exit(1);
+        }
+        let (archives, files, errors) = extract_archives(&opts.args);
+        opts.args = files;
+        arg_errors.extend(errors);
+        archives
+    } else {
+        Vec::new()
+    };
+
+    let mut printer = match printer::create(
+        opts.path,
+        opts.ldd,
+        opts.align,
+        opts.args.len() == 1,
+        opts.format.take(),
+        &opts.output,
+        &opts.color,
+    ) {
+        Ok(printer) => printer,
+        Err(e) => {
+            eprintln!(
+                "error: {}",
+                print_error(opts.output.as_ref().unwrap(), e.into())
+            );
+            std::process::exit(1);
+        }
+    };
+    if let Some(indent) = opts.indent {
+        printer.set_indent(indent);
+    }
+    printer.set_absolute(opts.absolute);
+    // The --events hook prints while the tree is resolved.
+    let printer = Rc::new(printer);
 
-    let printer = printer::create(opts.path, opts.ldd, opts.args.len() == 1);
+    #[cfg_attr(target_os = "macos", allow(unused_mut))]
+    let mut ropts = ResolveOptions {
+        ld_library_path: search_path::from_string(&opts.library_path, &[':']),
+        ld_preload: search_path::from_preload(&opts.preload),
+        extra_dirs: search_path::from_vec(&opts.extra_dir),
+        platform: opts.platform.clone(),
+        also: opts.also.clone(),
+        isolated: opts.isolated,
+        all: resolve_all(&opts),
+        explain: opts.explain.clone(),
+        verbose: opts.verbose,
+        max_nodes: opts.max_nodes,
+        timeout: opts.timeout.map(std::time::Duration::from_secs),
+        first_only: opts.first_only,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        audit_rpath: opts.audit_rpath,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        security: opts.security,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        show_arch: opts.show_arch,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        merge_hardlinks: opts.merge_hardlinks,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        metadata: opts.metadata,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        no_canonicalize: opts.no_canonicalize,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        check_symbols: opts.check_symbols,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        unused: opts.unused,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        deep: opts.deep,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        cache: opts.cache.clone(),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        hwcap: opts.hwcap.as_ref().map(|h| {
+            h.split(',')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        }),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        warn_shadow: opts.warn_shadow,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        warn_conflict: opts.warn_conflict,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        warn_interpose: opts.warn_interpose,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        relaxed_soname: opts.relaxed_soname,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        lint: opts.lint,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        object_type: opts.object_type,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        needed_index: opts.needed_index,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        no_interp: opts.no_interp,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        sysroot: sysroot(&opts),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        simulate: opts.simulate.then(|| Simulate {
+            like: opts.simulate_like.clone(),
+            rpath: opts.simulate_rpath.clone(),
+        }),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        libc: opts.libc,
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        glibc_interp: opts.glibc_interp.clone(),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        map: opts.map.clone(),
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        on_node: opts.events.then(|| {
+            let printer = Rc::clone(&printer);
+            Box::new(move |node: &DepNode, parent: Option<&DepNode>| {
+                printer.print_json_line(&json::event(node, parent))
+            }) as options::NodeHook
+        }),
+        #[cfg(target_os = "macos")]
+        install_name: opts.install_name,
+        #[cfg(target_os = "macos")]
+        arch: opts.arch.clone(),
+    };
 
-    let ld_library_path = search_path::from_string(&opts.library_path, &[':']);
-    let ld_preload = search_path::from_preload(&opts.preload);
+    let mut ctx = create_context(&ropts);
 
-    let mut ctx = create_context();
+    #[cfg(target_os = "macos")]
+    if opts.dump_dyld_cache {
+        let images = ctx.images();
+        if images.is_empty() {
+            eprintln!("error: dyld shared cache not found");
+            std::process::exit(1);
+        }
+        for image in images {
+            printer.print_line(image);
+        }
+        return;
+    }
 
-    if opts.args.is_empty() {
+    let arg_failed = !arg_errors.is_empty();
+    for (arg, e) in arg_errors {
+        print_result_error(&printer, &opts, &arg, e.into());
+    }
+
+    // The archives without any binary are reported after the resolution.
+    let no_args = opts.args.is_empty();
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    let no_args = no_args && archives.is_empty();
+    if no_args {
+        // Only patterns that did not match anything or unreadable directories.
+        if arg_failed {
+            printer.flush();
+            std::process::exit(1);
+        }
         println!(
             "{progname}: missing file arguments\n\
             Try `{progname} --help' for more information.",
@@ -130,17 +1333,193 @@ fn main() {
         std::process::exit(0);
     };
 
-    for arg in opts.args {
-        match resolve_binary(
-            &mut ctx,
-            &ld_preload,
-            &ld_library_path,
-            &opts.platform,
-            opts.all,
-            arg.as_str(),
-        ) {
-            Ok(deptree) => print_deps(&printer, &deptree),
-            Err(e) => eprintln!("error: {}", print_error(&arg, e)),
+    if opts.diff {
+        print_diff(&printer, &mut ctx, &ropts, &opts.args);
+        return;
+    }
+
+    let mut summary = Summary {
+        missing: BTreeSet::new(),
+        sizes: BTreeMap::new(),
+        violation: false,
+        failed: arg_failed,
+        modes: Vec::new(),
+        manifest: BTreeSet::new(),
+    };
+
+    // Most of the files in a directory tree or an archive are not binaries.
+    let skip_non_binaries = opts.recursive;
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    let skip_non_binaries = skip_non_binaries || opts.archive;
+
+    for arg in &opts.args {
+        if opts.first_only && !summary.missing.is_empty() {
+            break;
+        }
+
+        // The binaries of each archive are resolved against its own root, and reported
+        // by their path inside it.
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        let member = archives.iter().find_map(|a| Some((a, a.member(arg)?)));
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        let name = match &member {
+            Some((archive, name)) => {
+                ropts.sysroot = Some(archive.sysroot(&opts));
+                name.clone()
+            }
+            None => arg.clone(),
+        };
+        #[cfg(target_os = "macos")]
+        let name = arg.clone();
+
+        #[cfg(target_os = "macos")]
+        if opts.all_arches {
+            match resolve_binary_arches(&mut ctx, &ropts, arg.as_str()) {
+                Ok(deptrees) => {
+                    for (arch, deptree) in deptrees {
+                        print_result(
+                            &printer,
+                            &opts,
+                            &ropts,
+                            &name,
+                            Some(&arch),
+                            deptree,
+                            &mut summary,
+                        );
+                    }
+                }
+                Err(RlddError::Parse(_)) if skip_non_binaries => {}
+                Err(e) => {
+                    summary.failed = true;
+                    print_result_error(&printer, &opts, &name, e)
+                }
+            }
+            continue;
+        }
+
+        let result = resolve_binary(&mut ctx, &ropts, arg.as_str());
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+        if let Some((archive, _)) = &member {
+            if !matches!(result, Err(RlddError::Parse(_))) {
+                archive.binaries.set(true);
+            }
+        }
+        match result {
+            Ok(deptree) => {
+                print_result(&printer, &opts, &ropts, &name, None, deptree, &mut summary)
+            }
+            Err(RlddError::Parse(_)) if skip_non_binaries => {}
+            Err(e) => {
+                summary.failed = true;
+                print_result_error(&printer, &opts, &name, e)
+            }
+        }
+    }
+
+    // Unless --first-only stopped before checking all of their files.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    if !opts.first_only || summary.missing.is_empty() {
+        for archive in archives.iter().filter(|a| !a.binaries.get()) {
+            summary.failed = true;
+            print_result_error(
+                &printer,
+                &opts,
+                &archive.name,
+                RlddError::Parse("no binaries in the archive".to_string()),
+            );
+        }
+    }
+
+    // The not found dependencies make the manifest incomplete.
+    if opts.manifest {
+        for path in &summary.manifest {
+            printer.print_line(&path.to_string_lossy());
+        }
+        for name in &summary.missing {
+            eprintln!("error: {name}: not found");
+        }
+        if !summary.missing.is_empty() {
+            printer.flush();
+            std::process::exit(1);
+        }
+    }
+
+    // The summaries would break the one object per line output.
+    if !opts.jsonl && !opts.count && !opts.manifest && !opts.closure_hash && !events(&opts) {
+        if ropts.verbose {
+            if let Some(description) = describe_context(&ctx) {
+                printer.print_note(&env!("CARGO_PKG_NAME").to_string(), &description);
+            }
+        }
+
+        if !summary.missing.is_empty() {
+            printer.print_missing(&summary.missing);
+        }
+
+        if opts.legend {
+            printer.print_legend(&summary.modes);
+        }
+
+        if opts.size {
+            printer.print_total_size(
+                &format_size(summary.sizes.values().sum()),
+                summary.sizes.len(),
+            );
+        }
+    }
+
+    let missing = !summary.missing.is_empty();
+    if summary.violation
+        || (opts.first_only && missing)
+        || (opts.check && (missing || summary.failed))
+    {
+        printer.flush();
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_dirs() {
+        let node = |path: &str, name: &str, mode: DepMode| DepNode {
+            path: Some(path.to_string()),
+            name: name.to_string(),
+            mode,
+            found: false,
+            trace: SearchTrace::new(),
+            warnings: Vec::new(),
+            info: Vec::new(),
+            file_id: None,
+        };
+        let mut deptree = DepTree::new();
+        let root = deptree.addroot(node("/opt/bin", "foo", DepMode::Executable));
+        deptree.addnode(node("/usr/lib", "libc.so.6", DepMode::LdCache), root);
+        deptree.addnode(node("/opt/lib", "libbar.so", DepMode::DtRunpath), root);
+        // A missing dependency only has the directory of its DT_NEEDED, if any.
+        deptree.addnode(node("", "libbaz.so", DepMode::NotFound), root);
+
+        let allowed = ["/usr/lib".to_string()];
+        let outside = outside_allowed_dirs(&deptree, &allowed);
+        assert_eq!(
+            outside,
+            vec![(&"libbar.so".to_string(), &"/opt/lib".to_string())]
+        );
+    }
+
+    #[test]
+    fn version_capabilities() {
+        let help = match Options::from_args(&["rldd"], &["--help"]) {
+            Err(argh::EarlyExit { output, .. }) => output,
+            Ok(_) => panic!("--help did not exit early"),
+        };
+        for capability in CAPABILITIES {
+            assert!(
+                help.contains(&format!("--{capability} ")),
+                "--{capability} is not an option"
+            );
         }
     }
 }
@@ -0,0 +1,274 @@
+// Resolution options shared by the ELF and Mach-O backends.
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+use crate::deptree::DepNode;
+use crate::error::RlddError;
+use crate::search_path;
+use std::cell::Cell;
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Called with each node added to the tree, along with its parent (none for the input),
+// as the resolution goes.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+pub type NodeHook = Box<dyn Fn(&DepNode, Option<&DepNode>)>;
+
+pub struct ResolveOptions {
+    // Search list paths from --library-path (LD_LIBRARY_PATH/DYLD_LIBRARY_PATH).
+    pub ld_library_path: search_path::SearchPathVec,
+    // Libraries from --preload (LD_PRELOAD/DYLD_INSERT_LIBRARIES).
+    pub ld_preload: search_path::SearchPathVec,
+    // Search list paths from --extra-dir, checked after all the loader ones.
+    pub extra_dirs: search_path::SearchPathVec,
+    // Value used for $PLATFORM expansion, if overridden (ELF only).
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    pub platform: Option<String>,
+    // Additional dependencies from --also, resolved as if the executable listed them
+    // (for instance dlopen'ed plugins).
+    pub also: Vec<String>,
+    // Ignore the host loader configuration (cache, preload file, and CPU hwcaps) so only
+    // the command line options affect the resolution.
+    pub isolated: bool,
+    // Add already resolved dependencies to the tree.
+    pub all: bool,
+    // Dependency name to keep the search trace for.
+    pub explain: Option<String>,
+    // Keep the search trace for all dependencies.
+    pub verbose: bool,
+    // Abort the resolution once the tree has more nodes than this.
+    pub max_nodes: Option<usize>,
+    // Abort the resolution of each input once it takes longer than this.
+    pub timeout: Option<Duration>,
+    // Stop the resolution at the first dependency not found.
+    pub first_only: bool,
+    // Report insecure DT_RPATH/DT_RUNPATH entries.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub audit_rpath: bool,
+    // Show the GNU property hardening features (CET, BTI/PAC) of each object.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub security: bool,
+    // Show the class, machine, and OS ABI of each object.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub show_arch: bool,
+    // Handle objects with the same device and inode as the same library.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub merge_hardlinks: bool,
+    // Show the dynamic section metadata (such as DT_RELR usage) of each object.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub metadata: bool,
+    // Use the input directory as-is for $ORIGIN, without resolving symlinks.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub no_canonicalize: bool,
+    // Check that the undefined symbols of each object are exported by a loaded one.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub check_symbols: bool,
+    // Report the DT_NEEDED entries each object does not bind any symbol to.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub unused: bool,
+    // With unused, also report the objects only loaded through unused entries.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub deep: bool,
+    // Loader cache file to use instead of the system one (ld.so.cache, ld.so.hints, ...).
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub cache: Option<String>,
+    // glibc-hwcaps subdirectories to select the loader cache entries with, in priority
+    // order, instead of the ones supported by the host CPU.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub hwcap: Option<Vec<String>>,
+    // Report other candidates in the search locations shadowed by the resolved one.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub warn_shadow: bool,
+    // Report a DT_SONAME resolved to different files in the tree.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub warn_conflict: bool,
+    // Report the symbols exported by more than one loaded object.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub warn_interpose: bool,
+    // Accept a dependency whose DT_SONAME has additional version components.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub relaxed_soname: bool,
+    // Check the input object itself for common mistakes.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub lint: bool,
+    // Show whether the input is a PIE executable, a non-PIE one, or a shared library.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub object_type: bool,
+    // Show the position of each dependency in the DT_NEEDED list of its parent.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub needed_index: bool,
+    // Omit the program interpreter (PT_INTERP) from the tree.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub no_interp: bool,
+    // Root file system to resolve against instead of the host one.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub sysroot: Option<Sysroot>,
+    // Resolve the inputs as synthetic executables instead of files.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub simulate: Option<Simulate>,
+    // The C library to assume instead of the one detected from the program interpreter.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub libc: Option<Libc>,
+    // Program interpreter names recognized as glibc in addition to the built-in ones.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub glibc_interp: Vec<String>,
+    // Files to use for the given DT_NEEDED names instead of searching for them.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub map: Vec<Mapping>,
+    // Report each node as soon as it is resolved, for --events.
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    pub on_node: Option<NodeHook>,
+    // Show the LC_ID_DYLIB install name of each object.
+    #[cfg(target_os = "macos")]
+    pub install_name: bool,
+    // Architecture slice of universal binaries to resolve instead of the host one.
+    #[cfg(target_os = "macos")]
+    pub arch: Option<String>,
+}
+
+// A root file system to resolve against, for instance one extracted from a container
+// image, from --sysroot and --usr-prefix:
+// - root: directory the absolute paths used by the loader are relative to.
+// - cache_path: loader cache inside the root, '/etc/ld.so.cache' if not set.
+// - usr_prefix: prefix of the secondary system directories (the glibc --prefix), '/usr'
+//   if not set.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+pub struct Sysroot {
+    pub root: String,
+    pub cache_path: Option<String>,
+    pub usr_prefix: Option<String>,
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+impl Sysroot {
+    // Return the host path for the absolute PATH inside the root.
+    pub fn rebase(&self, path: &str) -> String {
+        Path::new(&self.root)
+            .join(path.trim_start_matches('/'))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn cache_path(&self) -> String {
+        self.rebase(self.cache_path.as_deref().unwrap_or("/etc/ld.so.cache"))
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn usr_prefix(&self) -> &str {
+        self.usr_prefix.as_deref().unwrap_or("/usr")
+    }
+}
+
+// A synthetic executable from --simulate, whose DT_NEEDED entries are the comma separated
+// names of each input:
+// - like: object the class, machine, OS ABI, and program interpreter are taken from, the
+//   rldd executable itself if not set.
+// - rpath: DT_RPATH entries, with $ORIGIN being the current directory.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+pub struct Simulate {
+    pub like: Option<String>,
+    pub rpath: Option<String>,
+}
+
+// The C library from --libc, which selects the loader behavior (the glibc ld.so.cache and
+// ld.so.preload, or the musl loader providing libc itself).
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Libc {
+    Glibc,
+    Musl,
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+impl std::str::FromStr for Libc {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "glibc" => Ok(Libc::Glibc),
+            "musl" => Ok(Libc::Musl),
+            _ => Err(format!("invalid libc '{s}' (expected glibc or musl)")),
+        }
+    }
+}
+
+// A SONAME=PATH override from --map, which resolves the DT_NEEDED SONAME to the (host)
+// file PATH without any search.
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Mapping {
+    pub soname: String,
+    pub path: String,
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+impl std::str::FromStr for Mapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((soname, path)) if !soname.is_empty() && !path.is_empty() => Ok(Mapping {
+                soname: soname.to_string(),
+                path: path.to_string(),
+            }),
+            _ => Err(format!("invalid mapping '{s}' (expected SONAME=PATH)")),
+        }
+    }
+}
+
+// Resolution limits from --max-nodes and --timeout, checked before resolving each
+// dependency so a binary with a huge DT_NEEDED fan-out can not stall a batch scan.  The
+// --first-only option instead stops the resolution without an error, keeping the tree
+// resolved so far.
+pub struct Limits {
+    max_nodes: Option<usize>,
+    timeout: Option<Duration>,
+    first_only: bool,
+    start: Instant,
+    // Whether a dependency was not found, for --first-only.
+    missing: Cell<bool>,
+}
+
+impl Limits {
+    pub fn new(opts: &ResolveOptions) -> Limits {
+        Limits {
+            max_nodes: opts.max_nodes,
+            timeout: opts.timeout,
+            first_only: opts.first_only,
+            start: Instant::now(),
+            missing: Cell::new(false),
+        }
+    }
+
+    pub fn check(&self, nodes: usize) -> Result<(), RlddError> {
+        if let Some(max_nodes) = self.max_nodes {
+            if nodes > max_nodes {
+                return Err(RlddError::Limit(format!(
+                    "resolution aborted: tree has more than {max_nodes} nodes"
+                )));
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            if self.start.elapsed() > timeout {
+                return Err(RlddError::Limit(format!(
+                    "resolution aborted: timeout of {} seconds exceeded",
+                    timeout.as_secs()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Record that a dependency was not found.
+    pub fn not_found(&self) {
+        self.missing.set(true);
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.first_only && self.missing.get()
+    }
+}
@@ -1,7 +1,17 @@
 // Configurable printer module.
 
-use std::io::Write;
-use termcolor::{BufferWriter, ColorChoice, WriteColor};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
+use termcolor::{Ansi, BufferWriter, ColorChoice, NoColor, WriteColor};
+
+use crate::deptree::{DepMode, DepNode, SearchTrace};
+
+const DEFAULT_INDENT: usize = 3;
+const MIN_INDENT: usize = 2;
 
 // Ignore output error for now.
 macro_rules! ok {
@@ -13,41 +23,210 @@ macro_rules! ok {
     };
 }
 
+// Output writer that holds everything until it is flushed, so the whole tree of an input
+// is written at once instead of line by line and it is not torn by other output (such as
+// the warnings on stderr).
+struct Block<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Block<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Block<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.inner.write_all(&std::mem::take(&mut self.buffer))?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Block<W> {
+    fn drop(&mut self) {
+        ok!(self.flush());
+    }
+}
+
+// Color selection from --color: 'auto' only colorizes the output if it is a terminal.
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "invalid color mode '{s}' (expected auto, always, or never)"
+            )),
+        }
+    }
+}
+
+impl ColorMode {
+    fn enabled(&self, terminal: bool) -> bool {
+        match self {
+            ColorMode::Auto => terminal,
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+// Dependency line template from --format, where each field between braces is replaced
+// by the dependency value (for instance '{name} {mode} {path}').
+pub struct Format(Vec<FormatToken>);
+
+enum FormatToken {
+    Text(String),
+    Name,
+    Path,
+    Dir,
+    Mode,
+    Info,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(FormatToken::Text(rest[..start].to_string()));
+            }
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("unterminated field in format '{s}'")),
+            };
+            tokens.push(match &rest[start + 1..end] {
+                "name" => FormatToken::Name,
+                "path" => FormatToken::Path,
+                "dir" => FormatToken::Dir,
+                "mode" => FormatToken::Mode,
+                "info" => FormatToken::Info,
+                field => {
+                    return Err(format!(
+                        "invalid format field '{field}' (expected name, path, dir, mode, or info)"
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            tokens.push(FormatToken::Text(rest.to_string()));
+        }
+        Ok(Format(tokens))
+    }
+}
+
+impl Format {
+    fn render(&self, dep: &DepNode) -> String {
+        let dir = dep.path.as_deref().unwrap_or("");
+        self.0
+            .iter()
+            .map(|token| match token {
+                FormatToken::Text(text) => text.clone(),
+                FormatToken::Name => dep.name.clone(),
+                FormatToken::Path if dep.mode == DepMode::NotFound => String::new(),
+                FormatToken::Path => format!("{dir}{}{}", std::path::MAIN_SEPARATOR, dep.name),
+                FormatToken::Dir => dir.to_string(),
+                FormatToken::Mode => dep.mode.to_string(),
+                FormatToken::Info => dep.info.join(" "),
+            })
+            .collect()
+    }
+}
+
 pub struct Printer {
     pp: bool,
     ldd: bool,
+    // Pad the entries so the modes start at the same column.
+    align: bool,
+    column: Cell<usize>,
+    // Width of each tree level (--indent).
+    indent: usize,
+    // Label each object with its absolute path, in a single column (--absolute).
+    absolute: bool,
     one: bool,
+    format: Option<Format>,
+    out: RefCell<Box<dyn WriteColor>>,
+    // Warnings are always printed on stderr.
+    color_stderr: ColorChoice,
 }
 
 impl Printer {
-    pub fn new(pp: bool, ldd: bool, one: bool) -> Self {
-        Self { pp, ldd, one }
+    pub fn new(
+        pp: bool,
+        ldd: bool,
+        align: bool,
+        one: bool,
+        format: Option<Format>,
+        out: Box<dyn WriteColor>,
+        color: &ColorMode,
+    ) -> Self {
+        let color_stderr = if color.enabled(std::io::stderr().is_terminal()) {
+            ColorChoice::Always
+        } else {
+            ColorChoice::Never
+        };
+        Self {
+            pp,
+            ldd,
+            align,
+            column: Cell::new(0),
+            indent: DEFAULT_INDENT,
+            absolute: false,
+            one,
+            format,
+            out: RefCell::new(out),
+            color_stderr,
+        }
     }
 
-    fn write_colorized<S: Into<String>>(
-        &self,
-        buffer: &mut termcolor::Buffer,
-        color: &termcolor::ColorSpec,
-        content: S,
-    ) {
-        ok!(buffer.set_color(color));
-        ok!(buffer.write_all(content.into().as_bytes()));
-        ok!(buffer.reset());
+    fn write_colorized<S: Into<String>>(&self, color: &termcolor::ColorSpec, content: S) {
+        let mut out = self.out.borrow_mut();
+        ok!(out.set_color(color));
+        ok!(out.write_all(content.into().as_bytes()));
+        ok!(out.reset());
     }
 
-    fn writeln_colorized<S: Into<String>>(
-        &self,
-        buffer: &mut termcolor::Buffer,
-        color: &termcolor::ColorSpec,
-        content: S,
-    ) {
-        self.write_colorized(buffer, color, format!("{}\n", content.into()));
+    fn writeln_colorized<S: Into<String>>(&self, color: &termcolor::ColorSpec, content: S) {
+        self.write_colorized(color, format!("{}\n", content.into()));
     }
 
-    pub fn print_executable(&self, path: &Option<String>, name: &String) {
-        let writer = BufferWriter::stdout(ColorChoice::Always);
-        let mut buffer = writer.buffer();
+    fn write<S: Into<String>>(&self, content: S) {
+        ok!(self.out.borrow_mut().write_all(content.into().as_bytes()));
+    }
 
+    fn write_info(&self, info: &[String]) {
+        for i in info {
+            self.write(format!(" {i}"));
+        }
+        self.write("\n");
+    }
+
+    pub fn print_executable(&self, path: &Option<String>, name: &String, info: &[String]) {
         let mut color_path = termcolor::ColorSpec::new();
         let mut color_name = termcolor::ColorSpec::new();
         if self.ldd {
@@ -61,26 +240,90 @@ impl Printer {
                 .set_bold(true);
         }
 
-        if self.pp {
+        if self.absolute && !self.ldd {
+            self.write_colorized(&color_name, self.label(name, path.as_deref()));
+            self.write_info(info);
+            return;
+        }
+
+        // The ldd mode only shows a header with multiple inputs, and it always includes the
+        // full path so binaries with the same name in different directories can be told apart.
+        if self.pp || (self.ldd && !self.one) {
             if let Some(path) = path {
                 let delim = std::path::MAIN_SEPARATOR.to_string();
-                self.write_colorized(&mut buffer, &color_path, &format!("{path}{delim}"));
+                self.write_colorized(&color_path, &format!("{path}{delim}"));
             }
         }
 
         if self.ldd {
-            self.writeln_colorized(&mut buffer, &color_name, format!("{name}:"));
+            self.writeln_colorized(&color_name, format!("{name}:"));
         } else {
-            self.writeln_colorized(&mut buffer, &color_name, name);
+            self.write_colorized(&color_name, name);
+            self.write_info(info);
+        }
+    }
+
+    pub fn print_arch(&self, arch: &str) {
+        self.writeln_colorized(
+            termcolor::ColorSpec::new().set_bold(true),
+            format!("[{arch}]"),
+        );
+    }
+
+    pub fn align(&self) -> bool {
+        self.align && !self.ldd && self.format.is_none()
+    }
+
+    pub fn set_column(&self, column: usize) {
+        self.column.set(column);
+    }
+
+    // Set the width of each tree level, which is at least the '\\_' glyph width.
+    pub fn set_indent(&mut self, indent: usize) {
+        self.indent = indent.max(MIN_INDENT);
+    }
+
+    pub fn set_absolute(&mut self, absolute: bool) {
+        self.absolute = absolute;
+    }
+
+    // The absolute path of the object NAME in the directory PATH with --absolute, the
+    // NAME otherwise.
+    fn label(&self, name: &str, path: Option<&str>) -> String {
+        match path {
+            Some(path) if self.absolute => {
+                let path = Path::new(path).join(name);
+                std::path::absolute(&path)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .to_string()
+            }
+            _ => name.to_string(),
         }
+    }
 
-        ok!(writer.print(&buffer));
+    // Width of the DEP entry at DEPTH in the tree, up to the resolution mode.
+    pub fn entry_width(&self, dep: &DepNode, depth: usize) -> usize {
+        self.width(&dep.name, dep.path.as_deref().unwrap_or(""), depth)
     }
 
-    fn print_entry(&self, dtneeded: &String, path: &String, mode: &str, found: bool) {
-        let writer = BufferWriter::stdout(ColorChoice::Always);
-        let mut buffer = writer.buffer();
+    fn width(&self, dtneeded: &str, path: &str, depth: usize) -> usize {
+        let mut width = self.indent * depth + self.label(dtneeded, Some(path)).chars().count();
+        if self.pp && !self.absolute {
+            width += path.chars().count() + 1;
+        }
+        width
+    }
 
+    fn print_entry(
+        &self,
+        dtneeded: &str,
+        path: &String,
+        mode: &str,
+        info: &[String],
+        found: bool,
+        depth: usize,
+    ) {
         let mut color = termcolor::ColorSpec::new();
         if !found {
             color.set_fg(Some(termcolor::Color::Cyan));
@@ -88,48 +331,48 @@ impl Printer {
             color.set_fg(Some(termcolor::Color::Magenta));
         }
 
-        if self.pp {
+        if self.pp && !self.absolute {
             let delim = std::path::MAIN_SEPARATOR.to_string();
-            self.write_colorized(&mut buffer, &color, format!("{path}{delim}"));
+            self.write_colorized(&color, format!("{path}{delim}"));
         }
 
         if !found {
             color.set_bold(true);
         }
-        self.write_colorized(&mut buffer, &color, dtneeded);
+        self.write_colorized(&color, self.label(dtneeded, Some(path)));
 
         color.set_bold(false);
         if !found {
             color.set_fg(Some(termcolor::Color::Yellow));
         }
-        self.writeln_colorized(&mut buffer, &color, format!(" {mode}"));
+        if self.align() {
+            let width = self.width(dtneeded, path, depth);
+            self.write(" ".repeat(self.column.get().saturating_sub(width)));
+        }
+        self.write_colorized(&color, format!(" {mode}"));
+        self.write_info(info);
+    }
 
-        ok!(writer.print(&buffer));
+    // The glyph for each ancestor level, padded to the indentation width.
+    fn indent_glyph(&self, v: bool) -> String {
+        format!("{:1$}", if v { "|" } else { "" }, self.indent)
     }
 
     fn print_preamble(&self, deptrace: &Vec<bool>) {
         for v in &deptrace[0..deptrace.len() - 1] {
-            print!("{}", if *v { "|  " } else { "   " });
+            self.write(self.indent_glyph(*v));
         }
-        print!("\\_ ");
+        self.write(format!("{:1$}", "\\_", self.indent));
     }
 
     fn print_ldd(&self, dtneeded: &String, path: &String) {
-        let writer = BufferWriter::stdout(ColorChoice::Always);
-        let mut buffer = writer.buffer();
-
-        ok!(buffer.write_all(
-            format!(
-                "        {} => {}{}{}\n",
-                dtneeded,
-                path,
-                std::path::MAIN_SEPARATOR,
-                dtneeded
-            )
-            .as_bytes()
+        self.write(format!(
+            "        {} => {}{}{}\n",
+            dtneeded,
+            path,
+            std::path::MAIN_SEPARATOR,
+            dtneeded
         ));
-
-        ok!(writer.print(&buffer));
     }
 
     pub fn print_dependency(
@@ -137,6 +380,7 @@ impl Printer {
         dtneeded: &String,
         path: &String,
         mode: &str,
+        info: &[String],
         deptrace: &Vec<bool>,
     ) {
         if self.ldd {
@@ -144,35 +388,526 @@ impl Printer {
             return;
         }
         self.print_preamble(deptrace);
-        self.print_entry(dtneeded, path, mode, false)
+        self.print_entry(dtneeded, path, mode, info, false, deptrace.len())
+    }
+
+    pub fn has_format(&self) -> bool {
+        self.format.is_some()
+    }
+
+    // Print DEP using the --format template, without colors.
+    pub fn print_formatted(&self, dep: &DepNode, deptrace: &Vec<bool>) {
+        if self.ldd {
+            self.write("        ");
+        } else {
+            self.print_preamble(deptrace);
+        }
+        if let Some(format) = &self.format {
+            self.write(format!("{}\n", format.render(dep)));
+        }
     }
 
     pub fn print_already_found(
         &self,
-        dtneeded: &String,
+        dtneeded: &str,
         path: &String,
         mode: &str,
+        info: &[String],
         deptrace: &Vec<bool>,
     ) {
         self.print_preamble(deptrace);
-        self.print_entry(dtneeded, path, mode, true)
+        self.print_entry(dtneeded, path, mode, info, true, deptrace.len())
     }
 
-    pub fn print_not_found(&self, dtneeded: &String, deptrace: &Vec<bool>) {
+    // The TRACE is only kept in verbose mode (or for the --explain dependency), in which
+    // case the directories searched are printed under the dependency.
+    pub fn print_not_found(&self, dtneeded: &String, trace: &SearchTrace, deptrace: &Vec<bool>) {
         self.print_preamble(deptrace);
-        let writer = BufferWriter::stdout(ColorChoice::Always);
-        let mut buffer = writer.buffer();
         self.writeln_colorized(
-            &mut buffer,
             termcolor::ColorSpec::new()
                 .set_fg(Some(termcolor::Color::Red))
                 .set_bold(true),
             format!("{dtneeded} not found"),
         );
+        for attempt in trace {
+            for v in deptrace {
+                self.write(self.indent_glyph(*v));
+            }
+            // A file that exists but can not be used is shown with the reason, since
+            // otherwise it looks like the directory does not have it.
+            if let Some(reason) = &attempt.rejected {
+                self.writeln_colorized(
+                    termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)),
+                    format!("rejected {} {}: {reason}", attempt.path, attempt.mode),
+                );
+                continue;
+            }
+            let dir = Path::new(&attempt.path)
+                .parent()
+                .map_or(attempt.path.clone(), |p| p.display().to_string());
+            self.writeln_colorized(
+                termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Yellow)),
+                format!("searched {dir} {}", attempt.mode),
+            );
+        }
+    }
+
+    // Print the not found dependencies, one per line.
+    pub fn print_missing(&self, missing: &BTreeSet<String>) {
+        // ldd does not print a summary, and the scripts parsing its output do not expect
+        // one.
+        if self.ldd {
+            return;
+        }
+        self.writeln_colorized(
+            termcolor::ColorSpec::new().set_bold(true),
+            "Missing dependencies:",
+        );
+        for name in missing {
+            self.writeln_colorized(
+                termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)),
+                name,
+            );
+        }
+    }
+
+    // Print each directory followed by the libraries resolved from it, and the ones not
+    // found last.
+    pub fn print_by_dir(
+        &self,
+        dirs: &BTreeMap<String, BTreeSet<String>>,
+        missing: &BTreeSet<String>,
+    ) {
+        for (dir, names) in dirs {
+            self.writeln_colorized(
+                termcolor::ColorSpec::new()
+                    .set_fg(Some(termcolor::Color::Cyan))
+                    .set_bold(true),
+                format!("{dir} ({}):", names.len()),
+            );
+            for name in names {
+                self.write(format!("  {name}\n"));
+            }
+        }
+        if !missing.is_empty() {
+            self.writeln_colorized(
+                termcolor::ColorSpec::new()
+                    .set_fg(Some(termcolor::Color::Red))
+                    .set_bold(true),
+                format!("not found ({}):", missing.len()),
+            );
+            for name in missing {
+                self.write(format!("  {name}\n"));
+            }
+        }
+    }
+
+    pub fn print_line(&self, line: &str) {
+        self.write(format!("{line}\n"));
+    }
+
+    // Print a JSON object line, flushing so each input is available as soon as it is
+    // resolved.
+    pub fn print_json_line(&self, line: &str) {
+        self.write(format!("{line}\n"));
+        self.flush();
+    }
+
+    pub fn print_legend(&self, modes: &[DepMode]) {
+        self.writeln_colorized(termcolor::ColorSpec::new().set_bold(true), "Legend:");
+        for mode in modes {
+            self.write_colorized(
+                termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Yellow)),
+                format!("{mode}"),
+            );
+            self.write(format!(": {}\n", mode.description()));
+        }
+    }
+
+    pub fn print_stats(&self, unique: usize, edges: usize, depth: usize) {
+        self.writeln_colorized(
+            termcolor::ColorSpec::new().set_bold(true),
+            format!("Statistics: unique: {unique}, edges: {edges}, max-depth: {depth}"),
+        );
+    }
+
+    pub fn print_total_size(&self, size: &str, files: usize) {
+        self.writeln_colorized(
+            termcolor::ColorSpec::new().set_bold(true),
+            format!("Total size: {size} ({files} files)"),
+        );
+    }
+
+    // Print the differences between two sets of resolved libraries: added ('+'), removed
+    // ('-'), and resolved from a different directory ('~').
+    pub fn print_diff(&self, old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) {
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for name in names {
+            match (old.get(name), new.get(name)) {
+                (None, Some(_)) => self.writeln_colorized(
+                    termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Green)),
+                    format!("+{name}"),
+                ),
+                (Some(_), None) => self.writeln_colorized(
+                    termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)),
+                    format!("-{name}"),
+                ),
+                (Some(oldpath), Some(newpath)) if oldpath != newpath => self.writeln_colorized(
+                    termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Yellow)),
+                    format!("~{name} ({oldpath} -> {newpath})"),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn print_explain(&self, dtneeded: &String, trace: &SearchTrace) {
+        self.writeln_colorized(
+            termcolor::ColorSpec::new().set_bold(true),
+            format!("{dtneeded} resolution order:"),
+        );
+
+        for (i, attempt) in trace.iter().enumerate() {
+            let mut color = termcolor::ColorSpec::new();
+            let result = if attempt.found {
+                color.set_fg(Some(termcolor::Color::Cyan));
+                "found".to_string()
+            } else if let Some(reason) = &attempt.rejected {
+                color.set_fg(Some(termcolor::Color::Red));
+                format!("rejected: {reason}")
+            } else {
+                color.set_fg(Some(termcolor::Color::Yellow));
+                "not found".to_string()
+            };
+            self.writeln_colorized(
+                &color,
+                format!("  {}: {} {} {result}", i + 1, attempt.path, attempt.mode),
+            );
+        }
+    }
+
+    pub fn print_explain_missing(&self, dtneeded: &String) {
+        self.writeln_colorized(
+            termcolor::ColorSpec::new()
+                .set_fg(Some(termcolor::Color::Red))
+                .set_bold(true),
+            format!("{dtneeded} is not a dependency"),
+        );
+    }
+
+    fn print_diagnostic(&self, color: &termcolor::ColorSpec, content: String) {
+        let writer = BufferWriter::stderr(self.color_stderr);
+        let mut buffer = writer.buffer();
+        ok!(buffer.set_color(color));
+        ok!(buffer.write_all(content.as_bytes()));
+        ok!(buffer.reset());
+        ok!(buffer.write_all(b"\n"));
         ok!(writer.print(&buffer));
     }
+
+    pub fn print_warning(&self, name: &String, warning: &String) {
+        self.print_diagnostic(
+            termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Yellow)),
+            format!("warning: {name}: {warning}"),
+        );
+    }
+
+    pub fn print_note(&self, name: &String, note: &String) {
+        self.print_diagnostic(
+            termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Blue)),
+            format!("note: {name}: {note}"),
+        );
+    }
+
+    pub fn print_violation(&self, name: &String, path: &String) {
+        self.print_diagnostic(
+            termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)),
+            format!("violation: {name}: {path} is outside the allowed directories"),
+        );
+    }
+
+    // Write the pending output, which is also required before std::process::exit since it
+    // does not run the destructors.
+    pub fn flush(&self) {
+        ok!(self.out.borrow_mut().flush());
+    }
+}
+
+// Create a printer that writes to OUTPUT, or to stdout if not set.  The file is truncated
+// if it already exists.
+pub fn create(
+    pp: bool,
+    ldd: bool,
+    align: bool,
+    one: bool,
+    format: Option<Format>,
+    output: &Option<String>,
+    color: &ColorMode,
+) -> Result<Printer, std::io::Error> {
+    let out: Box<dyn WriteColor> = match output {
+        Some(output) => {
+            let file = Block::new(File::create(output)?);
+            // A file is never a terminal, so 'auto' disables color.
+            if color.enabled(false) {
+                Box::new(Ansi::new(file))
+            } else {
+                Box::new(NoColor::new(file))
+            }
+        }
+        None => {
+            let stdout = std::io::stdout();
+            if color.enabled(stdout.is_terminal()) {
+                Box::new(Ansi::new(Block::new(stdout)))
+            } else {
+                Box::new(NoColor::new(Block::new(stdout)))
+            }
+        }
+    };
+    Ok(Printer::new(pp, ldd, align, one, format, out, color))
 }
 
-pub fn create(pp: bool, ldd: bool, one: bool) -> Printer {
-    Printer::new(pp, ldd, one)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    // Writer that keeps the output available after the printer takes ownership of it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn take(&self) -> String {
+            String::from_utf8(self.0.borrow_mut().split_off(0)).unwrap()
+        }
+    }
+
+    fn printer(pp: bool, ldd: bool, buffer: &SharedBuffer) -> Printer {
+        let out = Box::new(NoColor::new(buffer.clone()));
+        Printer::new(pp, ldd, true, false, None, out, &ColorMode::Never)
+    }
+
+    #[test]
+    fn long_names() {
+        // Larger than PATH_MAX and any terminal width, neither should be truncated.
+        let dtneeded = format!("lib{}.so.1", "x".repeat(4096));
+        let path = format!("/{}", vec!["d".repeat(255); 16].join("/"));
+        let deptrace = vec![true, false];
+        let buffer = SharedBuffer::default();
+
+        let p = printer(false, false, &buffer);
+        p.set_column(10);
+        p.print_dependency(&dtneeded, &path, "[rpath]", &[], &deptrace);
+        assert_eq!(buffer.take(), format!("|  \\_ {dtneeded} [rpath]\n"));
+
+        let p = printer(true, false, &buffer);
+        p.print_already_found(&dtneeded, &path, "[rpath]", &[], &deptrace);
+        assert_eq!(buffer.take(), format!("|  \\_ {path}/{dtneeded} [rpath]\n"));
+
+        let p = printer(false, true, &buffer);
+        p.print_dependency(&dtneeded, &path, "[rpath]", &[], &deptrace);
+        assert_eq!(
+            buffer.take(),
+            format!("        {dtneeded} => {path}/{dtneeded}\n")
+        );
+    }
+
+    #[test]
+    fn indent() {
+        let deptrace = vec![true, false, true];
+        let trace = vec![crate::deptree::SearchAttempt {
+            path: "/lib/libfoo.so.1".to_string(),
+            mode: DepMode::SystemDirs,
+            found: false,
+            rejected: None,
+        }];
+        let buffer = SharedBuffer::default();
+
+        let mut p = printer(false, false, &buffer);
+        p.print_dependency(
+            &"libfoo.so.1".to_string(),
+            &"/lib".to_string(),
+            "[rpath]",
+            &[],
+            &deptrace,
+        );
+        assert_eq!(buffer.take(), "|     \\_ libfoo.so.1 [rpath]\n");
+
+        p.set_indent(5);
+        p.print_dependency(
+            &"libfoo.so.1".to_string(),
+            &"/lib".to_string(),
+            "[rpath]",
+            &[],
+            &deptrace,
+        );
+        assert_eq!(buffer.take(), "|         \\_   libfoo.so.1 [rpath]\n");
+
+        // The width is at least the '\\_' glyph.
+        p.set_indent(0);
+        p.print_not_found(&"libfoo.so.1".to_string(), &trace, &deptrace);
+        assert_eq!(
+            buffer.take(),
+            "|   \\_libfoo.so.1 not found\n|   | searched /lib [system default paths]\n"
+        );
+    }
+
+    #[test]
+    fn absolute() {
+        let deptrace = vec![false];
+        let buffer = SharedBuffer::default();
+
+        let mut p = printer(true, false, &buffer);
+        p.set_absolute(true);
+        p.print_executable(&Some("/usr/bin".to_string()), &"foo".to_string(), &[]);
+        p.set_column(p.width("libfoo.so.1", "/lib", 1));
+        p.print_dependency(
+            &"libfoo.so.1".to_string(),
+            &"/lib".to_string(),
+            "[rpath]",
+            &[],
+            &deptrace,
+        );
+        assert_eq!(
+            buffer.take(),
+            "/usr/bin/foo\n\\_ /lib/libfoo.so.1 [rpath]\n"
+        );
+
+        // A relative directory (such as from a relative DT_RUNPATH) is made absolute.
+        p.print_already_found(
+            "libfoo.so.1",
+            &"lib".to_string(),
+            "[runpath]",
+            &[],
+            &deptrace,
+        );
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            buffer.take(),
+            format!("\\_ {}/lib/libfoo.so.1 [runpath]\n", cwd.display())
+        );
+    }
+
+    #[test]
+    fn not_found_rejected() {
+        let trace = vec![
+            crate::deptree::SearchAttempt {
+                path: "/opt/lib/libfoo.so.1".to_string(),
+                mode: DepMode::DtRunpath,
+                found: false,
+                rejected: Some("not a valid object".to_string()),
+            },
+            crate::deptree::SearchAttempt {
+                path: "/lib/libfoo.so.1".to_string(),
+                mode: DepMode::SystemDirs,
+                found: false,
+                rejected: None,
+            },
+        ];
+        let buffer = SharedBuffer::default();
+
+        let p = printer(false, false, &buffer);
+        p.print_not_found(&"libfoo.so.1".to_string(), &trace, &vec![false]);
+        assert_eq!(
+            buffer.take(),
+            "\\_ libfoo.so.1 not found\n   rejected /opt/lib/libfoo.so.1 [runpath]: not a valid object\n   searched /lib [system default paths]\n"
+        );
+    }
+
+    #[test]
+    fn ldd_missing() {
+        let missing = BTreeSet::from(["libnope.so.1".to_string()]);
+        let buffer = SharedBuffer::default();
+
+        let p = printer(false, true, &buffer);
+        p.print_dependency(
+            &"libc.so.6".to_string(),
+            &"/lib".to_string(),
+            "[ld.so.cache]",
+            &[],
+            &vec![false],
+        );
+        p.print_missing(&missing);
+        assert_eq!(buffer.take(), "        libc.so.6 => /lib/libc.so.6\n");
+
+        let p = printer(false, false, &buffer);
+        p.print_missing(&missing);
+        assert_eq!(buffer.take(), "Missing dependencies:\nlibnope.so.1\n");
+    }
+
+    #[test]
+    fn ldd_header() {
+        let path = Some("/usr/bin".to_string());
+        let buffer = SharedBuffer::default();
+
+        // A single input has no header, as ldd itself.
+        let out = Box::new(NoColor::new(buffer.clone()));
+        let p = Printer::new(false, true, true, true, None, out, &ColorMode::Never);
+        p.print_executable(&path, &"foo".to_string(), &[]);
+        assert_eq!(buffer.take(), "");
+
+        let p = printer(false, true, &buffer);
+        p.print_executable(&path, &"foo".to_string(), &[]);
+        p.print_executable(&Some("/opt/bin".to_string()), &"foo".to_string(), &[]);
+        assert_eq!(buffer.take(), "/usr/bin/foo:\n/opt/bin/foo:\n");
+    }
+
+    #[test]
+    fn block_output() {
+        let buffer = SharedBuffer::default();
+        let out = Box::new(NoColor::new(Block::new(buffer.clone())));
+        let p = Printer::new(false, false, false, false, None, out, &ColorMode::Never);
+
+        p.print_executable(&None, &"foo".to_string(), &[]);
+        p.print_dependency(
+            &"libfoo.so.1".to_string(),
+            &"/lib".to_string(),
+            "[rpath]",
+            &[],
+            &vec![false],
+        );
+        assert_eq!(buffer.take(), "");
+        p.flush();
+        assert_eq!(buffer.take(), "foo\n\\_ libfoo.so.1 [rpath]\n");
+
+        // The pending output is also written once the printer is dropped.
+        p.print_line("bar");
+        drop(p);
+        assert_eq!(buffer.take(), "bar\n");
+    }
+
+    #[test]
+    fn by_dir() {
+        let dirs = BTreeMap::from([
+            (
+                "/lib64".to_string(),
+                BTreeSet::from(["libc.so.6".to_string(), "libm.so.6".to_string()]),
+            ),
+            (
+                "/opt/lib".to_string(),
+                BTreeSet::from(["libfoo.so".to_string()]),
+            ),
+        ]);
+        let missing = BTreeSet::from(["libbar.so".to_string()]);
+        let buffer = SharedBuffer::default();
+
+        let p = printer(false, false, &buffer);
+        p.print_by_dir(&dirs, &missing);
+        assert_eq!(
+            buffer.take(),
+            "/lib64 (2):\n  libc.so.6\n  libm.so.6\n/opt/lib (1):\n  libfoo.so\nnot found (1):\n  libbar.so\n"
+        );
+
+        p.print_by_dir(&dirs, &BTreeSet::new());
+        assert!(!buffer.take().contains("not found"));
+    }
 }
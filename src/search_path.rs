@@ -1,6 +1,8 @@
 // Provides helper function to handle search path for library resolution, for either DT_RPATH,
 // DT_RUNPATH, ld.so.conf, or system directories.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::{fmt, fs};
@@ -10,6 +12,7 @@ pub struct SearchPath {
     pub path: String,
     pub dev: u64,
     pub ino: u64,
+    pub mode: u32,
 }
 impl fmt::Display for SearchPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -22,13 +25,28 @@ impl PartialEq<&str> for SearchPath {
     }
 }
 
+// The same directories (the system ones, the loader cache ones, and the common DT_RUNPATH
+// entries) are checked for every object, so the result of each stat is kept for the
+// whole program run, including the not existent ones.
+thread_local! {
+    static STAT_CACHE: RefCell<HashMap<String, Option<SearchPath>>> = RefCell::new(HashMap::new());
+}
+
 fn get_search_path(entry: &str) -> Option<SearchPath> {
-    let path = Path::new(entry);
-    let meta = fs::metadata(path).ok()?;
-    Some(SearchPath {
-        path: entry.to_string(),
-        dev: meta.dev(),
-        ino: meta.ino(),
+    STAT_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(entry.to_string())
+            .or_insert_with(|| {
+                let meta = fs::metadata(Path::new(entry)).ok()?;
+                Some(SearchPath {
+                    path: entry.to_string(),
+                    dev: meta.dev(),
+                    ino: meta.ino(),
+                    mode: meta.mode(),
+                })
+            })
+            .clone()
     })
 }
 
@@ -58,6 +76,14 @@ pub fn from_string<S: AsRef<str>>(string: S, delim: &[char]) -> SearchPathVec {
     r
 }
 
+pub fn from_vec<S: AsRef<str>>(entries: &[S]) -> SearchPathVec {
+    let mut r = SearchPathVec::new();
+    for path in entries {
+        r.add_path(path.as_ref());
+    }
+    r
+}
+
 pub fn from_preload<S: AsRef<str>>(string: S) -> SearchPathVec {
     let mut r = SearchPathVec::new();
     for path in string.as_ref().split(':') {